@@ -0,0 +1,47 @@
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_midix::prelude::*;
+use bevy_seedling::SeedlingPlugin;
+use std::time::Duration;
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            SeedlingPlugin::default(),
+            MidiPlugin::default(),
+        ))
+        .add_systems(Startup, connect_to_first_output)
+        .add_systems(
+            Update,
+            play_note
+                .run_if(on_timer(Duration::from_millis(500)))
+                .before(ProcessSynthCommands),
+        )
+        .run();
+}
+
+/// Swap [`MidiOutputSink`] for a [`SynthPlayer`] to hear the same notes with a soundfont.
+fn connect_to_first_output(mut commands: Commands, mut output: ResMut<MidiOutput>) {
+    let Some(first) = output.refresh_ports().first().cloned() else {
+        warn!("No output ports found!");
+        return;
+    };
+    info!("Connecting to {}", first.id());
+    if let Err(e) = output.connect_to_port(&first) {
+        error!("{e:?}");
+        return;
+    }
+    commands.spawn(MidiOutputSink::new(first.id()));
+}
+
+fn play_note(mut synth: Single<&mut SynthCommands>, mut on: Local<bool>) {
+    const VEL: Velocity = Velocity::new_unchecked(90);
+    let note = Note::new(Key::C, Octave::new(4));
+
+    *on = !*on;
+    let event = if *on {
+        VoiceEvent::note_on(note, VEL)
+    } else {
+        VoiceEvent::note_off(note, VEL)
+    };
+    synth.send(ChannelVoiceMessage::new(Channel::One, event));
+}
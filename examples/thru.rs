@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use bevy_midix::prelude::*;
+use bevy_seedling::SeedlingPlugin;
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            SeedlingPlugin::default(),
+            MidiPlugin::default(),
+        ))
+        .add_systems(Startup, patch)
+        .add_systems(Update, connect_to_first_input)
+        .run();
+}
+
+/// Sends channel one of every input to the first output port on channel ten,
+/// and notes from every channel to a soundfont synth.
+fn patch(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    input: Res<MidiInput>,
+    mut output: ResMut<MidiOutput>,
+) {
+    if let Some(first) = output.refresh_ports().first().cloned() {
+        info!("Forwarding to {}", first.id());
+        _ = output
+            .connect_to_port(&first)
+            .inspect_err(|e| error!("{e:?}"));
+
+        input.router().add_route(
+            MidiRoute::to_output(output.handle(first.id()))
+                .with_filter(RouteFilter::default().only_channels([Channel::One]))
+                .with_remap(ChannelRemap::all_to(Channel::Ten)),
+        );
+    }
+
+    let synth = commands
+        .spawn(SynthPlayer::new(assets.load("soundfont.sf2"), false))
+        .id();
+    input
+        .router()
+        .add_route(MidiRoute::to_synth(synth).with_filter(RouteFilter::default().only_notes()));
+}
+
+fn connect_to_first_input(mut input: ResMut<MidiInput>) {
+    let Some(ports) = input.refresh_ports() else {
+        return;
+    };
+    if let Some(first) = ports.first().cloned() {
+        info!("Connecting to {}", first.id());
+        _ = input
+            .connect_to_port(&first)
+            .inspect_err(|e| error!("{e:?}"));
+    }
+}
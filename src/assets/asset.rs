@@ -16,6 +16,9 @@ pub trait MidiFileExt {
     ///
     /// This consumes the MIDI file and extracts all the timing and event information
     /// needed for playback.
+    ///
+    /// Meta events, including tempo changes, are not kept. To keep the file's
+    /// tempo map, read its bytes with [`MidiSong::from_smf`] instead.
     fn into_song(self) -> MidiSong;
 }
 
@@ -32,7 +35,11 @@ impl<'a> MidiFileExt for MidiFile<'a> {
     }
 }
 
-/// Loader for sound fonts
+/// Loader for MIDI files.
+///
+/// To load a file straight into a [`MidiSong`], use the [`MidiSongLoader`](crate::assets::MidiSongLoader).
+/// Both read `.mid` files, and the type of asset asked for picks the loader, so load these
+/// with `load::<MidiFile>`.
 #[derive(Default)]
 pub struct MidiFileLoader;
 
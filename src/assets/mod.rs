@@ -12,6 +12,17 @@ pub use sound_font::*;
 mod song;
 pub use song::*;
 
+mod smf;
+pub use smf::*;
+
+mod song_loader;
+pub use song_loader::*;
+
+#[cfg(feature = "musicxml")]
+pub mod musicxml;
+#[cfg(feature = "musicxml")]
+pub use musicxml::{MusicXmlError, MusicXmlLoader};
+
 /// Plugin for loading and managing MIDI-related assets.
 ///
 /// This plugin enables loading MIDI files and soundfont files as Bevy assets.
@@ -26,6 +37,17 @@ impl Plugin for MidiAssetsPlugin {
             .init_asset::<MidiFile<'static>>()
             .register_type::<MidiFile<'static>>();
 
+        // registered after `MidiFileLoader`, so untyped loads of `.mid` files are songs
+        app.init_asset_loader::<MidiSongLoader>()
+            .init_asset::<MidiSong>()
+            .register_type::<MidiSong>();
+
+        #[cfg(feature = "serde")]
+        app.init_asset_loader::<SongDocumentLoader>();
+
+        #[cfg(feature = "musicxml")]
+        app.init_asset_loader::<MusicXmlLoader>();
+
         #[cfg(feature = "synth")]
         app.init_asset_loader::<SoundFontLoader>()
             .init_asset::<SoundFontAsset>();
@@ -0,0 +1,898 @@
+#![doc = r#"
+Importing MusicXML scores as [`MidiSong`]s.
+
+Each part of the score is played on its own channel, using the channel and program of
+the part's `<midi-instrument>` when it has one. Parts without a channel take the channels
+no part asks for, except channel 10 which is kept for percussion, so a score can have at
+most 15 such parts. Tempo marks, dynamics, ties, repeats and first and second endings are
+followed. Grace notes and cue notes are skipped.
+"#]
+
+use std::io::{Cursor, Read};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::HashMap,
+};
+use midix::prelude::*;
+use roxmltree::{Document, Node, ParsingOptions};
+use thiserror::Error;
+
+use crate::{
+    assets::{MidiSong, TempoChange, TempoMap, TimeSignatureChange},
+    util::{self, CHANNELS},
+};
+
+/// The velocity of notes before the score sets any dynamics
+const DEFAULT_VELOCITY: u8 = 80;
+/// MusicXML dynamics are a percentage of this velocity, which is forte
+const FORTE_VELOCITY: f64 = 90.;
+
+/// Possible errors when reading a MusicXML score
+#[derive(Debug, Error)]
+pub enum MusicXmlError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// The score is not valid XML
+    #[error("Invalid XML: {0}")]
+    Xml(#[from] roxmltree::Error),
+    /// A compressed `.mxl` score could not be read
+    #[error("Invalid MXL archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    /// The score is not a `<score-partwise>` document. Timewise scores are not supported.
+    #[error("Expected a <score-partwise> document, found <{0}>")]
+    NotPartwise(String),
+    /// A compressed `.mxl` score has no score in it
+    #[error("No score found in MXL archive")]
+    MissingScore,
+    /// A time signature has more beats in a bar than MIDI can hold
+    #[error("Invalid time signature: {0} beats")]
+    InvalidTimeSignature(String),
+    /// A part doesn't ask for a channel, and every channel it could take is used
+    #[error("No free MIDI channel for part {0}")]
+    NoFreeChannel(String),
+}
+
+/// The MIDI hints of a `<score-part>`
+#[derive(Default)]
+struct PartInfo {
+    channel: Option<Channel>,
+    program: Option<u8>,
+    /// Keys of unpitched (percussion) instruments, by instrument id
+    unpitched: HashMap<String, u8>,
+}
+
+/// A note in a measure, in quarter notes from the start of the measure
+struct MeasureNote {
+    start: f64,
+    length: f64,
+    key: u8,
+    velocity: u8,
+    tie_start: bool,
+    tie_stop: bool,
+}
+
+/// One measure of one part
+#[derive(Default)]
+struct Measure {
+    notes: Vec<MeasureNote>,
+    length: f64,
+    /// Tempo marks, in quarter notes from the start of the measure and beats per minute
+    tempos: Vec<(f64, f64)>,
+    time_signature: Option<(u8, u8)>,
+    forward_repeat: bool,
+    /// The number of times the section is played, if this measure ends a repeat
+    backward_repeat: Option<u32>,
+    /// The passes of a repeat this measure starts an ending for
+    ending_start: Vec<u32>,
+    ending_stop: bool,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn child_number<T: core::str::FromStr>(node: Node<'_, '_>, name: &str) -> Option<T> {
+    child_text(node, name)?.parse().ok()
+}
+
+/// The velocity of a dynamics mark such as `<mf/>`
+fn dynamics_velocity(mark: &str) -> Option<u8> {
+    Some(match mark {
+        "pppp" => 10,
+        "ppp" => 23,
+        "pp" => 36,
+        "p" => 49,
+        "mp" => 62,
+        "mf" => 75,
+        "f" => 88,
+        "ff" => 101,
+        "fff" => 114,
+        "ffff" => 127,
+        "sf" | "sfz" | "fz" => 100,
+        _ => return None,
+    })
+}
+
+/// The velocity of a `dynamics` attribute, which is a percentage of forte
+fn percent_velocity(percent: &str) -> Option<u8> {
+    let percent = percent.trim().parse::<f64>().ok()?;
+    Some((percent * FORTE_VELOCITY / 100.).round().clamp(1., 127.) as u8)
+}
+
+fn part_infos(root: Node<'_, '_>) -> HashMap<String, PartInfo> {
+    let mut infos = HashMap::new();
+    let Some(part_list) = child(root, "part-list") else {
+        return infos;
+    };
+    for score_part in part_list
+        .children()
+        .filter(|node| node.has_tag_name("score-part"))
+    {
+        let Some(id) = score_part.attribute("id") else {
+            continue;
+        };
+        let mut info = PartInfo::default();
+        for instrument in score_part
+            .children()
+            .filter(|node| node.has_tag_name("midi-instrument"))
+        {
+            if info.channel.is_none() {
+                info.channel = child_number::<usize>(instrument, "midi-channel")
+                    .and_then(|channel| CHANNELS.get(channel.checked_sub(1)?).copied());
+            }
+            if info.program.is_none() {
+                info.program = child_number::<u8>(instrument, "midi-program")
+                    .and_then(|program| program.checked_sub(1));
+            }
+            let unpitched =
+                child_number::<u8>(instrument, "midi-unpitched").and_then(|key| key.checked_sub(1));
+            if let (Some(id), Some(key)) = (instrument.attribute("id"), unpitched) {
+                info.unpitched.insert(id.to_owned(), key);
+            }
+        }
+        infos.insert(id.to_owned(), info);
+    }
+    infos
+}
+
+/// The key of a `<note>`, or `None` for rests
+fn note_key(note: Node<'_, '_>, info: &PartInfo) -> Option<u8> {
+    if let Some(pitch) = child(note, "pitch") {
+        let step = match child_text(pitch, "step")? {
+            "C" => 0,
+            "D" => 2,
+            "E" => 4,
+            "F" => 5,
+            "G" => 7,
+            "A" => 9,
+            "B" => 11,
+            _ => return None,
+        };
+        let alter = child_text(pitch, "alter")
+            .and_then(|alter| alter.parse::<f64>().ok())
+            .unwrap_or_default()
+            .round() as i16;
+        let octave = child_number::<i16>(pitch, "octave")?;
+        let key = octave
+            .checked_add(1)?
+            .checked_mul(12)?
+            .checked_add(step)?
+            .checked_add(alter)?;
+        return u8::try_from(key).ok().filter(|key| *key < 128);
+    }
+    if let Some(unpitched) = child(note, "unpitched") {
+        let instrument = child(note, "instrument")
+            .and_then(|instrument| instrument.attribute("id"))
+            .and_then(|id| info.unpitched.get(id))
+            .or_else(|| info.unpitched.values().next());
+        if let Some(key) = instrument {
+            return Some(*key);
+        }
+        let step = child_text(unpitched, "display-step")?;
+        let octave = child_text(unpitched, "display-octave")?;
+        return util::parse_note_name(&format!("{step}{octave}"));
+    }
+    None
+}
+
+/// The number of beats of a time signature, such as `3+2`.
+///
+/// Beats that aren't numbers are ignored, but too many beats for MIDI are an error.
+fn signature_beats(text: &str) -> Result<Option<u8>, MusicXmlError> {
+    let Ok(parts) = text
+        .split('+')
+        .map(|part| part.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return Ok(None);
+    };
+    parts
+        .into_iter()
+        .try_fold(0u32, u32::checked_add)
+        .and_then(|beats| u8::try_from(beats).ok())
+        .map(Some)
+        .ok_or_else(|| MusicXmlError::InvalidTimeSignature(text.to_owned()))
+}
+
+/// Reads the measures of a `<part>`
+fn parse_part(part: Node<'_, '_>, info: &PartInfo) -> Result<Vec<Measure>, MusicXmlError> {
+    let mut measures = Vec::new();
+    let mut divisions = 1.;
+    let mut velocity = DEFAULT_VELOCITY;
+
+    for measure_node in part.children().filter(|node| node.has_tag_name("measure")) {
+        let mut measure = Measure::default();
+        let mut cursor: f64 = 0.;
+        let mut last_start = 0.;
+
+        for node in measure_node.children().filter(|node| node.is_element()) {
+            match node.tag_name().name() {
+                "attributes" => {
+                    if let Some(value) = child_number::<f64>(node, "divisions") {
+                        divisions = value.max(1.);
+                    }
+                    if let Some(time) = child(node, "time") {
+                        let beats = match child_text(time, "beats") {
+                            Some(beats) => signature_beats(beats)?,
+                            None => None,
+                        };
+                        let beat_type = child_number::<u8>(time, "beat-type");
+                        if let (Some(beats), Some(beat_type)) = (beats, beat_type) {
+                            measure.time_signature = Some((beats, beat_type));
+                        }
+                    }
+                }
+                "direction" | "sound" => {
+                    let sound = if node.has_tag_name("sound") {
+                        Some(node)
+                    } else {
+                        child(node, "sound")
+                    };
+                    if let Some(sound) = sound {
+                        if let Some(tempo) = sound
+                            .attribute("tempo")
+                            .and_then(|tempo| tempo.trim().parse::<f64>().ok())
+                            .filter(|tempo| *tempo > 0.)
+                        {
+                            measure.tempos.push((cursor, tempo));
+                        }
+                        if let Some(value) = sound.attribute("dynamics").and_then(percent_velocity)
+                        {
+                            velocity = value;
+                        }
+                    }
+                    let marks = node
+                        .descendants()
+                        .filter(|node| node.has_tag_name("dynamics"))
+                        .flat_map(|dynamics| dynamics.children())
+                        .filter_map(|mark| dynamics_velocity(mark.tag_name().name()))
+                        .next_back();
+                    if let Some(value) = marks {
+                        velocity = value;
+                    }
+                }
+                "backup" => {
+                    let duration = child_number::<f64>(node, "duration").unwrap_or_default();
+                    cursor = (cursor - duration / divisions).max(0.);
+                }
+                "forward" => {
+                    let duration = child_number::<f64>(node, "duration").unwrap_or_default();
+                    cursor += duration / divisions;
+                }
+                "barline" => {
+                    if let Some(repeat) = child(node, "repeat") {
+                        match repeat.attribute("direction") {
+                            Some("forward") => measure.forward_repeat = true,
+                            Some("backward") => {
+                                let times = repeat
+                                    .attribute("times")
+                                    .and_then(|times| times.parse().ok())
+                                    .unwrap_or(2);
+                                measure.backward_repeat = Some(times);
+                            }
+                            _ => {}
+                        }
+                    }
+                    if let Some(ending) = child(node, "ending") {
+                        match ending.attribute("type") {
+                            Some("start") => {
+                                measure.ending_start = ending
+                                    .attribute("number")
+                                    .unwrap_or_default()
+                                    .split([',', ' '])
+                                    .filter_map(|number| number.trim().parse().ok())
+                                    .collect();
+                            }
+                            Some("stop" | "discontinue") => measure.ending_stop = true,
+                            _ => {}
+                        }
+                    }
+                }
+                "note" => {
+                    if child(node, "grace").is_some() || child(node, "cue").is_some() {
+                        continue;
+                    }
+                    let length =
+                        child_number::<f64>(node, "duration").unwrap_or_default() / divisions;
+                    let start = if child(node, "chord").is_some() {
+                        last_start
+                    } else {
+                        last_start = cursor;
+                        cursor += length;
+                        last_start
+                    };
+                    let Some(key) = note_key(node, info) else {
+                        continue;
+                    };
+                    let ties = node
+                        .children()
+                        .filter(|child| child.has_tag_name("tie"))
+                        .filter_map(|tie| tie.attribute("type"))
+                        .collect::<Vec<_>>();
+                    let velocity = node
+                        .attribute("dynamics")
+                        .and_then(percent_velocity)
+                        .unwrap_or(velocity);
+                    measure.notes.push(MeasureNote {
+                        start,
+                        length,
+                        key,
+                        velocity,
+                        tie_start: ties.contains(&"start"),
+                        tie_stop: ties.contains(&"stop"),
+                    });
+                }
+                _ => {}
+            }
+            measure.length = measure.length.max(cursor);
+        }
+        measures.push(measure);
+    }
+    Ok(measures)
+}
+
+/// The order measures are played in, following repeats and endings
+fn playback_order(measures: &[Measure]) -> Vec<usize> {
+    // the endings each measure belongs to
+    let mut endings = Vec::with_capacity(measures.len());
+    let mut current: Vec<u32> = Vec::new();
+    for measure in measures {
+        if !measure.ending_start.is_empty() {
+            current = measure.ending_start.clone();
+        }
+        endings.push(current.clone());
+        if measure.ending_stop {
+            current.clear();
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut passes = HashMap::<usize, u32>::new();
+    let mut repeat_start = 0;
+    let mut pass = 1;
+    let mut index = 0;
+    // a backward repeat can only jump back so many times, but guard against bad scores
+    while index < measures.len() && order.len() < measures.len() * 64 {
+        let measure = &measures[index];
+        if measure.forward_repeat {
+            repeat_start = index;
+        }
+        if !endings[index].is_empty() && !endings[index].contains(&pass) {
+            index += 1;
+            continue;
+        }
+        order.push(index);
+        if let Some(times) = measure.backward_repeat {
+            let done = passes.entry(index).or_insert(1);
+            if *done < times {
+                *done += 1;
+                pass = *done;
+                index = repeat_start;
+                continue;
+            }
+            pass = 1;
+            repeat_start = index + 1;
+        }
+        index += 1;
+    }
+    order
+}
+
+/// A note of a part, laid out in quarter notes from the start of the song
+struct PlacedNote {
+    start: f64,
+    end: f64,
+    key: u8,
+    velocity: u8,
+}
+
+impl MidiSong {
+    /// Reads an uncompressed MusicXML score (`.musicxml`) into a song.
+    ///
+    /// See the [module documentation](crate::assets::musicxml) for what is imported.
+    ///
+    /// # Errors
+    /// If the text is not a partwise MusicXML score
+    pub fn from_musicxml(text: &str) -> Result<Self, MusicXmlError> {
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+        let document = Document::parse_with_options(text, options)?;
+        let root = document.root_element();
+        if !root.has_tag_name("score-partwise") {
+            return Err(MusicXmlError::NotPartwise(
+                root.tag_name().name().to_owned(),
+            ));
+        }
+
+        let infos = part_infos(root);
+        let no_info = PartInfo::default();
+        let parts = root
+            .children()
+            .filter(|node| node.has_tag_name("part"))
+            .map(|part| {
+                let info = part
+                    .attribute("id")
+                    .and_then(|id| infos.get(id))
+                    .unwrap_or(&no_info);
+                let id = part.attribute("id").unwrap_or_default();
+                Ok((id, info, parse_part(part, info)?))
+            })
+            .collect::<Result<Vec<_>, MusicXmlError>>()?;
+        let Some((_, _, first)) = parts.first() else {
+            return Ok(MidiSong::default());
+        };
+
+        let measure_count = parts
+            .iter()
+            .map(|(_, _, measures)| measures.len())
+            .max()
+            .unwrap_or(0);
+        let lengths = (0..measure_count)
+            .map(|index| {
+                parts
+                    .iter()
+                    .filter_map(|(_, _, measures)| measures.get(index))
+                    .map(|measure| measure.length)
+                    .fold(0., f64::max)
+            })
+            .collect::<Vec<_>>();
+        let order = playback_order(first);
+        let mut starts = Vec::with_capacity(order.len());
+        let mut position = 0.;
+        for index in &order {
+            starts.push(position);
+            position += lengths[*index];
+        }
+
+        // tempo marks and time signatures, from any part
+        let mut tempos = Vec::new();
+        let mut time_signatures = Vec::new();
+        for (start, index) in starts.iter().zip(&order) {
+            for (_, _, measures) in &parts {
+                let Some(measure) = measures.get(*index) else {
+                    continue;
+                };
+                tempos.extend(measure.tempos.iter().map(|(at, bpm)| (start + at, *bpm)));
+                if let Some(signature) = measure.time_signature {
+                    time_signatures.push((*start, signature));
+                }
+            }
+        }
+        tempos.sort_by(|a, b| a.0.total_cmp(&b.0));
+        tempos.dedup_by(|a, b| (a.0 - b.0).abs() < 1e-9);
+        time_signatures.dedup_by(|a, b| (a.0 - b.0).abs() < 1e-9);
+
+        let mut tempo_map = TempoMap::default();
+        for (at, bpm) in &tempos {
+            tempo_map.add_tempo_at_quarters(*at, TempoChange::from_bpm(0, *bpm).micros_per_quarter);
+        }
+        for (at, (numerator, denominator)) in &time_signatures {
+            tempo_map.add_time_signature(TimeSignatureChange {
+                timestamp: tempo_map.micros_at_quarters(*at),
+                numerator: *numerator,
+                denominator: *denominator,
+            });
+        }
+
+        let mut events = Vec::new();
+        let mut tracks = Vec::new();
+        // parts without a channel take the channels no part asked for, keeping ten for drums
+        let mut free_channels = CHANNELS.into_iter().filter(|channel| {
+            *channel != Channel::Ten
+                && parts
+                    .iter()
+                    .all(|(_, info, _)| info.channel != Some(*channel))
+        });
+        for (track, (id, info, measures)) in parts.iter().enumerate() {
+            let channel = match info.channel {
+                Some(channel) => channel,
+                None if !info.unpitched.is_empty() => Channel::Ten,
+                None => free_channels
+                    .next()
+                    .ok_or_else(|| MusicXmlError::NoFreeChannel((*id).to_owned()))?,
+            };
+            let mut push = |quarters: f64, message: Option<ChannelVoiceMessage>| {
+                if let Some(message) = message {
+                    events.push(Timed::new(tempo_map.micros_at_quarters(quarters), message));
+                    tracks.push(track as u16);
+                }
+            };
+            if let Some(program) = info.program {
+                push(
+                    0.,
+                    util::voice_message(util::STATUS_PROGRAM_CHANGE, channel, program, 0),
+                );
+            }
+
+            let mut notes: Vec<PlacedNote> = Vec::new();
+            // notes waiting for the rest of their tie, by key
+            let mut tied = HashMap::<u8, usize>::new();
+            for (start, index) in starts.iter().zip(&order) {
+                let Some(measure) = measures.get(*index) else {
+                    continue;
+                };
+                for note in &measure.notes {
+                    let note_start = start + note.start;
+                    let note_end = note_start + note.length;
+                    let continued = note.tie_stop.then(|| tied.remove(&note.key)).flatten();
+                    let slot = match continued {
+                        Some(slot) => {
+                            notes[slot].end = notes[slot].end.max(note_end);
+                            slot
+                        }
+                        None => {
+                            notes.push(PlacedNote {
+                                start: note_start,
+                                end: note_end,
+                                key: note.key,
+                                velocity: note.velocity,
+                            });
+                            notes.len() - 1
+                        }
+                    };
+                    if note.tie_start {
+                        tied.insert(note.key, slot);
+                    }
+                }
+            }
+
+            for note in notes {
+                push(
+                    note.start,
+                    util::voice_message(util::STATUS_NOTE_ON, channel, note.key, note.velocity),
+                );
+                push(
+                    note.end,
+                    util::voice_message(util::STATUS_NOTE_OFF, channel, note.key, 0),
+                );
+            }
+        }
+
+        let mut song = MidiSong::with_tracks(events, tracks);
+        song.tempo_map = tempo_map;
+        Ok(song)
+    }
+
+    /// Reads a compressed MusicXML score (`.mxl`) into a song.
+    /// See [`MidiSong::from_musicxml`].
+    ///
+    /// # Errors
+    /// If the bytes are not a zip archive with a partwise MusicXML score in it
+    pub fn from_mxl(bytes: &[u8]) -> Result<Self, MusicXmlError> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+
+        let mut read = |name: &str| -> Result<String, MusicXmlError> {
+            let mut text = String::new();
+            archive.by_name(name)?.read_to_string(&mut text)?;
+            Ok(text)
+        };
+        // the container names the score, otherwise it is the first xml file outside META-INF
+        let root_file = read("META-INF/container.xml").ok().and_then(|container| {
+            let document = Document::parse(&container).ok()?;
+            document
+                .descendants()
+                .find(|node| node.has_tag_name("rootfile"))?
+                .attribute("full-path")
+                .map(str::to_owned)
+        });
+        let root_file = match root_file {
+            Some(root_file) => root_file,
+            None => archive
+                .file_names()
+                .find(|name| {
+                    !name.starts_with("META-INF")
+                        && (name.ends_with(".xml") || name.ends_with(".musicxml"))
+                })
+                .map(str::to_owned)
+                .ok_or(MusicXmlError::MissingScore)?,
+        };
+        let mut text = String::new();
+        archive.by_name(&root_file)?.read_to_string(&mut text)?;
+        Self::from_musicxml(&text)
+    }
+}
+
+/// Loader for MusicXML scores, both uncompressed (`.musicxml`) and compressed (`.mxl`).
+///
+/// Load a score with `asset_server.load::<MidiSong>("score.musicxml")`.
+#[derive(Default)]
+pub struct MusicXmlLoader;
+
+impl AssetLoader for MusicXmlLoader {
+    type Asset = MidiSong;
+    type Settings = ();
+    type Error = MusicXmlError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        // compressed scores are zip archives
+        if bytes.starts_with(b"PK\x03\x04") {
+            return MidiSong::from_mxl(&bytes);
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        MidiSong::from_musicxml(&text)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["musicxml", "mxl"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::{Measure, MusicXmlError, playback_order, signature_beats};
+    use crate::assets::MidiSong;
+
+    // at the default 120 beats per minute, a quarter note is half a second
+    const QUARTER: u64 = 500_000;
+
+    /// A score of parts, each with its `<score-part>` hints and its measures
+    fn score(parts: &[(&str, &str)]) -> Result<MidiSong, MusicXmlError> {
+        let mut list = String::new();
+        let mut bodies = String::new();
+        for (index, (hints, measures)) in parts.iter().enumerate() {
+            list += &format!(r#"<score-part id="P{index}"><part-name/>{hints}</score-part>"#);
+            bodies += &format!(r#"<part id="P{index}">{measures}</part>"#);
+        }
+        MidiSong::from_musicxml(&format!(
+            "<score-partwise><part-list>{list}</part-list>{bodies}</score-partwise>"
+        ))
+    }
+
+    /// A pitched note, a quarter note per duration
+    fn note(step: &str, octave: i16, duration: u32, extra: &str) -> String {
+        format!(
+            "<note>{extra}<pitch><step>{step}</step><octave>{octave}</octave></pitch>\
+             <duration>{duration}</duration></note>"
+        )
+    }
+
+    fn tied(step: &str, octave: i16, duration: u32, tie: &str) -> String {
+        format!(
+            "<note><pitch><step>{step}</step><octave>{octave}</octave></pitch>\
+             <duration>{duration}</duration><tie type=\"{tie}\"/></note>"
+        )
+    }
+
+    fn measure(content: &[String]) -> String {
+        format!("<measure>{}</measure>", content.concat())
+    }
+
+    /// The start and end of each note in quarter notes, and its key
+    fn spans(song: &MidiSong) -> Vec<(u64, u64, u8)> {
+        song.notes()
+            .iter()
+            .map(|note| (note.start / QUARTER, note.end() / QUARTER, note.key))
+            .collect()
+    }
+
+    fn repeat(measure: Measure) -> Measure {
+        Measure {
+            length: 4.,
+            ..measure
+        }
+    }
+
+    #[test]
+    fn repeats_play_their_endings_in_turn() {
+        let measures = [
+            repeat(Measure {
+                forward_repeat: true,
+                ..Default::default()
+            }),
+            repeat(Measure::default()),
+            repeat(Measure {
+                ending_start: vec![1],
+                ending_stop: true,
+                backward_repeat: Some(2),
+                ..Default::default()
+            }),
+            repeat(Measure {
+                ending_start: vec![2],
+                ending_stop: true,
+                ..Default::default()
+            }),
+            repeat(Measure::default()),
+        ];
+        assert_eq!(playback_order(&measures), vec![0, 1, 2, 0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn repeats_without_a_start_go_back_to_the_beginning() {
+        let measures = [
+            repeat(Measure::default()),
+            repeat(Measure {
+                backward_repeat: Some(3),
+                ..Default::default()
+            }),
+            repeat(Measure::default()),
+        ];
+        assert_eq!(playback_order(&measures), vec![0, 1, 0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn repeats_are_followed_in_the_song() {
+        let song = score(&[(
+            "",
+            &[
+                measure(&[note("C", 4, 4, "")]),
+                format!(
+                    r#"<measure>{}<barline><repeat direction="backward"/></barline></measure>"#,
+                    note("D", 4, 4, "")
+                ),
+            ]
+            .concat(),
+        )])
+        .unwrap();
+        assert_eq!(
+            spans(&song),
+            vec![(0, 4, 60), (4, 8, 62), (8, 12, 60), (12, 16, 62)]
+        );
+    }
+
+    #[test]
+    fn ties_join_notes_across_bars() {
+        let song = score(&[(
+            "",
+            &[
+                measure(&[tied("C", 4, 4, "start")]),
+                measure(&[tied("C", 4, 2, "stop"), note("C", 4, 2, "")]),
+            ]
+            .concat(),
+        )])
+        .unwrap();
+        assert_eq!(spans(&song), vec![(0, 6, 60), (6, 8, 60)]);
+    }
+
+    #[test]
+    fn chords_and_backup_start_notes_together() {
+        let song = score(&[(
+            "",
+            &measure(&[
+                note("C", 4, 2, ""),
+                note("E", 4, 2, "<chord/>"),
+                note("C", 4, 2, ""),
+                "<backup><duration>4</duration></backup>".to_owned(),
+                note("G", 3, 4, ""),
+            ]),
+        )])
+        .unwrap();
+        assert_eq!(
+            spans(&song),
+            vec![(0, 4, 55), (0, 2, 60), (0, 2, 64), (2, 4, 60)]
+        );
+    }
+
+    #[test]
+    fn dynamics_set_the_velocity() {
+        let song = score(&[(
+            "",
+            &measure(&[
+                note("C", 4, 1, ""),
+                "<direction><direction-type><dynamics><p/><f/></dynamics>\
+                 </direction-type></direction>"
+                    .to_owned(),
+                note("D", 4, 1, ""),
+                r#"<sound dynamics="100"/>"#.to_owned(),
+                note("E", 4, 1, ""),
+                note("F", 4, 1, "").replace("<note>", r#"<note dynamics="50">"#),
+            ]),
+        )])
+        .unwrap();
+        let velocities = song
+            .notes()
+            .iter()
+            .map(|note| note.velocity)
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, vec![80, 88, 90, 45]);
+    }
+
+    #[test]
+    fn notes_out_of_range_are_skipped() {
+        let song = score(&[(
+            "",
+            &measure(&[
+                note("C", 3000, 1, ""),
+                note("C", 10, 1, ""),
+                note("C", 4, 1, ""),
+            ]),
+        )])
+        .unwrap();
+        assert_eq!(spans(&song), vec![(2, 3, 60)]);
+    }
+
+    #[test]
+    fn parts_take_their_own_or_free_channels() {
+        let drums =
+            r#"<midi-instrument id="P2-I1"><midi-unpitched>37</midi-unpitched></midi-instrument>"#;
+        let drum = "<note><unpitched><display-step>C</display-step>\
+                    <display-octave>2</display-octave></unpitched><duration>1</duration>\
+                    <instrument id=\"P2-I1\"/></note>";
+        let song = score(&[
+            (
+                "<midi-instrument id=\"P0-I1\"><midi-channel>2</midi-channel></midi-instrument>",
+                &measure(&[note("C", 4, 1, "")]),
+            ),
+            ("", &measure(&[note("D", 4, 1, "")])),
+            (drums, &format!("<measure>{drum}</measure>")),
+        ])
+        .unwrap();
+        let channels = song
+            .notes()
+            .iter()
+            .map(|note| (note.channel, note.key))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            channels,
+            vec![(Channel::One, 62), (Channel::Two, 60), (Channel::Ten, 36)]
+        );
+    }
+
+    #[test]
+    fn too_many_parts_without_channels_is_an_error() {
+        let part = measure(&[note("C", 4, 1, "")]);
+        let parts = vec![("", part.as_str()); 16];
+        assert!(matches!(
+            score(&parts),
+            Err(MusicXmlError::NoFreeChannel(id)) if id == "P15"
+        ));
+        assert!(score(&parts[..15]).is_ok());
+    }
+
+    #[test]
+    fn signature_beats_adds_compound_beats() {
+        assert_eq!(signature_beats("3").unwrap(), Some(3));
+        assert_eq!(signature_beats("3+2+2").unwrap(), Some(7));
+        assert_eq!(signature_beats("x").unwrap(), None);
+    }
+
+    #[test]
+    fn signature_beats_rejects_too_many_beats() {
+        assert!(matches!(
+            signature_beats("200+200"),
+            Err(MusicXmlError::InvalidTimeSignature(_))
+        ));
+        assert!(matches!(
+            signature_beats("4294967295+1"),
+            Err(MusicXmlError::InvalidTimeSignature(_))
+        ));
+    }
+}
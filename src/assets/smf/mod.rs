@@ -0,0 +1,158 @@
+#![doc = r#"
+Reading Standard MIDI Files into [`MidiSong`]s, and writing them back out.
+
+Files are read chunk by chunk with midix's [`Reader`](midix::reader::Reader).
+
+Unlike [`MidiFileExt::into_song`](crate::assets::MidiFileExt::into_song), this keeps the
+file's tempo map, time signatures and text meta events, and supports SMPTE time division.
+"#]
+
+use midix::prelude::*;
+use thiserror::Error;
+
+mod reader;
+pub(crate) use reader::*;
+
+mod writer;
+pub(crate) use writer::write_song;
+pub use writer::{SmfExportSettings, SmfFormat};
+
+use crate::assets::{
+    MidiSong, MidiSongLoaderSettings, SongMetaEvent, TempoChange, TempoMap, TimeSignatureChange,
+};
+
+/// Possible errors when reading a Standard MIDI File
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SmfError {
+    /// The bytes could not be read as a MIDI file
+    #[error("Could not read MIDI file: {0}")]
+    Read(String),
+}
+
+/// Converts the ticks of a file into microseconds, following tempo changes
+struct TickClock {
+    timing: SmfTiming,
+    last_tick: u64,
+    last_micros: u64,
+    micros_per_quarter: u32,
+}
+
+impl TickClock {
+    fn new(timing: SmfTiming) -> Self {
+        Self {
+            timing,
+            last_tick: 0,
+            last_micros: 0,
+            micros_per_quarter: crate::assets::DEFAULT_MICROS_PER_QUARTER,
+        }
+    }
+
+    /// Ticks must never decrease between calls
+    fn micros(&self, tick: u64) -> u64 {
+        let ticks = (tick - self.last_tick) as u128;
+        let elapsed = match self.timing {
+            SmfTiming::TicksPerQuarterNote(ppqn) => {
+                ticks * self.micros_per_quarter as u128 / ppqn as u128
+            }
+            SmfTiming::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => {
+                let fps = if frames_per_second == 29 {
+                    29.97
+                } else {
+                    frames_per_second as f64
+                };
+                (ticks as f64 * 1_000_000. / (fps * ticks_per_frame as f64)) as u128
+            }
+        };
+        self.last_micros + elapsed as u64
+    }
+
+    fn set_tempo(&mut self, tick: u64, micros_per_quarter: u32) {
+        self.last_micros = self.micros(tick);
+        self.last_tick = tick;
+        self.micros_per_quarter = micros_per_quarter;
+    }
+}
+
+impl Smf {
+    /// Converts the file into a song, applying the full tempo map
+    pub(crate) fn into_song(self, settings: &MidiSongLoaderSettings) -> MidiSong {
+        let mut clock = TickClock::new(self.timing);
+        let mut tempo_map = TempoMap::default();
+        if let Some(bpm) = settings.tempo_override {
+            let change = TempoChange::from_bpm(0, bpm);
+            clock.set_tempo(0, change.micros_per_quarter);
+            tempo_map.add_tempo(change);
+        }
+        let mut events = Vec::new();
+        let mut tracks = Vec::new();
+        let mut meta = Vec::new();
+
+        for (track, tick, event) in self.merged() {
+            let timestamp = clock.micros(tick);
+            match &event.kind {
+                SmfEventKind::Voice(message) => {
+                    if let Some(message) = settings.import(track, *message) {
+                        events.push(Timed::new(timestamp, message));
+                        tracks.push(track as u16);
+                    }
+                }
+                SmfEventKind::Tempo(_) if settings.tempo_override.is_some() => {}
+                SmfEventKind::Tempo(micros_per_quarter) => {
+                    clock.set_tempo(tick, *micros_per_quarter);
+                    tempo_map.add_tempo(TempoChange {
+                        timestamp,
+                        micros_per_quarter: *micros_per_quarter,
+                    });
+                }
+                SmfEventKind::TimeSignature {
+                    numerator,
+                    denominator,
+                } => tempo_map.add_time_signature(TimeSignatureChange {
+                    timestamp,
+                    numerator: *numerator,
+                    denominator: *denominator,
+                }),
+                SmfEventKind::Meta(event) => {
+                    if settings.keep_meta && settings.keeps_track(track) {
+                        meta.push(SongMetaEvent::new(timestamp, event.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut song = MidiSong::with_tracks(events, tracks);
+        song.tempo_map = tempo_map;
+        song.meta = meta;
+        song
+    }
+}
+
+impl MidiSong {
+    /// Reads a Standard MIDI File (format 0, 1 or 2) into a song.
+    ///
+    /// All tracks are merged, remembering the [track of each event](MidiSong::event_track).
+    /// The tracks of format 2 files are independent sequences, so they are played one after
+    /// another. Every timestamp is converted to microseconds using the file's tempo map, which is kept in [`MidiSong::tempo_map`]. Text, lyric, marker and
+    /// cue point events are kept in [`MidiSong::meta_events`].
+    ///
+    /// # Errors
+    /// If the bytes are not a valid MIDI file
+    pub fn from_smf(bytes: &[u8]) -> Result<Self, SmfError> {
+        Self::from_smf_with(bytes, &MidiSongLoaderSettings::default())
+    }
+
+    /// Reads a Standard MIDI File into a song, keeping only the tracks and channels
+    /// of the settings. See [`MidiSong::from_smf`].
+    ///
+    /// # Errors
+    /// If the bytes are not a valid MIDI file
+    pub fn from_smf_with(
+        bytes: &[u8],
+        settings: &MidiSongLoaderSettings,
+    ) -> Result<Self, SmfError> {
+        Ok(Smf::parse(bytes)?.into_song(settings))
+    }
+}
@@ -0,0 +1,297 @@
+use midix::{
+    file::{BytesText, FormatType, Timing, TrackEvent, TrackMessage, builder::event::ChunkEvent},
+    prelude::*,
+};
+
+use crate::assets::{SmfError, SongMeta};
+
+/// The time division of a Standard MIDI File
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SmfTiming {
+    /// Ticks are a fraction of a quarter note, and depend on the tempo
+    TicksPerQuarterNote(u16),
+    /// Ticks are a fraction of a SMPTE frame, and don't depend on the tempo
+    Smpte {
+        /// 24, 25, 29 (drop frame 29.97) or 30
+        frames_per_second: u8,
+        /// The number of ticks in each frame
+        ticks_per_frame: u8,
+    },
+}
+
+/// The events of a Standard MIDI File that are kept on a song, with each track's events in order.
+pub(crate) struct Smf {
+    pub(crate) format: FormatType,
+    pub(crate) timing: SmfTiming,
+    pub(crate) tracks: Vec<SmfTrack>,
+}
+
+pub(crate) struct SmfTrack {
+    pub(crate) events: Vec<SmfEvent>,
+    /// The tick of the last event of the track, including its end of track event
+    pub(crate) length: u64,
+}
+
+pub(crate) struct SmfEvent {
+    /// Absolute ticks from the start of the track
+    pub(crate) tick: u64,
+    pub(crate) kind: SmfEventKind,
+}
+
+pub(crate) enum SmfEventKind {
+    Voice(ChannelVoiceMessage),
+    Tempo(u32),
+    TimeSignature { numerator: u8, denominator: u8 },
+    Meta(SongMeta),
+}
+
+impl Smf {
+    /// Reads the header and track chunks of a file.
+    ///
+    /// Chunks are read with midix's [`Reader`] rather than [`MidiFile::parse`](midix::file::MidiFile::parse),
+    /// which only keeps the last tempo and text event of each track.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self, SmfError> {
+        let read_error = |error: ReaderError| SmfError::Read(error.to_string());
+        let mut reader = Reader::from_byte_slice(bytes);
+        let mut header = None;
+        let mut tracks = Vec::new();
+        loop {
+            match reader.read_chunk().map_err(read_error)? {
+                ChunkEvent::Header(chunk) => header = Some((chunk.format_type(), chunk.timing())),
+                ChunkEvent::Track(chunk) => {
+                    let events = chunk
+                        .events()
+                        .map_err(|kind| SmfError::Read(format!("{kind:?}")))?;
+                    tracks.push(track_events(&events));
+                }
+                ChunkEvent::Unknown(_) => {}
+                ChunkEvent::Eof => break,
+            }
+        }
+        let Some((format, timing)) = header else {
+            return Err(SmfError::Read("Missing header chunk".to_string()));
+        };
+
+        let timing = match timing {
+            Timing::TicksPerQuarterNote(ppqn) => {
+                SmfTiming::TicksPerQuarterNote(ppqn.ticks_per_quarter_note().max(1))
+            }
+            Timing::Smpte(smpte) => SmfTiming::Smpte {
+                frames_per_second: match smpte.fps() {
+                    SmpteFps::TwentyFour => 24,
+                    SmpteFps::TwentyFive => 25,
+                    SmpteFps::TwentyNine => 29,
+                    SmpteFps::Thirty => 30,
+                },
+                ticks_per_frame: smpte.ticks_per_frame().max(1),
+            },
+        };
+
+        Ok(Self {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// Every event of every track, with its tick from the start of the song.
+    ///
+    /// Tracks of format 0 and 1 files play at the same time, so their events are ordered
+    /// by tick, and ties keep their track order. Tracks of format 2 files are independent
+    /// sequences, so they are played one after another.
+    pub(crate) fn merged(&self) -> Vec<(usize, u64, &SmfEvent)> {
+        let sequential = self.format == FormatType::SequentiallyIndependent;
+        let mut offset = 0;
+        let mut events = Vec::new();
+        for (index, track) in self.tracks.iter().enumerate() {
+            events.extend(
+                track
+                    .events
+                    .iter()
+                    .map(|event| (index, offset + event.tick, event)),
+            );
+            if sequential {
+                offset += track.length;
+            }
+        }
+        events.sort_by_key(|(_, tick, _)| *tick);
+        events
+    }
+}
+
+/// The events of a track that are kept on a song, with their ticks from the start of the track
+fn track_events(events: &[TrackEvent<'_>]) -> SmfTrack {
+    let mut length = 0;
+    let events = events
+        .iter()
+        .filter_map(|event| {
+            length += event.delta_ticks() as u64;
+            let kind = match event.event() {
+                TrackMessage::ChannelVoice(message) => SmfEventKind::Voice(*message),
+                TrackMessage::Meta(meta) => meta_kind(meta)?,
+                TrackMessage::SystemExclusive(_) => return None,
+            };
+            Some(SmfEvent { tick: length, kind })
+        })
+        .collect();
+    SmfTrack { events, length }
+}
+
+/// The meta events that are kept on a song
+fn meta_kind(meta: &MetaMessage<'_>) -> Option<SmfEventKind> {
+    // midix only exposes text that is valid UTF-8, so any other text event is skipped
+    let text = |text: &BytesText<'_>| text.as_str().ok().map(str::to_owned);
+    Some(match meta {
+        MetaMessage::Tempo(tempo) => SmfEventKind::Tempo(tempo.micros_per_quarter_note()),
+        MetaMessage::TimeSignature(signature) => SmfEventKind::TimeSignature {
+            numerator: signature.num(),
+            // the denominator is stored as a power of two
+            denominator: 1u8.checked_shl(signature.den() as u32).unwrap_or(4),
+        },
+        // empty text events only pad long gaps between events
+        MetaMessage::Text(data) => {
+            SmfEventKind::Meta(SongMeta::Text(text(data).filter(|text| !text.is_empty())?))
+        }
+        MetaMessage::Lyric(data) => SmfEventKind::Meta(SongMeta::Lyric(text(data)?)),
+        MetaMessage::Marker(data) => SmfEventKind::Meta(SongMeta::Marker(text(data)?)),
+        // cue points aren't wrapped in `BytesText` by midix
+        MetaMessage::CuePoint(data) => SmfEventKind::Meta(SongMeta::CuePoint(
+            String::from_utf8_lossy(data).into_owned(),
+        )),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assets::{MidiSong, SongMeta, SongMetaEvent, TempoChange, TimeSignatureChange};
+
+    const END_OF_TRACK: [u8; 4] = [0x00, 0xFF, 0x2F, 0x00];
+    /// 96 ticks per quarter note
+    const PPQN_96: [u8; 2] = [0x00, 0x60];
+
+    /// A file with the given format, time division and track bodies
+    fn smf(format: u8, division: [u8; 2], tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend([0, 0, 0, 6, 0, format, 0, tracks.len() as u8]);
+        bytes.extend(division);
+        for track in tracks {
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32).to_be_bytes());
+            bytes.extend(*track);
+        }
+        bytes
+    }
+
+    fn timestamps(song: &MidiSong) -> Vec<u64> {
+        song.events().iter().map(|event| event.timestamp).collect()
+    }
+
+    #[test]
+    fn format_0_follows_tempo_changes() {
+        let track = [
+            &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20][..], // 500_000 µs per quarter
+            &[0x00, 0xFF, 0x58, 0x04, 0x03, 0x03, 0x18, 0x08], // 3/8
+            &[0x00, 0x90, 0x3C, 0x64],
+            &[0x60, 0x80, 0x3C, 0x00],                         // tick 96
+            &[0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90],       // 250_000 µs per quarter
+            &[0x00, 0xFF, 0x06, 0x04, b'v', b'e', b'r', b's'], // marker
+            &[0x81, 0x40, 0x90, 0x3E, 0x64],                   // tick 288
+            &[0x60, 0x3E, 0x00],                               // running status, tick 384
+            &END_OF_TRACK,
+        ]
+        .concat();
+        let song = MidiSong::from_smf(&smf(0, PPQN_96, &[&track])).unwrap();
+
+        assert_eq!(timestamps(&song), [0, 500_000, 1_000_000, 1_250_000]);
+        assert_eq!(
+            song.tempo_map().tempos(),
+            [
+                TempoChange {
+                    timestamp: 0,
+                    micros_per_quarter: 500_000
+                },
+                TempoChange {
+                    timestamp: 500_000,
+                    micros_per_quarter: 250_000
+                }
+            ]
+        );
+        assert_eq!(
+            song.tempo_map().time_signatures(),
+            [TimeSignatureChange {
+                timestamp: 0,
+                numerator: 3,
+                denominator: 8
+            }]
+        );
+        assert_eq!(
+            song.meta_events(),
+            [SongMetaEvent::new(
+                500_000,
+                SongMeta::Marker("vers".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn format_1_tracks_share_the_conductor_tempo() {
+        let conductor = [
+            &[0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90][..], // 250_000 µs per quarter
+            &END_OF_TRACK,
+        ]
+        .concat();
+        let notes = [
+            &[0x00, 0x90, 0x3C, 0x64][..],
+            &[0x60, 0x80, 0x3C, 0x00],
+            &END_OF_TRACK,
+        ]
+        .concat();
+        let song = MidiSong::from_smf(&smf(1, PPQN_96, &[&conductor, &notes])).unwrap();
+
+        assert_eq!(timestamps(&song), [0, 250_000]);
+        assert_eq!(song.event_track(0), 1);
+        assert_eq!(song.event_track(1), 1);
+        assert_eq!(song.tempo_map().bpm_at(0), 240.);
+    }
+
+    #[test]
+    fn format_2_tracks_play_one_after_another() {
+        // a quarter note, then a quarter rest before the end of the track
+        let pattern = [
+            &[0x00, 0x90, 0x3C, 0x64][..],
+            &[0x60, 0x80, 0x3C, 0x00],
+            &[0x60, 0xFF, 0x2F, 0x00],
+        ]
+        .concat();
+        let song = MidiSong::from_smf(&smf(2, PPQN_96, &[&pattern, &pattern])).unwrap();
+
+        assert_eq!(timestamps(&song), [0, 500_000, 1_000_000, 1_500_000]);
+        let tracks: Vec<u16> = (0..4).map(|index| song.event_track(index)).collect();
+        assert_eq!(tracks, [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn smpte_ticks_ignore_tempo() {
+        let track = [
+            &[0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90][..], // 250_000 µs per quarter
+            &[0x00, 0x90, 0x3C, 0x64],
+            &[0x81, 0x7A, 0x80, 0x3C, 0x00], // tick 250
+            &END_OF_TRACK,
+        ]
+        .concat();
+        // 25 frames per second, 40 ticks per frame: a tick is a millisecond
+        let song = MidiSong::from_smf(&smf(0, [0xE7, 0x28], &[&track])).unwrap();
+
+        assert_eq!(timestamps(&song), [0, 250_000]);
+    }
+
+    #[test]
+    fn missing_header_is_an_error() {
+        let track = [&[0x00, 0x90, 0x3C, 0x64][..], &END_OF_TRACK].concat();
+        let mut bytes = b"MTrk".to_vec();
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+        assert!(MidiSong::from_smf(&bytes).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+
+use midix::prelude::*;
+
+use crate::{
+    assets::{
+        DEFAULT_MICROS_PER_QUARTER, MidiSong, SongMeta, TempoChange, TempoMap, TimeSignatureChange,
+    },
+    util,
+};
+
+const META_TEXT: u8 = 0x01;
+const META_LYRIC: u8 = 0x05;
+const META_MARKER: u8 = 0x06;
+const META_CUE_POINT: u8 = 0x07;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+const META_END_OF_TRACK: u8 = 0x2F;
+
+/// The largest delta time a variable length quantity can hold
+const MAX_DELTA: u64 = 0x0FFF_FFFF;
+
+/// The layout of the tracks of a written Standard MIDI File
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SmfFormat {
+    /// Format 0: every event in one track
+    SingleTrack,
+    /// Format 1: a first track with the tempo map and meta events, then one track for
+    /// each original track of the song. Songs without tracks get one track per channel.
+    #[default]
+    MultiTrack,
+}
+
+/// Settings to write a song as a Standard MIDI File.
+///
+/// The timing of the song is always kept. Overriding the tempo changes how
+/// the song is laid out in beats and bars, not how it sounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmfExportSettings {
+    /// The layout of the tracks
+    pub format: SmfFormat,
+    /// Ticks per quarter note. Clamped to `1..=0x7FFF`.
+    pub ppqn: u16,
+    /// A constant tempo that replaces the song's tempo changes
+    pub micros_per_quarter: Option<u32>,
+    /// A time signature, as numerator and denominator, that replaces the song's
+    /// time signature changes. The denominator must be a power of two.
+    pub time_signature: Option<(u8, u8)>,
+}
+
+impl Default for SmfExportSettings {
+    fn default() -> Self {
+        Self {
+            format: SmfFormat::default(),
+            ppqn: 480,
+            micros_per_quarter: None,
+            time_signature: None,
+        }
+    }
+}
+
+impl SmfExportSettings {
+    /// Write the song with this layout of tracks
+    pub fn with_format(mut self, format: SmfFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Write the song with this many ticks per quarter note
+    pub fn with_ppqn(mut self, ppqn: u16) -> Self {
+        self.ppqn = ppqn;
+        self
+    }
+    /// Write the song at a constant tempo, in beats per minute
+    pub fn with_bpm(mut self, bpm: f64) -> Self {
+        self.micros_per_quarter = Some(TempoChange::from_bpm(0, bpm).micros_per_quarter);
+        self
+    }
+    /// Write the song in one time signature
+    pub fn with_time_signature(mut self, numerator: u8, denominator: u8) -> Self {
+        self.time_signature = Some((numerator, denominator));
+        self
+    }
+
+    /// The tempo map that is written for a song
+    fn tempo_map(&self, song: &TempoMap) -> TempoMap {
+        let mut map = TempoMap::default();
+        match self.micros_per_quarter {
+            Some(micros_per_quarter) => map.add_tempo(TempoChange {
+                timestamp: 0,
+                micros_per_quarter,
+            }),
+            None => song
+                .tempos()
+                .iter()
+                .for_each(|change| map.add_tempo(*change)),
+        }
+        match self.time_signature {
+            Some((numerator, denominator)) => map.add_time_signature(TimeSignatureChange {
+                timestamp: 0,
+                numerator,
+                denominator,
+            }),
+            None => song
+                .time_signatures()
+                .iter()
+                .for_each(|change| map.add_time_signature(*change)),
+        }
+        map
+    }
+}
+
+/// An event of a track being written, with its data after the delta time
+struct TrackEvent {
+    tick: u64,
+    bytes: Vec<u8>,
+}
+
+/// Writes a song as a Standard MIDI File
+pub(crate) fn write_song(song: &MidiSong, settings: &SmfExportSettings) -> Vec<u8> {
+    let ppqn = settings.ppqn.clamp(1, 0x7FFF);
+    let tempo_map = settings.tempo_map(&song.tempo_map);
+    let tick = |micros: u64| (tempo_map.quarters_at(micros) * ppqn as f64).round() as u64;
+
+    let mut conductor = conductor_events(&tempo_map, tick);
+    for event in &song.meta {
+        let kind = match &event.meta {
+            SongMeta::Text(_) => META_TEXT,
+            SongMeta::Lyric(_) => META_LYRIC,
+            SongMeta::Marker(_) => META_MARKER,
+            SongMeta::CuePoint(_) => META_CUE_POINT,
+        };
+        conductor.push(TrackEvent {
+            tick: tick(event.timestamp),
+            bytes: meta_bytes(kind, event.meta.text().as_bytes()),
+        });
+    }
+
+    let has_tracks = song.tracks.len() == song.events.len();
+    let mut tracks = BTreeMap::<u16, Vec<TrackEvent>>::new();
+    for (index, event) in song.events.iter().enumerate() {
+        let track = match settings.format {
+            SmfFormat::SingleTrack => 0,
+            SmfFormat::MultiTrack if has_tracks => song.tracks[index],
+            SmfFormat::MultiTrack => util::channel_index(event.event.channel()) as u16,
+        };
+        tracks.entry(track).or_default().push(TrackEvent {
+            tick: tick(event.timestamp),
+            bytes: message_bytes(&event.event),
+        });
+    }
+
+    let tracks = match settings.format {
+        SmfFormat::SingleTrack => {
+            conductor.extend(tracks.into_values().flatten());
+            vec![conductor]
+        }
+        SmfFormat::MultiTrack => core::iter::once(conductor)
+            .chain(tracks.into_values())
+            .collect(),
+    };
+
+    let format: u16 = match settings.format {
+        SmfFormat::SingleTrack => 0,
+        SmfFormat::MultiTrack => 1,
+    };
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"MThd");
+    bytes.extend_from_slice(&6u32.to_be_bytes());
+    bytes.extend_from_slice(&format.to_be_bytes());
+    bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(&ppqn.to_be_bytes());
+    for track in tracks {
+        write_track(&mut bytes, track);
+    }
+    bytes
+}
+
+/// The tempo and time signature events of a tempo map. The defaults are written at
+/// tick zero if the map doesn't start with a change.
+fn conductor_events(tempo_map: &TempoMap, tick: impl Fn(u64) -> u64) -> Vec<TrackEvent> {
+    let mut events = Vec::new();
+    if tempo_map
+        .tempos()
+        .first()
+        .is_none_or(|change| change.timestamp > 0)
+    {
+        events.push(TrackEvent {
+            tick: 0,
+            bytes: tempo_bytes(DEFAULT_MICROS_PER_QUARTER),
+        });
+    }
+    for change in tempo_map.tempos() {
+        events.push(TrackEvent {
+            tick: tick(change.timestamp),
+            bytes: tempo_bytes(change.micros_per_quarter),
+        });
+    }
+    if tempo_map
+        .time_signatures()
+        .first()
+        .is_none_or(|change| change.timestamp > 0)
+    {
+        events.push(TrackEvent {
+            tick: 0,
+            bytes: time_signature_bytes(4, 4),
+        });
+    }
+    for change in tempo_map.time_signatures() {
+        events.push(TrackEvent {
+            tick: tick(change.timestamp),
+            bytes: time_signature_bytes(change.numerator, change.denominator),
+        });
+    }
+    events
+}
+
+/// Writes an `MTrk` chunk. Events are ordered by tick, keeping their order on ties.
+fn write_track(bytes: &mut Vec<u8>, mut events: Vec<TrackEvent>) {
+    events.sort_by_key(|event| event.tick);
+    let mut data = Vec::new();
+    let mut last_tick = 0;
+    for event in events {
+        let mut delta = event.tick - last_tick;
+        // longer gaps are bridged with empty text events, which are skipped when reading
+        while delta > MAX_DELTA {
+            write_vlq(&mut data, MAX_DELTA as u32);
+            data.extend_from_slice(&meta_bytes(META_TEXT, &[]));
+            delta -= MAX_DELTA;
+        }
+        write_vlq(&mut data, delta as u32);
+        data.extend_from_slice(&event.bytes);
+        last_tick = event.tick;
+    }
+    write_vlq(&mut data, 0);
+    data.extend_from_slice(&meta_bytes(META_END_OF_TRACK, &[]));
+
+    bytes.extend_from_slice(b"MTrk");
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&data);
+}
+
+/// Writes a variable length quantity, which can be at most [`MAX_DELTA`]
+fn write_vlq(bytes: &mut Vec<u8>, value: u32) {
+    debug_assert!(value as u64 <= MAX_DELTA);
+    let mut groups = [0u8; 4];
+    let mut len = 0;
+    let mut rest = value;
+    loop {
+        groups[len] = (rest & 0x7F) as u8;
+        len += 1;
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    for index in (0..len).rev() {
+        let more = if index > 0 { 0x80 } else { 0 };
+        bytes.push(groups[index] | more);
+    }
+}
+
+fn message_bytes(message: &ChannelVoiceMessage) -> Vec<u8> {
+    let [status, data_1, data_2] = util::voice_bytes(message);
+    match status & 0xF0 {
+        util::STATUS_PROGRAM_CHANGE | util::STATUS_CHANNEL_PRESSURE => vec![status, data_1],
+        _ => vec![status, data_1, data_2],
+    }
+}
+
+fn meta_bytes(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xFF, kind];
+    write_vlq(&mut bytes, data.len() as u32);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn tempo_bytes(micros_per_quarter: u32) -> Vec<u8> {
+    meta_bytes(
+        META_TEMPO,
+        &micros_per_quarter.min(0xFF_FFFF).to_be_bytes()[1..],
+    )
+}
+
+fn time_signature_bytes(numerator: u8, denominator: u8) -> Vec<u8> {
+    // the denominator is written as a power of two, with 24 clocks per
+    // metronome click and 8 thirty-second notes per quarter
+    let power = denominator.max(1).ilog2() as u8;
+    meta_bytes(META_TIME_SIGNATURE, &[numerator, power, 24, 8])
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use crate::{
+        assets::{
+            MidiSong, SmfExportSettings, SmfFormat, SongMeta, SongMetaEvent, SongWriter,
+            TempoChange, TempoMap, TimeSignatureChange,
+        },
+        util,
+    };
+
+    fn message(status: u8, channel: Channel, key: u8) -> ChannelVoiceMessage {
+        util::voice_message(status, channel, key, 100).unwrap()
+    }
+
+    /// Every event as its time, bytes and track, to compare songs
+    fn events(song: &MidiSong) -> Vec<(u64, [u8; 3], u16)> {
+        song.events()
+            .iter()
+            .enumerate()
+            .map(|(index, event)| {
+                let bytes = util::voice_bytes(&event.event);
+                (event.timestamp, bytes, song.event_track(index))
+            })
+            .collect()
+    }
+
+    fn song() -> MidiSong {
+        let mut song = MidiSong::with_tracks(
+            vec![
+                Timed::new(0, message(util::STATUS_NOTE_ON, Channel::One, 60)),
+                Timed::new(500_000, message(util::STATUS_NOTE_OFF, Channel::One, 60)),
+                Timed::new(1_000_000, message(util::STATUS_NOTE_ON, Channel::Two, 64)),
+                Timed::new(1_250_000, message(util::STATUS_NOTE_OFF, Channel::Two, 64)),
+            ],
+            vec![1, 1, 2, 2],
+        );
+        let mut tempo_map = TempoMap::default();
+        tempo_map.add_tempo(TempoChange::from_bpm(0, 120.));
+        tempo_map.add_tempo(TempoChange::from_bpm(1_000_000, 240.));
+        tempo_map.add_time_signature(TimeSignatureChange {
+            timestamp: 0,
+            numerator: 3,
+            denominator: 4,
+        });
+        tempo_map.add_time_signature(TimeSignatureChange {
+            timestamp: 1_000_000,
+            numerator: 6,
+            denominator: 8,
+        });
+        song.tempo_map = tempo_map;
+        song.meta = vec![
+            SongMetaEvent::new(0, SongMeta::Marker("verse".to_string())),
+            SongMetaEvent::new(1_250_000, SongMeta::Lyric("la".to_string())),
+        ];
+        song
+    }
+
+    #[test]
+    fn written_songs_read_back_the_same() {
+        let song = song();
+        let read = MidiSong::from_smf(&song.to_smf(&SmfExportSettings::default())).unwrap();
+
+        assert_eq!(events(&read), events(&song));
+        assert_eq!(read.tempo_map().tempos(), song.tempo_map().tempos());
+        assert_eq!(
+            read.tempo_map().time_signatures(),
+            song.tempo_map().time_signatures()
+        );
+        assert_eq!(read.meta_events(), song.meta_events());
+    }
+
+    #[test]
+    fn single_track_files_keep_the_timing() {
+        let song = song();
+        let settings = SmfExportSettings::default()
+            .with_format(SmfFormat::SingleTrack)
+            .with_ppqn(96);
+        let read = MidiSong::from_smf(&song.to_smf(&settings)).unwrap();
+
+        let timestamps = |song: &MidiSong| {
+            song.events()
+                .iter()
+                .map(|event| event.timestamp)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(timestamps(&read), timestamps(&song));
+        assert_eq!(read.meta_events(), song.meta_events());
+    }
+
+    #[test]
+    fn long_gaps_are_split_across_events() {
+        // nearly a week at 120 beats per minute, which is more ticks than one delta can hold
+        let end = 600_000_000_000;
+        let song = MidiSong::new(vec![
+            Timed::new(0, message(util::STATUS_NOTE_ON, Channel::One, 60)),
+            Timed::new(end, message(util::STATUS_NOTE_OFF, Channel::One, 60)),
+        ]);
+        let read = MidiSong::from_smf(&song.to_smf(&SmfExportSettings::default())).unwrap();
+
+        assert_eq!(read.events()[1].timestamp, end);
+        assert!(read.meta_events().is_empty());
+    }
+}
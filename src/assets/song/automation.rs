@@ -0,0 +1,289 @@
+use core::f64::consts::TAU;
+
+use midix::prelude::*;
+
+use crate::util;
+
+/// The default number of automation events per second
+pub const DEFAULT_AUTOMATION_DENSITY: f64 = 50.;
+/// The most automation events placed per second. Higher densities are clamped to this.
+pub const MAX_AUTOMATION_DENSITY: f64 = 1000.;
+
+/// How an [`Automation`] moves from its first value to its last
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Curve {
+    /// A straight line
+    #[default]
+    Linear,
+    /// Starts slowly and speeds up, like a fade in. `curvature` of about 4 sounds natural,
+    /// and larger values bend the curve more.
+    Exponential {
+        /// How strongly the curve bends. Zero is a straight line.
+        curvature: f64,
+    },
+    /// Jumps between this many equal steps, such as for a stepped filter sweep
+    Stepped {
+        /// The number of distinct values
+        steps: u32,
+    },
+    /// Swings from the first value to the last and back, such as for vibrato or tremolo
+    Sine {
+        /// The number of full swings over the automation
+        cycles: f64,
+    },
+}
+
+impl Curve {
+    /// Where the curve is, from 0 to 1, at a point from 0 to 1 of the automation
+    pub fn shape(&self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match *self {
+            Curve::Linear => t,
+            Curve::Exponential { curvature } if curvature.abs() < f64::EPSILON => t,
+            Curve::Exponential { curvature } => (curvature * t).exp_m1() / curvature.exp_m1(),
+            Curve::Stepped { steps } => {
+                let steps = steps.max(2) as f64;
+                ((t * steps).floor() / (steps - 1.)).min(1.)
+            }
+            Curve::Sine { cycles } => 0.5 - 0.5 * (TAU * cycles * t).cos(),
+        }
+    }
+}
+
+/// What an [`Automation`] changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AutomationTarget {
+    /// A controller, such as 7 for volume or 74 for brightness. Values are 0 to 127.
+    ///
+    /// Controllers go up to 127. Automating a higher number adds no events.
+    Controller(u8),
+    /// The pitch bend. Values are -1 (fully down) to 1 (fully up).
+    PitchBend,
+    /// The channel pressure. Values are 0 to 127.
+    ChannelPressure,
+    /// The velocity of notes that start during the automation. Values are 1 to 127.
+    Velocity,
+    /// The tempo of the song, in beats per minute.
+    Tempo,
+}
+
+impl AutomationTarget {
+    /// The message that sets this target to a value, if it is set with a message
+    pub(crate) fn message(&self, channel: Channel, value: f64) -> Option<ChannelVoiceMessage> {
+        let byte = value.round().clamp(0., 127.) as u8;
+        match self {
+            AutomationTarget::Controller(controller) if *controller < 128 => {
+                util::voice_message(util::STATUS_CONTROL_CHANGE, channel, *controller, byte)
+            }
+            AutomationTarget::Controller(_) => None,
+            AutomationTarget::PitchBend => {
+                let bend = (8192. + value.clamp(-1., 1.) * 8192.).round().min(16383.) as u16;
+                util::voice_message(
+                    util::STATUS_PITCH_BEND,
+                    channel,
+                    (bend & 0x7F) as u8,
+                    (bend >> 7) as u8,
+                )
+            }
+            AutomationTarget::ChannelPressure => {
+                util::voice_message(util::STATUS_CHANNEL_PRESSURE, channel, byte, 0)
+            }
+            AutomationTarget::Velocity | AutomationTarget::Tempo => None,
+        }
+    }
+}
+
+/// A curve for a controller, pitch bend, velocity or tempo over a span of time.
+///
+/// Automation is added with `automate` on a [`ChannelBuilder`](crate::assets::ChannelBuilder)
+/// or [`ChannelModifier`](crate::assets::ChannelModifier), which renders it to events.
+/// An automation whose values or density aren't finite numbers adds no events.
+///
+/// ```ignore
+/// // a two second filter sweep
+/// builder
+///     .channel(Channel::One)
+///     .automate(0, 2_000_000, Automation::controller(74, 20, 127));
+/// // a crescendo over four beats, starting on beat 5
+/// song.channel(Channel::One).automate(
+///     5.,
+///     4.,
+///     Automation::velocity(40, 127).with_curve(Curve::Exponential { curvature: 4. }),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Automation {
+    /// What is changed
+    pub target: AutomationTarget,
+    /// The value at the start
+    pub from: f64,
+    /// The value at the end
+    pub to: f64,
+    /// How the value moves from start to end
+    pub curve: Curve,
+    /// How many events are placed per second, up to [`MAX_AUTOMATION_DENSITY`].
+    /// Events that wouldn't change anything are skipped.
+    pub density: f64,
+}
+
+impl Automation {
+    /// Automate any target
+    pub fn new(target: AutomationTarget, from: f64, to: f64) -> Self {
+        Self {
+            target,
+            from,
+            to,
+            curve: Curve::default(),
+            density: DEFAULT_AUTOMATION_DENSITY,
+        }
+    }
+    /// Automate a controller from one value to another
+    pub fn controller(controller: u8, from: u8, to: u8) -> Self {
+        Self::new(
+            AutomationTarget::Controller(controller),
+            from as f64,
+            to as f64,
+        )
+    }
+    /// Automate the pitch bend, from -1 (fully down) to 1 (fully up)
+    pub fn pitch_bend(from: f64, to: f64) -> Self {
+        Self::new(AutomationTarget::PitchBend, from, to)
+    }
+    /// Automate the channel pressure from one value to another
+    pub fn channel_pressure(from: u8, to: u8) -> Self {
+        Self::new(AutomationTarget::ChannelPressure, from as f64, to as f64)
+    }
+    /// Change the velocity of the notes that start during the automation
+    pub fn velocity(from: u8, to: u8) -> Self {
+        Self::new(AutomationTarget::Velocity, from as f64, to as f64)
+    }
+    /// Change the tempo, in beats per minute
+    pub fn tempo(from: f64, to: f64) -> Self {
+        Self::new(AutomationTarget::Tempo, from, to)
+    }
+    /// Use a different curve
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+    /// Place this many events per second, up to [`MAX_AUTOMATION_DENSITY`]
+    pub fn with_density(mut self, density: f64) -> Self {
+        self.density = density;
+        self
+    }
+
+    /// The value at a point from 0 to 1 of the automation
+    pub fn value_at(&self, t: f64) -> f64 {
+        self.from + (self.to - self.from) * self.curve.shape(t)
+    }
+
+    /// True if the values and density are finite numbers. Other automations add no events.
+    pub(crate) fn is_finite(&self) -> bool {
+        [self.from, self.to, self.density]
+            .iter()
+            .all(|value| value.is_finite())
+    }
+
+    /// The points, from 0 to 1, at which events are placed for an automation lasting
+    /// `seconds`. The first and last points are always included, unless the automation
+    /// isn't made of finite numbers, in which case there are none.
+    pub(crate) fn points(&self, seconds: f64) -> Vec<f64> {
+        if !self.is_finite() || !seconds.is_finite() {
+            return Vec::new();
+        }
+        let density = self.density.clamp(0., MAX_AUTOMATION_DENSITY);
+        let count = (seconds.max(0.) * density).ceil().max(1.) as u32;
+        (0..=count).map(|step| step as f64 / count as f64).collect()
+    }
+
+    /// Renders the automation into messages for a channel, at points from 0 to 1.
+    /// Messages that repeat the previous one are skipped.
+    pub(crate) fn render(&self, channel: Channel, seconds: f64) -> Vec<(f64, ChannelVoiceMessage)> {
+        let mut messages: Vec<(f64, ChannelVoiceMessage)> = Vec::new();
+        for t in self.points(seconds) {
+            let Some(message) = self.target.message(channel, self.value_at(t)) else {
+                continue;
+            };
+            if messages.last().is_some_and(|(_, last)| *last == message) {
+                continue;
+            }
+            messages.push((t, message));
+        }
+        messages
+    }
+
+    /// The velocity of a note that starts at a point from 0 to 1 of the automation
+    pub(crate) fn velocity_at(&self, t: f64) -> u8 {
+        self.value_at(t).round().clamp(1., 127.) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::{Automation, AutomationTarget, MAX_AUTOMATION_DENSITY};
+    use crate::assets::MidiSong;
+
+    #[test]
+    fn density_is_clamped() {
+        let points = Automation::controller(7, 0, 127)
+            .with_density(1e12)
+            .points(2.);
+        assert_eq!(points.len(), 2 * MAX_AUTOMATION_DENSITY as usize + 1);
+        assert_eq!(points.first(), Some(&0.));
+        assert_eq!(points.last(), Some(&1.));
+
+        let points = Automation::controller(7, 0, 127)
+            .with_density(-5.)
+            .points(2.);
+        assert_eq!(points, vec![0., 1.]);
+    }
+
+    #[test]
+    fn numbers_that_are_not_finite_add_no_events() {
+        let automation = Automation::controller(7, 0, 127);
+        assert!(automation.with_density(f64::NAN).points(1.).is_empty());
+        assert!(automation.with_density(f64::INFINITY).points(1.).is_empty());
+        assert!(automation.points(f64::INFINITY).is_empty());
+        assert!(Automation::tempo(120., f64::NAN).points(1.).is_empty());
+        assert!(
+            Automation::pitch_bend(f64::NEG_INFINITY, 0.)
+                .render(Channel::One, 1.)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn velocity_automation_that_is_not_finite_is_ignored() {
+        let mut builder = MidiSong::builder();
+        builder
+            .channel(Channel::One)
+            .automate(
+                0,
+                1_000_000,
+                Automation::new(AutomationTarget::Velocity, f64::NAN, 1.),
+            )
+            .note_on(
+                0,
+                Note::from_databyte(60).unwrap(),
+                Velocity::new(100).unwrap(),
+            );
+        let song = builder.build();
+        assert_eq!(song.notes()[0].velocity, 100);
+    }
+
+    #[test]
+    fn controllers_above_127_add_no_events() {
+        assert!(
+            Automation::controller(128, 0, 127)
+                .render(Channel::One, 1.)
+                .is_empty()
+        );
+        assert!(
+            !Automation::controller(127, 0, 127)
+                .render(Channel::One, 1.)
+                .is_empty()
+        );
+    }
+}
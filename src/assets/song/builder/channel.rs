@@ -1,6 +1,7 @@
 use midix::prelude::*;
 
 use super::MidiSongBuilder;
+use crate::assets::Automation;
 
 /// A struct provided to play a bunch of commands for one channel.
 ///
@@ -70,4 +71,20 @@ impl ChannelBuilder<'_> {
         ));
         self
     }
+
+    /// Render an automation lane, starting `time` micros after the last event and lasting
+    /// `duration` micros. Unlike other events, this doesn't move the time of the next event.
+    ///
+    /// Velocity automation changes the notes of this channel that start during it.
+    /// Tempo automation is written to the song's [tempo map](crate::assets::MidiSong::tempo_map).
+    /// The builder's times are then read as if written at 120 BPM, so every event after a tempo
+    /// change is moved to follow it. This differs from
+    /// [`SimpleMidiSong`](crate::assets::SimpleMidiSong), whose positions are in beats at its
+    /// own tempo.
+    pub fn automate(&mut self, time: u64, duration: u64, automation: Automation) -> &mut Self {
+        let start = self.builder.accumulated_time + time;
+        self.builder
+            .automate(self.channel, start, duration, automation);
+        self
+    }
 }
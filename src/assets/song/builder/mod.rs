@@ -4,20 +4,42 @@ use midix::prelude::*;
 mod channel;
 pub use channel::*;
 
-use super::MidiSong;
+mod musical;
+pub use musical::*;
+
+use super::{
+    Automation, AutomationTarget, DEFAULT_MICROS_PER_QUARTER, MidiSong, TempoChange, TempoMap,
+};
+use crate::util;
 
 /// A struct to build a [`MidiSong`] programatically.
 ///
 /// This struct does not consider beats per minute or anything of that sort.
 ///
-/// Straight micros, delta seconds.
+/// Straight micros, delta seconds. Times are played as written unless tempo is automated.
+/// Once a tempo is automated with [`ChannelBuilder::automate`], every time is read as if
+/// written at 120 BPM, a quarter note being 500,000 micros, and moved to follow the
+/// automated tempo when the song is built. To place events at bars and beats, use
+/// [`MidiSongBuilder::musical`].
 #[derive(Default, Clone, Debug)]
 pub struct MidiSongBuilder {
     accumulated_time: u64,
     events: Vec<Timed<ChannelVoiceMessage>>,
+    track: u16,
+    tracks: Vec<u16>,
+    /// Velocity automation as channels, start and end times
+    velocity_lanes: Vec<(Channel, u64, u64, Automation)>,
+    /// Tempo changes as written micros and beats per minute
+    tempo_changes: Vec<(u64, f64)>,
 }
 
 impl MidiSongBuilder {
+    /// Returns a builder that places events in bars, beats and ticks, with
+    /// `ppqn` ticks per quarter note.
+    pub fn musical(ppqn: u16) -> MusicalSongBuilder {
+        MusicalSongBuilder::new(ppqn)
+    }
+
     /// Send a lot of events to one channel efficiently
     pub fn channel(&mut self, channel: Channel) -> ChannelBuilder<'_> {
         ChannelBuilder {
@@ -26,12 +48,22 @@ impl MidiSongBuilder {
         }
     }
 
+    /// Put the events added from now on in a track.
+    ///
+    /// Tracks can be muted, soloed and scaled separately when the song is played
+    /// with a [`SongPlayer`](crate::playback::SongPlayer). Events are in track 0 by default.
+    pub fn track(&mut self, track: u16) -> &mut Self {
+        self.track = track;
+        self
+    }
+
     /// Add a timed channel voice message. event should be in DELTA micros.
     pub fn add(&mut self, mut event: Timed<ChannelVoiceMessage>) -> &mut Self {
         self.accumulated_time += event.timestamp;
         event.timestamp = self.accumulated_time;
 
         self.events.push(event);
+        self.tracks.push(self.track);
         self
     }
     /// Add many timed channel voice messages
@@ -44,11 +76,91 @@ impl MidiSongBuilder {
             event.timestamp = self.accumulated_time;
             event
         }));
+        self.tracks.resize(self.events.len(), self.track);
         self
     }
 
+    /// Render an automation on a channel, from `start` for `duration`, in absolute micros.
+    pub(crate) fn automate(
+        &mut self,
+        channel: Channel,
+        start: u64,
+        duration: u64,
+        automation: Automation,
+    ) {
+        if !automation.is_finite() {
+            return;
+        }
+        let seconds = duration as f64 / 1_000_000.;
+        let at = |t: f64| start + (t * duration as f64) as u64;
+        match automation.target {
+            AutomationTarget::Velocity => {
+                self.velocity_lanes
+                    .push((channel, start, start + duration, automation));
+            }
+            AutomationTarget::Tempo => {
+                for t in automation.points(seconds) {
+                    self.tempo_changes.push((at(t), automation.value_at(t)));
+                }
+            }
+            _ => {
+                for (t, message) in automation.render(channel, seconds) {
+                    self.events.push(Timed::new(at(t), message));
+                    self.tracks.push(self.track);
+                }
+            }
+        }
+    }
+
     /// Build a midi song from the provided events
-    pub fn build(self) -> MidiSong {
-        MidiSong::new(self.events)
+    pub fn build(mut self) -> MidiSong {
+        for (channel, start, end, automation) in &self.velocity_lanes {
+            for event in &mut self.events {
+                if event.event.channel() != *channel || !(*start..=*end).contains(&event.timestamp)
+                {
+                    continue;
+                }
+                let Some(key) = util::note_on_key(&event.event) else {
+                    continue;
+                };
+                let t = (event.timestamp - start) as f64 / (end - start).max(1) as f64;
+                if let Some(message) = util::voice_message(
+                    util::STATUS_NOTE_ON,
+                    *channel,
+                    key,
+                    automation.velocity_at(t),
+                ) {
+                    event.event = message;
+                }
+            }
+        }
+        let tempo_map = self.tempo_map();
+        if !tempo_map.is_empty() {
+            for event in &mut self.events {
+                event.timestamp = tempo_map
+                    .micros_at_quarters(event.timestamp as f64 / DEFAULT_MICROS_PER_QUARTER as f64);
+            }
+        }
+        let mut song = MidiSong::with_tracks(self.events, self.tracks);
+        song.tempo_map = tempo_map;
+        song
+    }
+
+    /// The tempo changes moved from written micros, which are at 120 BPM, to the time they
+    /// are played at
+    fn tempo_map(&mut self) -> TempoMap {
+        self.tempo_changes.sort_by_key(|(written, _)| *written);
+        let mut map = TempoMap::default();
+        let (mut written, mut played) = (0, 0);
+        let mut micros_per_quarter = DEFAULT_MICROS_PER_QUARTER;
+        for (at, bpm) in &self.tempo_changes {
+            played += ((at - written) as f64 * micros_per_quarter as f64
+                / DEFAULT_MICROS_PER_QUARTER as f64) as u64;
+            written = *at;
+            let change = TempoChange::from_bpm(played, *bpm);
+            micros_per_quarter = change.micros_per_quarter;
+            map.add_tempo(change);
+        }
+        map
     }
 }
@@ -0,0 +1,379 @@
+use core::fmt;
+
+use midix::prelude::*;
+
+use crate::assets::{
+    MidiSong, SongMeta, SongMetaEvent, TempoChange, TempoMap, TimeSignatureChange,
+};
+
+/// A position in musical time. Bars and beats are counted from 1, ticks from 0.
+///
+/// A beat is the note value of the time signature's denominator, so a beat of 6/8 is an
+/// eighth note. Beats and ticks past the end of a bar carry into the next bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BarBeatTick {
+    /// The bar, counted from 1
+    pub bar: u32,
+    /// The beat in the bar, counted from 1
+    pub beat: u32,
+    /// Ticks after the start of the beat
+    pub tick: u32,
+}
+
+impl BarBeatTick {
+    /// Create a position from a bar, beat and tick
+    pub const fn new(bar: u32, beat: u32, tick: u32) -> Self {
+        Self { bar, beat, tick }
+    }
+    /// The start of a bar
+    pub const fn bar(bar: u32) -> Self {
+        Self::new(bar, 1, 0)
+    }
+}
+
+impl fmt::Display for BarBeatTick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.bar, self.beat, self.tick)
+    }
+}
+
+/// A builder that places events at [`BarBeatTick`] positions, with a tempo map and
+/// time signature changes. Create one with [`MidiSongBuilder::musical`](super::MidiSongBuilder::musical).
+///
+/// Positions are kept as they are given, and converted to ticks and microseconds when the
+/// song is built, so time signatures and tempos can be added in any order.
+/// The song starts at 120 beats per minute in 4/4.
+#[derive(Clone, Debug)]
+pub struct MusicalSongBuilder {
+    ppqn: u16,
+    /// Tempo changes as positions and microseconds per quarter note
+    tempos: Vec<(Position, u32)>,
+    /// Time signature changes as bars, numerators and denominators
+    time_signatures: Vec<(u32, u8, u8)>,
+    events: Vec<(Position, ChannelVoiceMessage)>,
+    track: u16,
+    tracks: Vec<u16>,
+    meta: Vec<(Position, SongMeta)>,
+}
+
+/// A position that is resolved to a tick when the song is built
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Position {
+    /// A number of ticks after a position in bars and beats
+    After(BarBeatTick, u64),
+    /// An absolute tick
+    Tick(u64),
+}
+
+impl From<BarBeatTick> for Position {
+    fn from(at: BarBeatTick) -> Self {
+        Position::After(at, 0)
+    }
+}
+
+impl MusicalSongBuilder {
+    /// Create a builder with this many ticks per quarter note
+    pub fn new(ppqn: u16) -> Self {
+        Self {
+            ppqn: ppqn.max(1),
+            tempos: Vec::new(),
+            time_signatures: Vec::new(),
+            events: Vec::new(),
+            track: 0,
+            tracks: Vec::new(),
+            meta: Vec::new(),
+        }
+    }
+
+    /// Ticks per quarter note
+    pub fn ppqn(&self) -> u16 {
+        self.ppqn
+    }
+
+    /// Change the tempo, in beats (quarter notes) per minute. Replaces any change at the same tick.
+    pub fn tempo(&mut self, at: BarBeatTick, bpm: f64) -> &mut Self {
+        let micros_per_quarter = TempoChange::from_bpm(0, bpm).micros_per_quarter;
+        self.tempos.push((at.into(), micros_per_quarter));
+        self
+    }
+
+    /// Change the time signature at the start of a bar. Replaces any change at the same bar.
+    ///
+    /// Positions are resolved when the song is built, so this also moves the positions
+    /// placed before this call. MIDI files can only hold denominators that are powers of two,
+    /// so any other denominator, such as 3, is ignored with a warning.
+    pub fn time_signature(&mut self, bar: u32, numerator: u8, denominator: u8) -> &mut Self {
+        if !denominator.is_power_of_two() {
+            bevy::log::warn!(
+                "Ignoring time signature {numerator}/{denominator} at bar {bar}: \
+                 the denominator must be a power of two"
+            );
+            return self;
+        }
+        let bar = bar.max(1);
+        self.time_signatures
+            .retain(|(existing, ..)| *existing != bar);
+        self.time_signatures
+            .push((bar, numerator.max(1), denominator));
+        self.time_signatures.sort_by_key(|(bar, ..)| *bar);
+        self
+    }
+
+    /// The tick of a position, following the time signature changes added so far
+    pub fn tick_at(&self, at: BarBeatTick) -> u64 {
+        let ppqn = self.ppqn as u64;
+        let target = at.bar.max(1);
+        let mut tick = 0;
+        let mut bar = 1;
+        let (mut numerator, mut denominator) = (4, 4);
+        for (change_bar, change_numerator, change_denominator) in &self.time_signatures {
+            if *change_bar > target {
+                break;
+            }
+            tick += (*change_bar - bar) as u64 * ticks_per_bar(ppqn, numerator, denominator);
+            bar = *change_bar;
+            (numerator, denominator) = (*change_numerator, *change_denominator);
+        }
+        tick + (target - bar) as u64 * ticks_per_bar(ppqn, numerator, denominator)
+            + at.beat.saturating_sub(1) as u64 * ticks_per_beat(ppqn, denominator)
+            + at.tick as u64
+    }
+
+    /// Send a lot of events to one channel efficiently
+    pub fn channel(&mut self, channel: Channel) -> MusicalChannelBuilder<'_> {
+        MusicalChannelBuilder {
+            builder: self,
+            channel,
+        }
+    }
+
+    /// Put the events added from now on in a track. Events are in track 0 by default.
+    pub fn track(&mut self, track: u16) -> &mut Self {
+        self.track = track;
+        self
+    }
+
+    /// Add a channel voice message at a position
+    pub fn add(&mut self, at: BarBeatTick, event: ChannelVoiceMessage) -> &mut Self {
+        self.push(at.into(), event)
+    }
+
+    /// Add a channel voice message at an absolute tick
+    pub fn add_at_tick(&mut self, tick: u64, event: ChannelVoiceMessage) -> &mut Self {
+        self.push(Position::Tick(tick), event)
+    }
+
+    fn push(&mut self, at: Position, event: ChannelVoiceMessage) -> &mut Self {
+        self.events.push((at, event));
+        self.tracks.push(self.track);
+        self
+    }
+
+    /// The tick of a position
+    fn resolve(&self, at: Position) -> u64 {
+        match at {
+            Position::After(at, ticks) => self.tick_at(at) + ticks,
+            Position::Tick(tick) => tick,
+        }
+    }
+
+    /// Add a marker, such as [`LOOP_START_MARKER`](crate::playback::LOOP_START_MARKER)
+    pub fn marker(&mut self, at: BarBeatTick, name: impl Into<String>) -> &mut Self {
+        self.meta.push((at.into(), SongMeta::Marker(name.into())));
+        self
+    }
+
+    /// Build a midi song, converting every position to microseconds
+    pub fn build(self) -> MidiSong {
+        let quarters = |at: Position| self.resolve(at) as f64 / self.ppqn as f64;
+
+        let mut tempos = self
+            .tempos
+            .iter()
+            .map(|(at, micros_per_quarter)| (quarters(*at), *micros_per_quarter))
+            .collect::<Vec<_>>();
+        // stable, so the last change at a tick wins
+        tempos.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut tempo_map = TempoMap::default();
+        for (at, micros_per_quarter) in tempos {
+            tempo_map.add_tempo_at_quarters(at, micros_per_quarter);
+        }
+        let micros = |at: Position| tempo_map.micros_at_quarters(quarters(at));
+
+        let time_signatures = self
+            .time_signatures
+            .iter()
+            .map(|(bar, numerator, denominator)| TimeSignatureChange {
+                timestamp: micros(BarBeatTick::bar(*bar).into()),
+                numerator: *numerator,
+                denominator: *denominator,
+            })
+            .collect::<Vec<_>>();
+        let events = self
+            .events
+            .iter()
+            .map(|(at, event)| Timed::new(micros(*at), *event))
+            .collect();
+        let meta = self
+            .meta
+            .iter()
+            .map(|(at, meta)| SongMetaEvent::new(micros(*at), meta.clone()))
+            .collect();
+
+        for change in time_signatures {
+            tempo_map.add_time_signature(change);
+        }
+        let mut song = MidiSong::with_tracks(events, self.tracks.clone());
+        song.tempo_map = tempo_map;
+        song.meta = meta;
+        song
+    }
+}
+
+fn ticks_per_beat(ppqn: u64, denominator: u8) -> u64 {
+    ppqn * 4 / denominator as u64
+}
+
+fn ticks_per_bar(ppqn: u64, numerator: u8, denominator: u8) -> u64 {
+    numerator as u64 * ticks_per_beat(ppqn, denominator)
+}
+
+/// A struct provided to place a bunch of events on one channel in musical time.
+pub struct MusicalChannelBuilder<'a> {
+    pub(crate) builder: &'a mut MusicalSongBuilder,
+    pub(crate) channel: Channel,
+}
+
+impl MusicalChannelBuilder<'_> {
+    fn add(&mut self, at: BarBeatTick, event: VoiceEvent) -> &mut Self {
+        self.builder
+            .add(at, ChannelVoiceMessage::new(self.channel, event));
+        self
+    }
+    /// Set a program change (new voice)
+    pub fn program_change(&mut self, at: BarBeatTick, program: Program) -> &mut Self {
+        self.add(at, VoiceEvent::program_change(program))
+    }
+    /// Turn a note on
+    pub fn note_on(&mut self, at: BarBeatTick, note: Note, velocity: Velocity) -> &mut Self {
+        self.add(at, VoiceEvent::note_on(note, velocity))
+    }
+    /// Turn a note off
+    pub fn note_off(&mut self, at: BarBeatTick, note: Note, velocity: Velocity) -> &mut Self {
+        self.add(at, VoiceEvent::note_off(note, velocity))
+    }
+    /// Play a note for a number of ticks
+    pub fn note(
+        &mut self,
+        at: BarBeatTick,
+        ticks: u64,
+        note: Note,
+        velocity: Velocity,
+    ) -> &mut Self {
+        let channel = self.channel;
+        self.builder
+            .push(
+                Position::After(at, 0),
+                ChannelVoiceMessage::new(channel, VoiceEvent::note_on(note, velocity)),
+            )
+            .push(
+                Position::After(at, ticks),
+                ChannelVoiceMessage::new(channel, VoiceEvent::note_off(note, velocity)),
+            );
+        self
+    }
+    /// Modify the velocity of a note after it's been played.
+    pub fn after_touch(&mut self, at: BarBeatTick, note: Note, velocity: Velocity) -> &mut Self {
+        self.add(at, VoiceEvent::after_touch(note, velocity))
+    }
+    ///Modify the controller's presets
+    pub fn control_change(&mut self, at: BarBeatTick, controller: Controller) -> &mut Self {
+        self.add(at, VoiceEvent::control_change(controller))
+    }
+    /// Change the note velocity of a whole channel at once without starting new notes
+    pub fn channel_after_touch(&mut self, at: BarBeatTick, velocity: Velocity) -> &mut Self {
+        self.add(at, VoiceEvent::channel_after_touch(velocity))
+    }
+    /// Bend the pitch
+    pub fn pitch_bend(&mut self, at: BarBeatTick, pitch_bend: PitchBend) -> &mut Self {
+        self.add(at, VoiceEvent::pitch_bend(pitch_bend))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BarBeatTick, MusicalSongBuilder};
+    use crate::assets::TimeSignatureChange;
+
+    /// 4/4 for two bars, 3/4 for two bars, then 6/8
+    fn builder() -> MusicalSongBuilder {
+        let mut builder = MusicalSongBuilder::new(480);
+        builder.time_signature(5, 6, 8).time_signature(3, 3, 4);
+        builder
+    }
+
+    #[test]
+    fn positions_follow_time_signature_changes() {
+        let builder = builder();
+        assert_eq!(builder.tick_at(BarBeatTick::bar(1)), 0);
+        assert_eq!(builder.tick_at(BarBeatTick::new(2, 4, 0)), 1920 + 3 * 480);
+        assert_eq!(builder.tick_at(BarBeatTick::bar(3)), 3840);
+        assert_eq!(builder.tick_at(BarBeatTick::new(3, 2, 0)), 3840 + 480);
+        assert_eq!(builder.tick_at(BarBeatTick::bar(5)), 3840 + 2 * 1440);
+        // a beat of 6/8 is an eighth note
+        assert_eq!(
+            builder.tick_at(BarBeatTick::new(5, 4, 10)),
+            6720 + 3 * 240 + 10
+        );
+        assert_eq!(builder.tick_at(BarBeatTick::bar(6)), 6720 + 6 * 240);
+    }
+
+    #[test]
+    fn beats_past_the_end_of_a_bar_carry_over() {
+        let builder = builder();
+        assert_eq!(
+            builder.tick_at(BarBeatTick::new(3, 4, 0)),
+            builder.tick_at(BarBeatTick::bar(4))
+        );
+    }
+
+    #[test]
+    fn a_later_change_at_the_same_bar_replaces_it() {
+        let mut builder = builder();
+        builder.time_signature(3, 2, 4);
+        assert_eq!(builder.tick_at(BarBeatTick::bar(5)), 3840 + 2 * 960);
+    }
+
+    #[test]
+    fn denominators_that_are_not_powers_of_two_are_ignored() {
+        let mut ignored = builder();
+        ignored.time_signature(3, 4, 3).time_signature(7, 4, 0);
+        for bar in 1..10 {
+            assert_eq!(
+                ignored.tick_at(BarBeatTick::bar(bar)),
+                builder().tick_at(BarBeatTick::bar(bar))
+            );
+        }
+    }
+
+    #[test]
+    fn time_signatures_are_placed_at_their_bars() {
+        let song = builder().build();
+        // at 120 beats per minute, a quarter note is half a second
+        assert_eq!(
+            song.tempo_map().time_signatures(),
+            [
+                TimeSignatureChange {
+                    timestamp: 4_000_000,
+                    numerator: 3,
+                    denominator: 4
+                },
+                TimeSignatureChange {
+                    timestamp: 7_000_000,
+                    numerator: 6,
+                    denominator: 8
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use midix::prelude::*;
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{Error as _, Unexpected},
+};
+use thiserror::Error;
+
+use super::{MidiSong, SongMeta, SongMetaEvent, TempoMap};
+use crate::util;
+
+/// A song written by hand, in notes, beats and bars rather than microseconds.
+///
+/// This is loaded from `.song.ron` files by the
+/// [`SongDocumentLoader`](crate::assets::SongDocumentLoader), and converted with
+/// [`SongDocument::to_song`].
+///
+/// ```ron
+/// (
+///     bpm: 140.0,
+///     time_signature: (3, 4),
+///     tracks: [
+///         (
+///             channel: 1,
+///             program: Some(73),
+///             notes: [
+///                 (bar: 1, beat: 1.0, note: "E5", length: 0.5),
+///                 (bar: 1, beat: 1.5, note: "G5", length: 0.5, velocity: 80),
+///                 (bar: 1, beat: 2.0, note: 76, length: 2.0),
+///             ],
+///         ),
+///     ],
+///     markers: [(bar: 2, name: "loopStart")],
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SongDocument {
+    /// The tempo, in beats (quarter notes) per minute
+    pub bpm: f64,
+    /// The time signature, as numerator and denominator
+    pub time_signature: (u8, u8),
+    /// If true, the song repeats
+    pub looped: bool,
+    /// The tracks of the song. Each becomes a track of the [`MidiSong`].
+    pub tracks: Vec<TrackDocument>,
+    /// Markers, such as `loopStart` and `loopEnd`
+    pub markers: Vec<MarkerDocument>,
+}
+
+impl Default for SongDocument {
+    fn default() -> Self {
+        Self {
+            bpm: 120.,
+            time_signature: (4, 4),
+            looped: false,
+            tracks: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+}
+
+/// The notes of one channel in a [`SongDocument`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackDocument {
+    /// The channel, counted from 1
+    pub channel: u8,
+    /// A program to select at the start of the song, from 0 to 127
+    #[serde(deserialize_with = "data_byte")]
+    pub program: Option<u8>,
+    /// A channel volume (controller 7) to set at the start of the song, from 0 to 127
+    #[serde(deserialize_with = "data_byte")]
+    pub volume: Option<u8>,
+    /// The notes of the track, in any order
+    pub notes: Vec<NoteDocument>,
+}
+
+impl Default for TrackDocument {
+    fn default() -> Self {
+        Self {
+            channel: 1,
+            program: None,
+            volume: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// Reads an optional MIDI data byte, which must be from 0 to 127
+fn data_byte<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let value = Option::<u8>::deserialize(deserializer)?;
+    match value {
+        Some(byte) if byte > 127 => Err(D::Error::invalid_value(
+            Unexpected::Unsigned(byte as u64),
+            &"a value from 0 to 127",
+        )),
+        _ => Ok(value),
+    }
+}
+
+/// A note in a [`TrackDocument`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteDocument {
+    /// The bar, counted from 1
+    pub bar: u32,
+    /// The beat within the bar, counted from 1. `1.5` is halfway through the first beat.
+    pub beat: f64,
+    /// The note, as a name such as `"C4"` or `"F#3"`, or a key number
+    pub note: NoteName,
+    /// The length of the note, in beats
+    pub length: f64,
+    /// How hard the note is played, from 1 to 127
+    pub velocity: u8,
+}
+
+impl Default for NoteDocument {
+    fn default() -> Self {
+        Self {
+            bar: 1,
+            beat: 1.,
+            note: NoteName::Key(60),
+            length: 1.,
+            velocity: 100,
+        }
+    }
+}
+
+/// A marker in a [`SongDocument`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkerDocument {
+    /// The bar, counted from 1
+    pub bar: u32,
+    /// The beat within the bar, counted from 1
+    pub beat: f64,
+    /// The text of the marker
+    pub name: String,
+}
+
+impl Default for MarkerDocument {
+    fn default() -> Self {
+        Self {
+            bar: 1,
+            beat: 1.,
+            name: String::new(),
+        }
+    }
+}
+
+/// A note, written as a key number or a name where `"C4"` is 60
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NoteName {
+    /// A MIDI key, from 0 to 127
+    Key(u8),
+    /// A name such as `"C4"`, `"F#3"` or `"Bb2"`
+    Name(String),
+}
+
+impl NoteName {
+    /// The MIDI key of this note
+    pub fn key(&self) -> Option<u8> {
+        match self {
+            NoteName::Key(key) => (*key < 128).then_some(*key),
+            NoteName::Name(name) => util::parse_note_name(name),
+        }
+    }
+}
+
+/// Possible errors when converting a [`SongDocument`] into a [`MidiSong`]
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SongDocumentError {
+    /// A channel is not between 1 and 16
+    #[error("Invalid channel {0}, expected 1 to 16")]
+    InvalidChannel(u8),
+    /// A note name or key could not be read
+    #[error("Invalid note {0:?}")]
+    InvalidNote(NoteName),
+    /// The time signature has a zero or a denominator that isn't a power of two
+    #[error("Invalid time signature {0}/{1}")]
+    InvalidTimeSignature(u8, u8),
+    /// The tempo is not a positive number
+    #[error("Invalid tempo {0}")]
+    InvalidTempo(f64),
+    /// A program or volume is above 127
+    #[error("Invalid {0} {1}, expected 0 to 127")]
+    OutOfRange(&'static str, u8),
+}
+
+impl SongDocument {
+    /// Converts the document into a song.
+    ///
+    /// # Errors
+    /// If a channel, note, program, volume, the time signature or the tempo is invalid
+    pub fn to_song(&self) -> Result<MidiSong, SongDocumentError> {
+        let (numerator, denominator) = self.time_signature;
+        if numerator == 0 || !denominator.is_power_of_two() {
+            return Err(SongDocumentError::InvalidTimeSignature(
+                numerator,
+                denominator,
+            ));
+        }
+        if !self.bpm.is_finite() || self.bpm <= 0. {
+            return Err(SongDocumentError::InvalidTempo(self.bpm));
+        }
+        let tempo_map = TempoMap::constant(self.bpm, numerator, denominator);
+        let beat = 4. / denominator as f64;
+        let bar = numerator as f64 * beat;
+        let micros = |bar_number: u32, beat_number: f64| {
+            let quarters =
+                bar_number.saturating_sub(1) as f64 * bar + (beat_number - 1.).max(0.) * beat;
+            tempo_map.micros_at_quarters(quarters)
+        };
+
+        let mut events = Vec::new();
+        let mut tracks = Vec::new();
+        for (index, track) in self.tracks.iter().enumerate() {
+            let channel = track
+                .channel
+                .checked_sub(1)
+                .and_then(|channel| util::CHANNELS.get(channel as usize).copied())
+                .ok_or(SongDocumentError::InvalidChannel(track.channel))?;
+            let mut push = |timestamp: u64, message: Option<ChannelVoiceMessage>| {
+                if let Some(message) = message {
+                    events.push(Timed::new(timestamp, message));
+                    tracks.push(index as u16);
+                }
+            };
+            for (name, value) in [("program", track.program), ("volume", track.volume)] {
+                if let Some(value) = value.filter(|value| *value > 127) {
+                    return Err(SongDocumentError::OutOfRange(name, value));
+                }
+            }
+            if let Some(program) = track.program {
+                push(
+                    0,
+                    util::voice_message(util::STATUS_PROGRAM_CHANGE, channel, program, 0),
+                );
+            }
+            if let Some(volume) = track.volume {
+                push(
+                    0,
+                    util::voice_message(util::STATUS_CONTROL_CHANGE, channel, 7, volume),
+                );
+            }
+            for note in &track.notes {
+                let key = note
+                    .note
+                    .key()
+                    .ok_or_else(|| SongDocumentError::InvalidNote(note.note.clone()))?;
+                let start = micros(note.bar, note.beat);
+                let end = micros(note.bar, note.beat + note.length.max(0.));
+                let velocity = note.velocity.clamp(1, 127);
+                push(
+                    start,
+                    util::voice_message(util::STATUS_NOTE_ON, channel, key, velocity),
+                );
+                push(
+                    end,
+                    util::voice_message(util::STATUS_NOTE_OFF, channel, key, 0),
+                );
+            }
+        }
+
+        let mut song = MidiSong::with_tracks(events, tracks);
+        song.tempo_map = tempo_map.clone();
+        song.meta = self
+            .markers
+            .iter()
+            .map(|marker| {
+                SongMetaEvent::new(
+                    micros(marker.bar, marker.beat),
+                    SongMeta::Marker(marker.name.clone()),
+                )
+            })
+            .collect();
+        song.looped = self.looped;
+        Ok(song)
+    }
+}
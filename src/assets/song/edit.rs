@@ -0,0 +1,685 @@
+use bevy::platform::collections::HashMap;
+use midix::prelude::*;
+
+use super::{MidiSong, SongMetaEvent, TempoChange, TempoMap, TimeSignatureChange};
+use crate::util;
+
+/// The first controller that is a channel mode message, such as All Notes Off
+const FIRST_CHANNEL_MODE_CONTROLLER: u8 = 120;
+
+impl MidiSong {
+    /// The track of every event, with track 0 for every event if the tracks are not known
+    fn tracks_or_default(&self) -> Vec<u16> {
+        (0..self.events.len())
+            .map(|index| self.event_track(index))
+            .collect()
+    }
+
+    /// Cut out the part of the song from `start` up to `end`, in microseconds.
+    /// The new song starts at 0.
+    ///
+    /// Notes that are still sounding at `end` are ended there, and notes that started before
+    /// `start` are left out. The latest program, controllers, pitch bend and channel pressure
+    /// of each channel before `start` are placed at 0, so the slice sounds like the original.
+    /// Meta events and the tempo map are cut the same way.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let song = MidiSong::default();
+    /// // the second bar of a 4/4 song at 120 beats per minute
+    /// let bar = song.slice(2_000_000, 4_000_000);
+    /// ```
+    pub fn slice(&self, start: u64, end: u64) -> MidiSong {
+        let pairs = self.note_pairs();
+        let mut off_of_on = HashMap::new();
+        let mut on_of_off = HashMap::new();
+        for (on, off) in &pairs {
+            off_of_on.insert(*on, *off);
+            if let Some(off) = off {
+                on_of_off.insert(*off, *on);
+            }
+        }
+
+        let mut order = (0..self.events.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| self.events[*index].timestamp);
+
+        let mut events = Vec::new();
+        let mut tracks = Vec::new();
+        // the latest state of each channel before the slice, by status, channel and controller
+        let mut chased = Vec::<((u8, Channel, u8), usize)>::new();
+        for index in order {
+            let event = &self.events[index];
+            let [status, data_1, _] = util::voice_bytes(&event.event);
+            if event.timestamp < start {
+                let kind = match status & 0xF0 {
+                    util::STATUS_PROGRAM_CHANGE
+                    | util::STATUS_PITCH_BEND
+                    | util::STATUS_CHANNEL_PRESSURE => (status & 0xF0, event.event.channel(), 0),
+                    util::STATUS_CONTROL_CHANGE if data_1 < FIRST_CHANNEL_MODE_CONTROLLER => {
+                        (status & 0xF0, event.event.channel(), data_1)
+                    }
+                    _ => continue,
+                };
+                chased.retain(|(existing, _)| *existing != kind);
+                chased.push((kind, index));
+                continue;
+            }
+            if event.timestamp >= end {
+                continue;
+            }
+            if on_of_off
+                .get(&index)
+                .is_some_and(|on| self.events[*on].timestamp < start)
+            {
+                continue;
+            }
+            events.push(Timed::new(event.timestamp - start, event.event));
+            tracks.push(self.event_track(index));
+
+            // end notes that last past the slice, including notes that never end
+            let ends_after = match off_of_on.get(&index) {
+                Some(Some(off)) => self.events[*off].timestamp >= end,
+                Some(None) => end != u64::MAX,
+                None => false,
+            };
+            if ends_after {
+                let [_, key, _] = util::voice_bytes(&event.event);
+                if let Some(off) =
+                    util::voice_message(util::STATUS_NOTE_OFF, event.event.channel(), key, 0)
+                {
+                    events.push(Timed::new(end - start, off));
+                    tracks.push(self.event_track(index));
+                }
+            }
+        }
+        let chased_events = chased.iter().map(|(_, index)| {
+            (
+                Timed::new(0, self.events[*index].event),
+                self.event_track(*index),
+            )
+        });
+        let (mut all_events, mut all_tracks): (Vec<_>, Vec<_>) = chased_events.unzip();
+        all_events.extend(events);
+        all_tracks.extend(tracks);
+
+        let mut song = MidiSong::with_tracks(all_events, all_tracks);
+        song.sort_events();
+        song.tempo_map = self.tempo_map.slice(start, end);
+        song.meta = self
+            .meta
+            .iter()
+            .filter(|meta| meta.timestamp >= start && meta.timestamp < end)
+            .map(|meta| SongMetaEvent::new(meta.timestamp - start, meta.meta.clone()))
+            .collect();
+        song.looped = self.looped;
+        song.paused = self.paused;
+        song
+    }
+
+    /// Add another song after the end of this one, which is the last event or meta event,
+    /// see [`MidiSong::duration`].
+    ///
+    /// Silence after the last event, such as rests or the rest of the last bar, is not kept.
+    /// Use [`MidiSong::concat_at`] to place the other song at a time of your choosing.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let intro = MidiSong::default();
+    /// # let verse = MidiSong::default();
+    /// # let outro = MidiSong::default();
+    /// let mut medley = intro.clone();
+    /// medley.concat(&verse).concat(&outro);
+    /// ```
+    pub fn concat(&mut self, other: &MidiSong) -> &mut Self {
+        let offset = self.duration();
+        self.concat_at(other, offset)
+    }
+
+    /// Add another song starting at `offset`, in microseconds.
+    ///
+    /// Notes of this song that never end are ended where the other song starts. The other
+    /// song keeps its tempo map, and its tracks are kept as they are.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let mut song = MidiSong::default();
+    /// # let chorus = MidiSong::default();
+    /// // start the chorus on bar 9, keeping the rests at the end of bar 8
+    /// let bar = song.tempo_map().micros_at_bar(9.);
+    /// song.concat_at(&chorus, bar);
+    /// ```
+    pub fn concat_at(&mut self, other: &MidiSong, offset: u64) -> &mut Self {
+        let mut tracks = self.tracks_or_default();
+        for (on, off) in self.note_pairs() {
+            if off.is_some() {
+                continue;
+            }
+            let message = self.events[on].event;
+            let [_, key, _] = util::voice_bytes(&message);
+            if let Some(off) = util::voice_message(util::STATUS_NOTE_OFF, message.channel(), key, 0)
+            {
+                // the tracks of the new events are pushed alongside, so `event_track` can't be used
+                tracks.push(tracks[on]);
+                self.events.push(Timed::new(offset, off));
+            }
+        }
+
+        tracks.extend(other.tracks_or_default());
+        self.events.extend(
+            other
+                .events
+                .iter()
+                .map(|event| Timed::new(event.timestamp + offset, event.event)),
+        );
+        self.tracks = tracks;
+        self.meta.extend(
+            other
+                .meta
+                .iter()
+                .map(|meta| SongMetaEvent::new(meta.timestamp + offset, meta.meta.clone())),
+        );
+
+        let other_map = &other.tempo_map;
+        if !(self.tempo_map.is_empty() && other_map.is_empty()) {
+            // the other song starts at its own tempo, which is 120 beats per minute by default
+            self.tempo_map.add_tempo(TempoChange {
+                timestamp: offset,
+                micros_per_quarter: other_map.micros_per_quarter_at(0),
+            });
+            let signature = other_map.time_signature_at(0);
+            self.tempo_map.add_time_signature(TimeSignatureChange {
+                timestamp: offset,
+                ..signature
+            });
+        }
+        for change in other_map.tempos() {
+            self.tempo_map.add_tempo(TempoChange {
+                timestamp: change.timestamp + offset,
+                ..*change
+            });
+        }
+        for change in other_map.time_signatures() {
+            self.tempo_map.add_time_signature(TimeSignatureChange {
+                timestamp: change.timestamp + offset,
+                ..*change
+            });
+        }
+        self.sort_events();
+        self
+    }
+
+    /// Play another song at the same time as this one.
+    ///
+    /// The events are sorted by timestamp, with this song's events first when they happen
+    /// at the same time. The tracks of the other song are numbered after this song's tracks.
+    /// This song's tempo map is kept.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let mut drums = MidiSong::default();
+    /// # let bass = MidiSong::default();
+    /// # let melody = MidiSong::default();
+    /// drums.merge(&bass).merge(&melody);
+    /// ```
+    pub fn merge(&mut self, other: &MidiSong) -> &mut Self {
+        let first_track = self.track_count();
+        let mut tracks = self.tracks_or_default();
+        tracks.extend(
+            other
+                .tracks_or_default()
+                .into_iter()
+                .map(|track| track.saturating_add(first_track)),
+        );
+        self.events.extend(other.events.iter().copied());
+        self.tracks = tracks;
+        self.meta.extend(other.meta.iter().cloned());
+        self.sort_events();
+        self
+    }
+
+    /// Move every note by a number of semitones. Notes on channel 10, the drum channel,
+    /// are not moved.
+    ///
+    /// Notes that would leave the range of MIDI keys are dropped, with their note off and
+    /// key pressure, rather than clamped to the lowest or highest key. Sections of an
+    /// [`Arrangement`](super::Arrangement) and songs read by the
+    /// [`MidiSongLoader`](crate::assets::MidiSongLoader) are transposed the same way.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let mut song = MidiSong::default();
+    /// song.transpose(-12);
+    /// ```
+    pub fn transpose(&mut self, semitones: i8) -> &mut Self {
+        if semitones == 0 {
+            return self;
+        }
+        let tracks_known = self.tracks.len() == self.events.len();
+        let tracks = self.tracks_or_default();
+        let (events, tracks): (Vec<_>, Vec<_>) = core::mem::take(&mut self.events)
+            .into_iter()
+            .zip(tracks)
+            .filter_map(|(event, track)| {
+                let message = util::transpose(event.event, semitones)?;
+                Some((Timed::new(event.timestamp, message), track))
+            })
+            .unzip();
+        self.events = events;
+        if tracks_known {
+            self.tracks = tracks;
+        }
+        self
+    }
+
+    /// Move the events of each channel to the channel returned by `remap`.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let mut song = MidiSong::default();
+    /// // play the melody on channel 3 instead of 1
+    /// song.remap_channels(|channel| match channel {
+    ///     Channel::One => Channel::Three,
+    ///     other => other,
+    /// });
+    /// ```
+    pub fn remap_channels(&mut self, remap: impl Fn(Channel) -> Channel) -> &mut Self {
+        for event in &mut self.events {
+            let channel = remap(event.event.channel());
+            if channel == event.event.channel() {
+                continue;
+            }
+            let [status, data_1, data_2] = util::voice_bytes(&event.event);
+            if let Some(message) = util::voice_message(status, channel, data_1, data_2) {
+                event.event = message;
+            }
+        }
+        self
+    }
+
+    /// Move the whole song later, or earlier with a negative number of microseconds.
+    ///
+    /// Moving earlier cuts off the start of the song like [`MidiSong::slice`], so notes are
+    /// never left without a start. Moving later stops at the largest possible time.
+    ///
+    /// ```
+    /// # use bevy_midix::prelude::*;
+    /// # let mut song = MidiSong::default();
+    /// // leave a one second count-in
+    /// song.shift(1_000_000);
+    /// ```
+    pub fn shift(&mut self, micros: i64) -> &mut Self {
+        if micros < 0 {
+            let id = self.id;
+            *self = self.slice(micros.unsigned_abs(), u64::MAX);
+            self.id = id;
+            return self;
+        }
+        let micros = micros as u64;
+        for event in &mut self.events {
+            event.timestamp = event.timestamp.saturating_add(micros);
+        }
+        for meta in &mut self.meta {
+            meta.timestamp = meta.timestamp.saturating_add(micros);
+        }
+        let mut tempo_map = TempoMap::default();
+        if !self.tempo_map.is_empty() {
+            // the silence at the start keeps the song's first tempo and time signature
+            tempo_map.add_tempo(TempoChange {
+                timestamp: 0,
+                micros_per_quarter: self.tempo_map.micros_per_quarter_at(0),
+            });
+            tempo_map.add_time_signature(self.tempo_map.time_signature_at(0));
+        }
+        for change in self.tempo_map.tempos() {
+            tempo_map.add_tempo(TempoChange {
+                timestamp: change.timestamp.saturating_add(micros),
+                ..*change
+            });
+        }
+        for change in self.tempo_map.time_signatures() {
+            tempo_map.add_time_signature(TimeSignatureChange {
+                timestamp: change.timestamp.saturating_add(micros),
+                ..*change
+            });
+        }
+        self.tempo_map = tempo_map;
+        self
+    }
+}
+
+impl TempoMap {
+    /// The changes from `start` up to `end`, moved so `start` is at 0.
+    ///
+    /// The tempo and time signature at `start` are placed at 0 if there are any changes.
+    pub(crate) fn slice(&self, start: u64, end: u64) -> TempoMap {
+        let mut map = TempoMap::default();
+        if !self.tempos().is_empty() {
+            map.add_tempo(TempoChange {
+                timestamp: 0,
+                micros_per_quarter: self.micros_per_quarter_at(start),
+            });
+        }
+        if !self.time_signatures().is_empty() {
+            map.add_time_signature(TimeSignatureChange {
+                timestamp: 0,
+                ..self.time_signature_at(start)
+            });
+        }
+        let within = |timestamp: u64| timestamp > start && timestamp < end;
+        for change in self.tempos().iter().filter(|c| within(c.timestamp)) {
+            map.add_tempo(TempoChange {
+                timestamp: change.timestamp - start,
+                ..*change
+            });
+        }
+        for change in self
+            .time_signatures()
+            .iter()
+            .filter(|c| within(c.timestamp))
+        {
+            map.add_time_signature(TimeSignatureChange {
+                timestamp: change.timestamp - start,
+                ..*change
+            });
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use crate::{
+        assets::{MidiSong, SongMeta, SongMetaEvent, TempoChange},
+        util,
+    };
+
+    fn message(status: u8, channel: Channel, data_1: u8, data_2: u8) -> ChannelVoiceMessage {
+        util::voice_message(status, channel, data_1, data_2).unwrap()
+    }
+
+    fn on(timestamp: u64, channel: Channel, key: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_ON, channel, key, 100))
+    }
+
+    fn off(timestamp: u64, channel: Channel, key: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_OFF, channel, key, 0))
+    }
+
+    fn control(timestamp: u64, controller: u8, value: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(
+            timestamp,
+            message(util::STATUS_CONTROL_CHANGE, Channel::One, controller, value),
+        )
+    }
+
+    fn program(timestamp: u64, program: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(
+            timestamp,
+            message(util::STATUS_PROGRAM_CHANGE, Channel::One, program, 0),
+        )
+    }
+
+    /// Every event as its time and bytes, to compare songs
+    fn bytes(song: &MidiSong) -> Vec<(u64, [u8; 3])> {
+        song.events()
+            .iter()
+            .map(|event| (event.timestamp, util::voice_bytes(&event.event)))
+            .collect()
+    }
+
+    fn assert_notes_paired(song: &MidiSong) {
+        let pairs = song.note_pairs();
+        assert!(
+            pairs.iter().all(|(_, off)| off.is_some()),
+            "a note on has no note off: {:?}",
+            bytes(song)
+        );
+        let offs = song
+            .events()
+            .iter()
+            .filter(|event| util::note_off_key(&event.event).is_some())
+            .count();
+        assert_eq!(offs, pairs.len(), "a note off has no note on");
+    }
+
+    fn song() -> MidiSong {
+        MidiSong::new(vec![
+            control(0, 7, 100),
+            program(0, 3),
+            on(0, Channel::One, 60),
+            control(100, 7, 50),
+            on(500, Channel::One, 62),
+            off(1_000, Channel::One, 62),
+            on(1_500, Channel::One, 64),
+            off(3_000, Channel::One, 60),
+            off(4_000, Channel::One, 64),
+        ])
+    }
+
+    #[test]
+    fn slice_never_leaves_a_note_on_without_its_note_off() {
+        let song = song();
+        for (start, end) in [(0, 4_001), (200, 2_000), (600, 1_600), (1_000, 1_500)] {
+            assert_notes_paired(&song.slice(start, end));
+        }
+
+        let slice = song.slice(400, 2_000);
+        let notes = slice
+            .events()
+            .iter()
+            .filter_map(|event| {
+                let key =
+                    util::note_on_key(&event.event).or_else(|| util::note_off_key(&event.event))?;
+                Some((event.timestamp, key))
+            })
+            .collect::<Vec<_>>();
+        // 60 started before the slice, 64 is ended at the end of the slice
+        assert_eq!(notes, vec![(100, 62), (600, 62), (1_100, 64), (1_600, 64)]);
+    }
+
+    #[test]
+    fn slice_chases_controllers_and_programs_to_zero() {
+        let slice = song().slice(400, 2_000);
+        let at_zero = slice
+            .events()
+            .iter()
+            .filter(|event| event.timestamp == 0)
+            .map(|event| util::voice_bytes(&event.event))
+            .collect::<Vec<_>>();
+        assert!(at_zero.contains(&[util::STATUS_CONTROL_CHANGE, 7, 50]));
+        assert!(at_zero.contains(&[util::STATUS_PROGRAM_CHANGE, 3, 0]));
+        assert!(!at_zero.contains(&[util::STATUS_CONTROL_CHANGE, 7, 100]));
+    }
+
+    #[test]
+    fn slice_cuts_meta_events_and_tempo_map() {
+        let mut song = song();
+        song.add_meta_event(SongMetaEvent::new(100, SongMeta::Marker("a".into())))
+            .add_meta_event(SongMetaEvent::new(700, SongMeta::Marker("b".into())));
+        song.tempo_map_mut()
+            .add_tempo(TempoChange::from_bpm(0, 100.));
+        song.tempo_map_mut()
+            .add_tempo(TempoChange::from_bpm(300, 140.));
+
+        let slice = song.slice(400, 2_000);
+        assert_eq!(
+            slice.meta_events(),
+            &[SongMetaEvent::new(300, SongMeta::Marker("b".into()))]
+        );
+        assert_eq!(slice.tempo_map().bpm_at(0).round(), 140.);
+    }
+
+    #[test]
+    fn shift_earlier_matches_slice() {
+        let song = song();
+        let mut shifted = song.clone();
+        shifted.shift(-400);
+        assert_eq!(bytes(&shifted), bytes(&song.slice(400, u64::MAX)));
+        assert_eq!(shifted.id(), song.id());
+        assert_notes_paired(&shifted);
+    }
+
+    #[test]
+    fn shift_later_moves_everything() {
+        let mut song = song();
+        song.tempo_map_mut()
+            .add_tempo(TempoChange::from_bpm(1_000, 90.));
+        song.add_meta_event(SongMetaEvent::new(100, SongMeta::Lyric("la".into())));
+        let before = bytes(&song);
+
+        song.shift(250);
+        let after = bytes(&song);
+        assert!(
+            before
+                .iter()
+                .zip(&after)
+                .all(|(before, after)| after.0 == before.0 + 250 && after.1 == before.1)
+        );
+        assert_eq!(song.meta_events()[0].timestamp, 350);
+        assert_eq!(song.tempo_map().tempos()[1].timestamp, 1_250);
+        assert_eq!(song.tempo_map().bpm_at(0).round(), 120.);
+    }
+
+    #[test]
+    fn shift_later_saturates() {
+        let mut song = song();
+        song.shift(i64::MAX).shift(i64::MAX).shift(i64::MAX);
+        assert!(
+            song.events()
+                .iter()
+                .all(|event| event.timestamp == u64::MAX)
+        );
+    }
+
+    #[test]
+    fn concat_places_the_other_song_after_the_last_event() {
+        let mut first = MidiSong::new(vec![on(0, Channel::One, 60), off(1_000, Channel::One, 60)]);
+        let second = MidiSong::new(vec![on(0, Channel::Two, 64), off(500, Channel::Two, 64)]);
+        first.concat(&second);
+        assert_eq!(
+            bytes(&first)
+                .iter()
+                .map(|(time, _)| *time)
+                .collect::<Vec<_>>(),
+            vec![0, 1_000, 1_000, 1_500]
+        );
+        assert_notes_paired(&first);
+    }
+
+    #[test]
+    fn concat_ends_stuck_notes_on_their_track() {
+        let mut first = MidiSong::with_tracks(
+            vec![
+                on(0, Channel::One, 60),
+                on(0, Channel::Two, 62),
+                off(800, Channel::Two, 62),
+            ],
+            vec![0, 2, 2],
+        );
+        let second = MidiSong::new(vec![on(0, Channel::One, 64), off(500, Channel::One, 64)]);
+        first.concat_at(&second, 1_000);
+        assert_notes_paired(&first);
+
+        let ended = (0..first.events().len())
+            .find(|index| {
+                let event = &first.events()[*index];
+                event.timestamp == 1_000 && util::note_off_key(&event.event) == Some(60)
+            })
+            .unwrap();
+        assert_eq!(first.event_track(ended), 0);
+        // the other song's events keep their own track
+        assert_eq!(first.event_track(first.events().len() - 1), 0);
+
+        let mut stuck_on_two = MidiSong::with_tracks(vec![on(0, Channel::One, 60)], vec![3]);
+        stuck_on_two.concat_at(&second, 1_000);
+        let ended = first_note_off(&stuck_on_two, 60);
+        assert_eq!(stuck_on_two.event_track(ended), 3);
+    }
+
+    fn first_note_off(song: &MidiSong, key: u8) -> usize {
+        song.events()
+            .iter()
+            .position(|event| util::note_off_key(&event.event) == Some(key))
+            .unwrap()
+    }
+
+    #[test]
+    fn merge_sorts_events_and_numbers_tracks_after_this_song() {
+        let mut first = MidiSong::with_tracks(
+            vec![on(0, Channel::One, 60), off(1_000, Channel::One, 60)],
+            vec![1, 1],
+        );
+        let second = MidiSong::with_tracks(
+            vec![on(500, Channel::Two, 64), off(1_500, Channel::Two, 64)],
+            vec![0, 0],
+        );
+        first.merge(&second);
+        assert_eq!(
+            bytes(&first)
+                .iter()
+                .map(|(time, _)| *time)
+                .collect::<Vec<_>>(),
+            vec![0, 500, 1_000, 1_500]
+        );
+        assert_eq!(first.track_count(), 3);
+        assert_eq!(first.event_track(1), 2);
+        assert_eq!(first.event_track(2), 1);
+    }
+
+    #[test]
+    fn transpose_drops_keys_out_of_range_and_skips_drums() {
+        let mut song = MidiSong::with_tracks(
+            vec![
+                on(0, Channel::One, 120),
+                off(100, Channel::One, 120),
+                on(0, Channel::One, 60),
+                off(100, Channel::One, 60),
+                on(0, Channel::Ten, 36),
+                off(100, Channel::Ten, 36),
+                control(0, 7, 100),
+            ],
+            vec![1, 1, 2, 2, 0, 0, 0],
+        );
+        song.transpose(12);
+        let keys = bytes(&song)
+            .iter()
+            .map(|(_, [_, key, _])| *key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![72, 72, 36, 36, 7]);
+        assert_eq!(song.event_track(0), 2);
+        assert_eq!(song.event_track(2), 0);
+        assert_notes_paired(&song);
+
+        song.transpose(-128);
+        let keys = bytes(&song)
+            .iter()
+            .map(|(_, [_, key, _])| *key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![36, 36, 7]);
+    }
+
+    #[test]
+    fn remap_channels_moves_every_event_of_a_channel() {
+        let mut song = MidiSong::new(vec![
+            on(0, Channel::One, 60),
+            on(0, Channel::Two, 62),
+            control(0, 7, 100),
+        ]);
+        song.remap_channels(|channel| match channel {
+            Channel::One => Channel::Three,
+            other => other,
+        });
+        let channels = song
+            .events()
+            .iter()
+            .map(|event| event.event.channel())
+            .collect::<Vec<_>>();
+        assert_eq!(channels, vec![Channel::Three, Channel::Two, Channel::Three]);
+        assert_eq!(
+            util::voice_bytes(&song.events()[2].event),
+            [util::STATUS_CONTROL_CHANGE | 2, 7, 100]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+
+/// Text that is attached to a point in a song
+#[derive(Clone, Debug, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SongMeta {
+    /// Any text, such as comments
+    Text(String),
+    /// A syllable or word to be sung at this time
+    Lyric(String),
+    /// A marker, such as a rehearsal letter or section name
+    Marker(String),
+    /// A cue for something happening outside of the music, such as a sound effect
+    CuePoint(String),
+}
+
+impl SongMeta {
+    /// The text of this meta event
+    pub fn text(&self) -> &str {
+        match self {
+            SongMeta::Text(text)
+            | SongMeta::Lyric(text)
+            | SongMeta::Marker(text)
+            | SongMeta::CuePoint(text) => text,
+        }
+    }
+}
+
+/// A [`SongMeta`] at a time in microseconds
+#[derive(Clone, Debug, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SongMetaEvent {
+    /// When this happens, in microseconds
+    pub timestamp: u64,
+    /// What happens
+    pub meta: SongMeta,
+}
+
+impl SongMetaEvent {
+    /// Create a new timed meta event
+    pub fn new(timestamp: u64, meta: SongMeta) -> Self {
+        Self { timestamp, meta }
+    }
+}
@@ -5,6 +5,8 @@ Components to make songs programatically
 use bevy::{asset::uuid::Uuid, prelude::*};
 use midix::prelude::*;
 
+use crate::assets::SmfExportSettings;
+
 pub mod simple;
 pub use simple::*;
 
@@ -14,6 +16,34 @@ pub use song_writer::*;
 mod builder;
 pub use builder::*;
 
+mod tempo;
+pub use tempo::*;
+
+mod meta;
+pub use meta::*;
+
+mod automation;
+pub use automation::*;
+
+mod transform;
+pub use transform::*;
+
+mod edit;
+
+mod notes;
+pub use notes::*;
+
+mod validate;
+pub use validate::*;
+
+pub mod notation;
+pub use notation::{NotationError, NotationErrorKind};
+
+#[cfg(feature = "serde")]
+mod document;
+#[cfg(feature = "serde")]
+pub use document::*;
+
 /// The identifier of a certain midi song
 #[derive(Clone, Copy, Hash, Eq, PartialEq, Debug, Reflect)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -25,12 +55,21 @@ impl Default for SongId {
     }
 }
 
-/// A set of commands
-#[derive(Clone, Debug, Reflect, Default)]
+/// A set of commands.
+///
+/// MIDI files can be loaded as songs with the [`MidiSongLoader`](crate::assets::MidiSongLoader).
+#[derive(Asset, Clone, Debug, Reflect, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MidiSong {
     pub(crate) id: SongId,
     pub(crate) events: Vec<Timed<ChannelVoiceMessage>>,
+    /// The original track of each event, in the same order. Empty if not known.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) tracks: Vec<u16>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) tempo_map: TempoMap,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) meta: Vec<SongMetaEvent>,
     /// If true, this will loop when sent to the synthesizer.
     pub looped: bool,
     /// If true, then this song starts paused.
@@ -55,14 +94,98 @@ impl MidiSong {
         Self {
             id: SongId::default(),
             events,
+            tracks: Vec::new(),
+            tempo_map: TempoMap::default(),
+            meta: Vec::new(),
             looped: false,
             paused: false,
         }
     }
-    /// Get a mutable reference to the events
+    /// Create a set of commands, with the original track of each event.
+    ///
+    /// `tracks` must have one entry per event, otherwise every event is on track 0.
+    pub fn with_tracks(events: Vec<Timed<ChannelVoiceMessage>>, tracks: Vec<u16>) -> Self {
+        let mut song = Self::new(events);
+        if tracks.len() == song.events.len() {
+            song.tracks = tracks;
+        }
+        song
+    }
+    /// The original track of the event at `index`.
+    ///
+    /// This is 0 if the song's tracks are not known, such as after
+    /// [`MidiSong::events_mut`] added or removed events.
+    pub fn event_track(&self, index: usize) -> u16 {
+        if self.tracks.len() != self.events.len() {
+            return 0;
+        }
+        self.tracks.get(index).copied().unwrap_or_default()
+    }
+    /// The number of tracks in this song. This is at least 1.
+    pub fn track_count(&self) -> u16 {
+        if self.tracks.len() != self.events.len() {
+            return 1;
+        }
+        self.tracks.iter().max().map_or(1, |track| track + 1)
+    }
+    /// Sort the events by timestamp, keeping the track of each event
+    pub(crate) fn sort_events(&mut self) {
+        if self.tracks.len() == self.events.len() {
+            let mut events = core::mem::take(&mut self.events)
+                .into_iter()
+                .zip(core::mem::take(&mut self.tracks))
+                .collect::<Vec<_>>();
+            events.sort_by_key(|(event, _)| event.timestamp);
+            (self.events, self.tracks) = events.into_iter().unzip();
+        } else {
+            self.tracks.clear();
+            self.events.sort_by_key(|event| event.timestamp);
+        }
+    }
+    /// Get a mutable reference to the events.
+    ///
+    /// The tracks of the events are forgotten if the number of events changes.
     pub fn events_mut(&mut self) -> &mut Vec<Timed<ChannelVoiceMessage>> {
         &mut self.events
     }
+    /// The tempo and time signature changes of this song.
+    ///
+    /// Event timestamps are already in microseconds. This is used to convert
+    /// them to beats and bars, such as for a [`MidiClock`](crate::output::MidiClock).
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+    /// Get a mutable reference to the tempo map
+    pub fn tempo_map_mut(&mut self) -> &mut TempoMap {
+        &mut self.tempo_map
+    }
+    /// The channels used by this song, in order
+    pub fn channels(&self) -> Vec<Channel> {
+        let mut channels = self
+            .events
+            .iter()
+            .map(|event| event.event.channel())
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|channel| crate::util::channel_index(*channel));
+        channels.dedup();
+        channels
+    }
+
+    /// The lyrics, markers, cue points and text of this song.
+    ///
+    /// Not guaranteed to be sorted.
+    pub fn meta_events(&self) -> &[SongMetaEvent] {
+        &self.meta
+    }
+    /// Get a mutable reference to the meta events
+    pub fn meta_events_mut(&mut self) -> &mut Vec<SongMetaEvent> {
+        &mut self.meta
+    }
+    /// Add a meta event to the song
+    pub fn add_meta_event(&mut self, event: SongMetaEvent) -> &mut Self {
+        self.meta.push(event);
+        self
+    }
     /// Start the song paused
     pub fn set_paused(mut self) -> Self {
         self.paused = true;
@@ -75,17 +198,38 @@ impl MidiSong {
         self
     }
     /// set's the speed of the commands. Not absolute.
+    ///
+    /// This rewrites every timestamp, losing precision each time it's used.
+    /// Meta events and the tempo map are moved too, and every tempo is sped up,
+    /// so bars and beats stay on the same events.
+    /// To change the speed of a playing song, use
+    /// [`SongPlayer::set_rate`](crate::playback::SongPlayer::set_rate) instead.
     pub fn set_speed(mut self, speed: f64) -> Self {
         let speed = 1. / speed;
         self.events
             .iter_mut()
             .for_each(|cmd| cmd.timestamp = (cmd.timestamp as f64 * speed) as u64);
+        self.meta
+            .iter_mut()
+            .for_each(|meta| meta.timestamp = (meta.timestamp as f64 * speed) as u64);
+        self.tempo_map = self.tempo_map.scaled(speed);
         self
     }
 
+    /// The timestamp of the last event or meta event in the song, in microseconds
+    pub fn duration(&self) -> u64 {
+        self.events
+            .iter()
+            .map(|event| event.timestamp)
+            .chain(self.meta.iter().map(|event| event.timestamp))
+            .max()
+            .unwrap_or_default()
+    }
+
     /// Returns the all timed midi events for the song.
     ///
-    /// Not guaranteed to be sorted.
+    /// Not guaranteed to be sorted. [`MidiSong::validate`] finds problems with the events,
+    /// and [`MidiSong::normalize`] sorts and fixes them.
     pub fn events(&self) -> &[Timed<ChannelVoiceMessage>] {
         &self.events
     }
@@ -111,4 +255,24 @@ impl SongWriter for MidiSong {
     fn paused(&self) -> bool {
         self.paused
     }
+    fn to_smf(&self, settings: &SmfExportSettings) -> Vec<u8> {
+        crate::assets::write_song(self, settings)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::MidiSong;
+
+    #[test]
+    fn songs_saved_before_tracks_tempo_and_meta_still_load() {
+        let song: MidiSong = ron::de::from_str(
+            r#"(id: ("67e55044-10b1-426f-9247-bb680e5fe0c8"), events: [], looped: true, paused: false)"#,
+        )
+        .unwrap();
+        assert!(song.looped);
+        assert!(song.tracks.is_empty());
+        assert!(song.meta.is_empty());
+        assert!(song.tempo_map.is_empty());
+    }
 }
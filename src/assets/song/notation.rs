@@ -0,0 +1,555 @@
+#![doc = r#"
+A compact, tracker-like text notation for melodies.
+
+```text
+t120 o4 l8 c d e f g4 g4 | a a a a g2 | [c e g]1
+```
+
+| Command      | Meaning                                                               |
+|--------------|-----------------------------------------------------------------------|
+| `c` to `b`   | A note in the current octave. `+` or `#` sharpens it, `-` flattens it |
+| `r`          | A rest                                                                |
+| `[c e g]`    | A chord, with its length after the `]`                                |
+| `&`          | Ties the next note to the previous one, which must have the same key  |
+| `o4`         | Sets the octave. `o4 c` is middle C (60)                              |
+| `>` / `<`    | Moves up or down an octave                                            |
+| `l8`         | Sets the length of notes and rests that don't have one                |
+| `t120`       | Sets the tempo, in beats (quarter notes) per minute                   |
+| `v100`       | Sets the velocity of the following notes, from 1 to 127               |
+| `@0`         | Changes the program (instrument)                                      |
+| `\|`         | A bar line, which is ignored                                          |
+
+Lengths are note values: `4` is a quarter note, `8` an eighth, `12` an eighth triplet.
+Each `.` after a length adds half of the previous value. Whitespace is optional.
+
+This is the only text notation that is read. ABC notation is not supported.
+"#]
+
+use core::ops::RangeInclusive;
+
+use midix::prelude::*;
+use thiserror::Error;
+
+use super::{BarBeatTick, MidiSong, MusicalSongBuilder, SimpleSection};
+use crate::util;
+
+/// Ticks per quarter note used when converting notation into a song
+const NOTATION_PPQN: u16 = 960;
+
+/// The kinds of errors in [text notation](self)
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum NotationErrorKind {
+    /// A character that doesn't start a command
+    #[error("unexpected {0:?}")]
+    UnexpectedCharacter(char),
+    /// The text ended in the middle of a command
+    #[error("unexpected end of text")]
+    UnexpectedEnd,
+    /// A command needs a number
+    #[error("expected a number")]
+    ExpectedNumber,
+    /// A number is out of range for its command
+    #[error("{0} is out of range")]
+    OutOfRange(u32),
+    /// A note is outside the range of MIDI keys
+    #[error("note is outside the range of MIDI keys")]
+    NoteOutOfRange,
+    /// A chord has no closing `]`
+    #[error("unclosed chord")]
+    UnclosedChord,
+    /// A tied note doesn't have the same key as the note before the `&`
+    #[error("tied note doesn't match the previous note")]
+    TieMismatch,
+}
+
+/// An error in [text notation](self), at a column of the text
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("column {column}: {kind}")]
+pub struct NotationError {
+    /// The column of the error, counted in characters from 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: NotationErrorKind,
+}
+
+/// A note read from text notation, in beats from the start
+#[derive(Clone, Copy, Debug)]
+struct ParsedNote {
+    position: f64,
+    length: f64,
+    key: u8,
+    velocity: u8,
+}
+
+/// Everything read from text notation
+#[derive(Default)]
+struct Parsed {
+    notes: Vec<ParsedNote>,
+    /// Tempo changes as positions in beats and beats per minute
+    tempos: Vec<(f64, f64)>,
+    /// Program changes as positions in beats and programs
+    programs: Vec<(f64, u8)>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    offset: usize,
+    octave: i16,
+    length: f64,
+    velocity: u8,
+    position: f64,
+    /// Set by `&`, with the column it was found at
+    tie: Option<usize>,
+    parsed: Parsed,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            offset: 0,
+            octave: 4,
+            length: 1.,
+            velocity: 100,
+            position: 0.,
+            tie: None,
+            parsed: Parsed::default(),
+        }
+    }
+
+    fn error(&self, column: usize, kind: NotationErrorKind) -> NotationError {
+        NotationError { column, kind }
+    }
+
+    /// The column of the next character
+    fn column(&self) -> usize {
+        self.offset + 1
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace() || c == '|') {
+            self.offset += 1;
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.offset;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.offset += 1;
+        }
+        if start == self.offset {
+            return None;
+        }
+        let digits = self.chars[start..self.offset].iter().collect::<String>();
+        Some(digits.parse().unwrap_or(u32::MAX))
+    }
+
+    fn required_number(&mut self, range: RangeInclusive<u32>) -> Result<u32, NotationError> {
+        let column = self.column();
+        let number = self
+            .number()
+            .ok_or_else(|| self.error(column, NotationErrorKind::ExpectedNumber))?;
+        if !range.contains(&number) {
+            return Err(self.error(column, NotationErrorKind::OutOfRange(number)));
+        }
+        Ok(number)
+    }
+
+    /// An optional note value and dots, in beats
+    fn length(&mut self) -> Result<Option<f64>, NotationError> {
+        let column = self.column();
+        let Some(value) = self.number() else {
+            return Ok(None);
+        };
+        if value == 0 || value > 256 {
+            return Err(self.error(column, NotationErrorKind::OutOfRange(value)));
+        }
+        let mut length = 4. / value as f64;
+        let mut dot = length;
+        while self.peek() == Some('.') {
+            self.offset += 1;
+            dot /= 2.;
+            length += dot;
+        }
+        Ok(Some(length))
+    }
+
+    /// A note name with its accidentals, as a key in the current octave
+    fn pitch(&mut self) -> Result<u8, NotationError> {
+        let column = self.column();
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error(column, NotationErrorKind::UnexpectedEnd))?;
+        let pitch_class: i16 = match c.to_ascii_lowercase() {
+            'c' => 0,
+            'd' => 2,
+            'e' => 4,
+            'f' => 5,
+            'g' => 7,
+            'a' => 9,
+            'b' => 11,
+            _ => return Err(self.error(column, NotationErrorKind::UnexpectedCharacter(c))),
+        };
+        self.offset += 1;
+        let mut shift = 0;
+        while let Some(accidental) = self.peek() {
+            match accidental {
+                '+' | '#' => shift += 1,
+                '-' => shift -= 1,
+                _ => break,
+            }
+            self.offset += 1;
+        }
+        u8::try_from((self.octave + 1) * 12 + pitch_class + shift)
+            .ok()
+            .filter(|key| *key < 128)
+            .ok_or_else(|| self.error(column, NotationErrorKind::NoteOutOfRange))
+    }
+
+    /// Adds notes that start now, or extends the notes they are tied to
+    fn add_notes(&mut self, keys: &[(usize, u8)], length: f64) -> Result<(), NotationError> {
+        let tie = self.tie.take();
+        for (column, key) in keys {
+            if tie.is_some() {
+                let position = self.position;
+                let tied = self.parsed.notes.iter_mut().rev().find(|note| {
+                    note.key == *key && (note.position + note.length - position).abs() < 1e-9
+                });
+                let Some(tied) = tied else {
+                    return Err(NotationError {
+                        column: *column,
+                        kind: NotationErrorKind::TieMismatch,
+                    });
+                };
+                tied.length += length;
+                continue;
+            }
+            self.parsed.notes.push(ParsedNote {
+                position: self.position,
+                length,
+                key: *key,
+                velocity: self.velocity,
+            });
+        }
+        self.position += length;
+        Ok(())
+    }
+
+    fn parse(mut self) -> Result<Parsed, NotationError> {
+        loop {
+            self.skip_whitespace();
+            let column = self.column();
+            let Some(c) = self.peek() else {
+                break;
+            };
+            match c.to_ascii_lowercase() {
+                'a'..='g' => {
+                    let key = self.pitch()?;
+                    let length = self.length()?.unwrap_or(self.length);
+                    self.add_notes(&[(column, key)], length)?;
+                }
+                'r' => {
+                    self.offset += 1;
+                    self.position += self.length()?.unwrap_or(self.length);
+                }
+                '[' => {
+                    self.offset += 1;
+                    let mut keys = Vec::new();
+                    loop {
+                        self.skip_whitespace();
+                        let note_column = self.column();
+                        match self.peek() {
+                            Some(']') => {
+                                self.offset += 1;
+                                break;
+                            }
+                            Some('>') => {
+                                self.offset += 1;
+                                self.octave += 1;
+                            }
+                            Some('<') => {
+                                self.offset += 1;
+                                self.octave -= 1;
+                            }
+                            Some(_) => keys.push((note_column, self.pitch()?)),
+                            None => {
+                                return Err(self.error(column, NotationErrorKind::UnclosedChord));
+                            }
+                        }
+                    }
+                    let length = self.length()?.unwrap_or(self.length);
+                    self.add_notes(&keys, length)?;
+                }
+                '&' => {
+                    self.offset += 1;
+                    self.tie = Some(column);
+                }
+                'o' => {
+                    self.offset += 1;
+                    self.octave = self.required_number(0..=9)? as i16;
+                }
+                '>' => {
+                    self.offset += 1;
+                    self.octave += 1;
+                }
+                '<' => {
+                    self.offset += 1;
+                    self.octave -= 1;
+                }
+                'l' => {
+                    self.offset += 1;
+                    self.length = self
+                        .length()?
+                        .ok_or_else(|| self.error(column + 1, NotationErrorKind::ExpectedNumber))?;
+                }
+                't' => {
+                    self.offset += 1;
+                    let bpm = self.required_number(1..=1000)?;
+                    self.parsed.tempos.push((self.position, bpm as f64));
+                }
+                'v' => {
+                    self.offset += 1;
+                    // a note on with a velocity of zero would be a note off
+                    self.velocity = self.required_number(1..=127)? as u8;
+                }
+                '@' => {
+                    self.offset += 1;
+                    let program = self.required_number(0..=127)? as u8;
+                    self.parsed.programs.push((self.position, program));
+                }
+                _ => {
+                    return Err(self.error(column, NotationErrorKind::UnexpectedCharacter(c)));
+                }
+            }
+        }
+        if let Some(column) = self.tie {
+            return Err(self.error(column, NotationErrorKind::TieMismatch));
+        }
+        Ok(self.parsed)
+    }
+}
+
+impl MidiSong {
+    /// Reads a melody written in [text notation](crate::assets::notation) into a song,
+    /// played on one channel.
+    ///
+    /// ```ignore
+    /// let jingle = MidiSong::from_notation("t140 o5 l16 e g > c8. < g c4", Channel::One)?;
+    /// ```
+    ///
+    /// # Errors
+    /// If the text can't be read. The error has the column of the problem.
+    pub fn from_notation(text: &str, channel: Channel) -> Result<Self, NotationError> {
+        let parsed = Parser::new(text).parse()?;
+        let ppqn = NOTATION_PPQN as f64;
+        let tick = |beats: f64| (beats * ppqn).round() as u64;
+        let at = |beats: f64| BarBeatTick::new(1, 1, tick(beats) as u32);
+
+        let mut builder = MusicalSongBuilder::new(NOTATION_PPQN);
+        for (position, bpm) in &parsed.tempos {
+            builder.tempo(at(*position), *bpm);
+        }
+        for (position, program) in &parsed.programs {
+            if let Some(message) =
+                util::voice_message(util::STATUS_PROGRAM_CHANGE, channel, *program, 0)
+            {
+                builder.add_at_tick(tick(*position), message);
+            }
+        }
+        for note in &parsed.notes {
+            let on = util::voice_message(util::STATUS_NOTE_ON, channel, note.key, note.velocity);
+            let off = util::voice_message(util::STATUS_NOTE_OFF, channel, note.key, 0);
+            if let (Some(on), Some(off)) = (on, off) {
+                builder
+                    .add_at_tick(tick(note.position), on)
+                    .add_at_tick(tick(note.position + note.length), off);
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+impl SimpleSection {
+    /// Reads a melody written in [text notation](crate::assets::notation) into a section.
+    ///
+    /// A beat of the section is a quarter note. Tempo, velocity and program commands are
+    /// checked but have no effect, as those are set by the song the section is played in.
+    ///
+    /// # Errors
+    /// If the text can't be read. The error has the column of the problem.
+    pub fn from_notation(text: &str) -> Result<Self, NotationError> {
+        let parsed = Parser::new(text).parse()?;
+        let mut section = SimpleSection::default();
+        for note in &parsed.notes {
+            let Ok(key) = Note::from_databyte(note.key) else {
+                continue;
+            };
+            section.at(note.position + 1.).play_for(key, note.length);
+        }
+        Ok(section)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::{NotationError, NotationErrorKind};
+    use crate::{
+        assets::{MidiSong, SimpleSection},
+        util,
+    };
+
+    /// The start and length in ticks of 960 per beat, and the key of each note of a section
+    fn notes(text: &str) -> Vec<(u64, u64, u8)> {
+        let ticks = |beats: f64| (beats * 960.).round() as u64;
+        SimpleSection::from_notation(text)
+            .unwrap()
+            .notes()
+            .iter()
+            .map(|note| {
+                (
+                    ticks(note.position - 1.),
+                    ticks(note.length),
+                    note.note.byte(),
+                )
+            })
+            .collect()
+    }
+
+    fn error(text: &str) -> NotationError {
+        SimpleSection::from_notation(text).unwrap_err()
+    }
+
+    fn error_at(column: usize, kind: NotationErrorKind) -> NotationError {
+        NotationError { column, kind }
+    }
+
+    #[test]
+    fn notes_follow_each_other() {
+        assert_eq!(
+            notes("o4 l4 c d e- | f+ r g#8 b"),
+            vec![
+                (0, 960, 60),
+                (960, 960, 62),
+                (1920, 960, 63),
+                (2880, 960, 66),
+                (4800, 480, 68),
+                (5280, 960, 71),
+            ]
+        );
+    }
+
+    #[test]
+    fn dotted_and_triplet_lengths() {
+        assert_eq!(
+            notes("c4. d8 e12 e12 e12 f2.."),
+            vec![
+                (0, 1440, 60),
+                (1440, 480, 62),
+                (1920, 320, 64),
+                (2240, 320, 64),
+                (2560, 320, 64),
+                (2880, 3360, 65),
+            ]
+        );
+        assert_eq!(error("c0"), error_at(2, NotationErrorKind::OutOfRange(0)));
+        assert_eq!(error("l c"), error_at(2, NotationErrorKind::ExpectedNumber));
+    }
+
+    #[test]
+    fn ties_extend_notes() {
+        assert_eq!(notes("c4 & c8 d"), vec![(0, 1440, 60), (1440, 960, 62)]);
+        assert_eq!(notes("[c e]4 & [c e]4"), vec![(0, 1920, 60), (0, 1920, 64)]);
+        assert_eq!(
+            error("c4 & d4"),
+            error_at(6, NotationErrorKind::TieMismatch)
+        );
+        assert_eq!(error("c4 &"), error_at(4, NotationErrorKind::TieMismatch));
+    }
+
+    #[test]
+    fn chords_start_together() {
+        assert_eq!(
+            notes("[c e g]2 a"),
+            vec![(0, 1920, 60), (0, 1920, 64), (0, 1920, 67), (1920, 960, 69)]
+        );
+        // octave changes inside a chord last after it
+        assert_eq!(
+            notes("[c > c] c"),
+            vec![(0, 960, 60), (0, 960, 72), (960, 960, 72)]
+        );
+        assert_eq!(
+            error("c [c e"),
+            error_at(3, NotationErrorKind::UnclosedChord)
+        );
+    }
+
+    #[test]
+    fn octaves_are_bounded() {
+        assert_eq!(notes("o9 g o0 < c"), vec![(0, 960, 127), (960, 960, 0)]);
+        assert_eq!(error("o-1"), error_at(2, NotationErrorKind::ExpectedNumber));
+        assert_eq!(
+            error("o9 g+"),
+            error_at(4, NotationErrorKind::NoteOutOfRange)
+        );
+        assert_eq!(
+            error("o0 < c-"),
+            error_at(6, NotationErrorKind::NoteOutOfRange)
+        );
+        assert_eq!(
+            error("<<<<<<c"),
+            error_at(7, NotationErrorKind::NoteOutOfRange)
+        );
+        assert_eq!(error("o10"), error_at(2, NotationErrorKind::OutOfRange(10)));
+    }
+
+    #[test]
+    fn errors_report_columns_in_characters() {
+        assert_eq!(
+            error("c d x"),
+            error_at(5, NotationErrorKind::UnexpectedCharacter('x'))
+        );
+        assert_eq!(
+            error("é c"),
+            error_at(1, NotationErrorKind::UnexpectedCharacter('é'))
+        );
+        assert_eq!(
+            error("c é"),
+            error_at(3, NotationErrorKind::UnexpectedCharacter('é'))
+        );
+        assert_eq!(error("v0"), error_at(2, NotationErrorKind::OutOfRange(0)));
+        assert_eq!(error("c é").to_string(), "column 3: unexpected 'é'");
+    }
+
+    #[test]
+    fn songs_use_tempo_velocity_and_program() {
+        let song = MidiSong::from_notation("t60 v90 @5 c4 d", Channel::Two).unwrap();
+        let program = util::voice_message(util::STATUS_PROGRAM_CHANGE, Channel::Two, 5, 0).unwrap();
+        assert_eq!(song.events[0].event, program);
+        let notes = song
+            .notes()
+            .iter()
+            .map(|note| {
+                (
+                    note.start,
+                    note.duration,
+                    note.channel,
+                    note.key,
+                    note.velocity,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            vec![
+                (0, 1_000_000, Channel::Two, 60, 90),
+                (1_000_000, 1_000_000, Channel::Two, 62, 90)
+            ]
+        );
+    }
+}
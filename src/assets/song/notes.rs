@@ -0,0 +1,269 @@
+use bevy::platform::collections::HashMap;
+use midix::prelude::*;
+
+use super::MidiSong;
+use crate::util;
+
+/// A note of a [`MidiSong`], with its start and length, as in a piano roll.
+///
+/// Get the notes of a song with [`MidiSong::notes`], and turn notes back into events with
+/// [`MidiSong::from_notes`] or [`MidiSong::set_notes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SongNote {
+    /// When the note starts, in microseconds
+    pub start: u64,
+    /// How long the note is held, in microseconds
+    pub duration: u64,
+    /// The channel the note is played on
+    pub channel: Channel,
+    /// The key, from 0 to 127. 60 is middle C.
+    pub key: u8,
+    /// The velocity of the note on, from 1 to 127
+    pub velocity: u8,
+    /// The velocity of the note off, from 0 to 127
+    pub off_velocity: u8,
+    /// The original track of the note
+    pub track: u16,
+}
+
+impl SongNote {
+    /// When the note ends, in microseconds
+    pub fn end(&self) -> u64 {
+        self.start + self.duration
+    }
+
+    /// The note on and note off of this note
+    fn messages(&self) -> Option<(ChannelVoiceMessage, ChannelVoiceMessage)> {
+        let on = util::voice_message(
+            util::STATUS_NOTE_ON,
+            self.channel,
+            self.key,
+            self.velocity.max(1),
+        )?;
+        let off = util::voice_message(
+            util::STATUS_NOTE_OFF,
+            self.channel,
+            self.key,
+            self.off_velocity,
+        )?;
+        Some((on, off))
+    }
+}
+
+impl MidiSong {
+    /// The index of each note on and of the note off that ends it, if any.
+    ///
+    /// Notes with the same key on the same channel are paired first in, first out.
+    pub(crate) fn note_pairs(&self) -> Vec<(usize, Option<usize>)> {
+        let mut order = (0..self.events.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| self.events[*index].timestamp);
+
+        let mut pairs = Vec::new();
+        let mut open = HashMap::<(usize, u8), Vec<usize>>::new();
+        for index in order {
+            let message = &self.events[index].event;
+            let channel = util::channel_index(message.channel());
+            if let Some(key) = util::note_on_key(message) {
+                open.entry((channel, key)).or_default().push(pairs.len());
+                pairs.push((index, None));
+            } else if let Some(key) = util::note_off_key(message) {
+                let waiting = open.entry((channel, key)).or_default();
+                if !waiting.is_empty() {
+                    let pair = waiting.remove(0);
+                    pairs[pair].1 = Some(index);
+                }
+            }
+        }
+        pairs
+    }
+
+    /// The notes of this song, sorted by start, then channel, then key.
+    ///
+    /// Each note on is ended by the first note off with the same channel and key that
+    /// follows it, so overlapping notes of the same key end in the order they started.
+    /// Notes that are never ended last until the end of the song, and note offs
+    /// without a note on are ignored.
+    ///
+    /// ```ignore
+    /// for note in song.notes() {
+    ///     draw_rect(note.start, note.duration, note.key);
+    /// }
+    /// ```
+    pub fn notes(&self) -> Vec<SongNote> {
+        let end = self.duration();
+        let mut notes = self
+            .note_pairs()
+            .into_iter()
+            .map(|(on, off)| {
+                let on_event = &self.events[on];
+                let [_, key, velocity] = util::voice_bytes(&on_event.event);
+                let (note_end, off_velocity) = match off {
+                    Some(off) => {
+                        let [status, _, velocity] = util::voice_bytes(&self.events[off].event);
+                        // a note on with a velocity of zero has no release velocity
+                        let velocity = if status & 0xF0 == util::STATUS_NOTE_OFF {
+                            velocity
+                        } else {
+                            0
+                        };
+                        (self.events[off].timestamp, velocity)
+                    }
+                    None => (end, 0),
+                };
+                SongNote {
+                    start: on_event.timestamp,
+                    duration: note_end.saturating_sub(on_event.timestamp),
+                    channel: on_event.event.channel(),
+                    key,
+                    velocity,
+                    off_velocity,
+                    track: self.event_track(on),
+                }
+            })
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|note| (note.start, util::channel_index(note.channel), note.key));
+        notes
+    }
+
+    /// Create a song that plays these notes
+    pub fn from_notes(notes: &[SongNote]) -> Self {
+        let mut song = MidiSong::default();
+        song.set_notes(notes);
+        song
+    }
+
+    /// Replace the notes of this song. Other events, the tempo map and meta events are kept.
+    ///
+    /// When one note ends where another with the same key starts, the note off comes first,
+    /// so [`MidiSong::notes`] gives back the same notes. Notes of the same key that overlap
+    /// on one channel can't be told apart in MIDI, and come back ending in the order they started.
+    pub fn set_notes(&mut self, notes: &[SongNote]) -> &mut Self {
+        let mut events = Vec::new();
+        let mut tracks = Vec::new();
+        // ordered by time, then note offs, note ons and the offs of notes without length
+        let mut order = Vec::new();
+        for (index, event) in self.events.iter().enumerate() {
+            if util::note_on_key(&event.event).is_none()
+                && util::note_off_key(&event.event).is_none()
+            {
+                order.push((event.timestamp, 1, *event, self.event_track(index)));
+            }
+        }
+        for note in notes {
+            let Some((on, off)) = note.messages() else {
+                continue;
+            };
+            let off_rank = if note.duration == 0 { 2 } else { 0 };
+            order.push((note.start, 1, Timed::new(note.start, on), note.track));
+            order.push((
+                note.end(),
+                off_rank,
+                Timed::new(note.end(), off),
+                note.track,
+            ));
+        }
+        order.sort_by_key(|(timestamp, rank, ..)| (*timestamp, *rank));
+        for (_, _, event, track) in order {
+            events.push(event);
+            tracks.push(track);
+        }
+        self.events = events;
+        self.tracks = tracks;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::SongNote;
+    use crate::{assets::MidiSong, util};
+
+    fn message(status: u8, key: u8, velocity: u8) -> ChannelVoiceMessage {
+        util::voice_message(status, Channel::One, key, velocity).unwrap()
+    }
+
+    fn on(timestamp: u64, key: u8, velocity: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_ON, key, velocity))
+    }
+
+    fn off(timestamp: u64, key: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_OFF, key, 0))
+    }
+
+    fn control(timestamp: u64) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_CONTROL_CHANGE, 7, 90))
+    }
+
+    fn note(start: u64, duration: u64, channel: Channel, key: u8) -> SongNote {
+        SongNote {
+            start,
+            duration,
+            channel,
+            key,
+            velocity: 100,
+            off_velocity: 0,
+            track: 0,
+        }
+    }
+
+    /// The start, length, key and velocity of each note
+    fn spans(song: &MidiSong) -> Vec<(u64, u64, u8, u8)> {
+        song.notes()
+            .iter()
+            .map(|note| (note.start, note.duration, note.key, note.velocity))
+            .collect()
+    }
+
+    #[test]
+    fn notes_come_back_from_their_events() {
+        let notes = vec![
+            note(0, 500, Channel::One, 60),
+            SongNote {
+                off_velocity: 40,
+                track: 2,
+                ..note(0, 250, Channel::Two, 64)
+            },
+            // starts where the first note of its key ends
+            note(500, 500, Channel::One, 60),
+            note(700, 0, Channel::One, 67),
+            SongNote {
+                velocity: 1,
+                ..note(800, 100, Channel::Ten, 36)
+            },
+        ];
+        let song = MidiSong::from_notes(&notes);
+        assert_eq!(song.events().len(), 10);
+        assert_eq!(song.notes(), notes);
+    }
+
+    #[test]
+    fn set_notes_keeps_other_events() {
+        let control = control(300);
+        let mut song = MidiSong::new(vec![on(0, 60, 100), control, off(500, 60)]);
+        song.set_notes(&[note(100, 100, Channel::One, 62)]);
+
+        assert_eq!(spans(&song), vec![(100, 100, 62, 100)]);
+        assert!(song.events().contains(&control));
+    }
+
+    #[test]
+    fn overlapping_notes_of_one_key_end_in_the_order_they_started() {
+        let song = MidiSong::new(vec![
+            on(0, 60, 100),
+            on(100, 60, 50),
+            off(200, 60),
+            // a note on without velocity ends a note too
+            on(300, 60, 0),
+        ]);
+        assert_eq!(spans(&song), vec![(0, 200, 60, 100), (100, 200, 60, 50)]);
+    }
+
+    #[test]
+    fn unmatched_notes_last_until_the_end_of_the_song() {
+        let control = control(900);
+        let song = MidiSong::new(vec![off(0, 62), on(100, 60, 100), off(200, 64), control]);
+        assert_eq!(spans(&song), vec![(100, 800, 60, 100)]);
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::platform::collections::HashMap;
+use midix::prelude::*;
+use thiserror::Error;
+
+use super::{SimpleMidiSong, SimpleSection};
+
+/// One section played on one channel in a step of an [`Arrangement`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrangementPart {
+    /// The name of the section
+    pub section: String,
+    /// The channel the section is played on
+    pub channel: Channel,
+    /// Semitones to move every note by. Notes that would leave the range of MIDI keys are
+    /// skipped, and notes on channel 10, the drum channel, are not moved.
+    pub transpose: i8,
+    /// The velocity of every note. Uses the channel's volume if `None`.
+    pub velocity: Option<Velocity>,
+}
+
+/// Sections that are played together, one or more times in a row
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrangementStep {
+    /// The sections played together
+    pub parts: Vec<ArrangementPart>,
+    /// How many times the step is played
+    pub repeat: u32,
+}
+
+/// Possible errors when laying out an [`Arrangement`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ArrangementError {
+    /// The arrangement plays a section that was never added
+    #[error("No section named {0:?} in the arrangement")]
+    UnknownSection(String),
+}
+
+/// Named [`SimpleSection`]s and the order they are played in, such as
+/// intro, verse twice, chorus, verse, outro.
+///
+/// Each step lasts as long as its longest section, see [`SimpleSection::length`].
+/// Lay it out on a song with [`SimpleMidiSong::arrange`].
+///
+/// ```ignore
+/// let mut arrangement = Arrangement::default();
+/// arrangement
+///     .add_section("verse", verse)
+///     .add_section("chorus", chorus)
+///     .add_section("bass", bass);
+/// arrangement.play("verse", Channel::One).with("bass", Channel::Two).times(2);
+/// arrangement.play("chorus", Channel::One).transpose(2);
+/// let end = song.arrange(&arrangement, 1.)?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Arrangement {
+    sections: HashMap<String, SimpleSection>,
+    steps: Vec<ArrangementStep>,
+}
+
+impl Arrangement {
+    /// Add a section that can be played by name. Replaces any section with the same name.
+    pub fn add_section(&mut self, name: impl Into<String>, section: SimpleSection) -> &mut Self {
+        self.sections.insert(name.into(), section);
+        self
+    }
+    /// Get a section by name
+    pub fn section(&self, name: &str) -> Option<&SimpleSection> {
+        self.sections.get(name)
+    }
+    /// The steps of the arrangement, in order
+    pub fn steps(&self) -> &[ArrangementStep] {
+        &self.steps
+    }
+    /// Play a section on a channel after the previous step
+    pub fn play(&mut self, section: impl Into<String>, channel: Channel) -> StepBuilder<'_> {
+        self.steps.push(ArrangementStep {
+            parts: Vec::new(),
+            repeat: 1,
+        });
+        let step = self.steps.last_mut().unwrap();
+        StepBuilder { step }.with(section, channel)
+    }
+
+    /// The length of a step in beats, which is the length of its longest section
+    fn step_length(&self, step: &ArrangementStep) -> Result<f64, ArrangementError> {
+        step.parts.iter().try_fold(0., |length: f64, part| {
+            Ok(length.max(self.get(&part.section)?.length()))
+        })
+    }
+
+    fn get(&self, name: &str) -> Result<&SimpleSection, ArrangementError> {
+        self.sections
+            .get(name)
+            .ok_or_else(|| ArrangementError::UnknownSection(name.to_owned()))
+    }
+}
+
+/// Configures the latest step of an [`Arrangement`].
+///
+/// [`StepBuilder::transpose`] and [`StepBuilder::velocity`] apply to the section added last.
+pub struct StepBuilder<'a> {
+    step: &'a mut ArrangementStep,
+}
+
+impl<'a> StepBuilder<'a> {
+    /// Also play a section on a channel during this step
+    pub fn with(self, section: impl Into<String>, channel: Channel) -> Self {
+        self.step.parts.push(ArrangementPart {
+            section: section.into(),
+            channel,
+            transpose: 0,
+            velocity: None,
+        });
+        self
+    }
+    /// Move the notes of the last section by a number of semitones.
+    /// See [`ArrangementPart::transpose`].
+    pub fn transpose(self, semitones: i8) -> Self {
+        if let Some(part) = self.step.parts.last_mut() {
+            part.transpose = semitones;
+        }
+        self
+    }
+    /// Play the notes of the last section with a velocity
+    pub fn velocity(self, velocity: Velocity) -> Self {
+        if let Some(part) = self.step.parts.last_mut() {
+            part.velocity = Some(velocity);
+        }
+        self
+    }
+    /// Play this step a number of times in a row
+    pub fn times(self, repeat: u32) -> Self {
+        self.step.repeat = repeat;
+        self
+    }
+}
+
+impl SimpleMidiSong {
+    /// Lay out an arrangement, with its first step at a position in beats.
+    ///
+    /// Returns the position right after the last step, where more can be added.
+    ///
+    /// # Errors
+    /// If the arrangement plays a section that it doesn't have. Nothing is added.
+    pub fn arrange(
+        &mut self,
+        arrangement: &Arrangement,
+        position: f64,
+    ) -> Result<f64, ArrangementError> {
+        let mut lengths = Vec::with_capacity(arrangement.steps.len());
+        for step in &arrangement.steps {
+            lengths.push(arrangement.step_length(step)?);
+        }
+
+        let mut position = position;
+        for (step, length) in arrangement.steps.iter().zip(lengths) {
+            for _ in 0..step.repeat {
+                for part in &step.parts {
+                    let section = arrangement.get(&part.section)?;
+                    let velocity = part
+                        .velocity
+                        .unwrap_or_else(|| self.channel_velocity(part.channel));
+                    self.place_section(
+                        part.channel,
+                        section,
+                        position - 1.,
+                        part.transpose,
+                        velocity,
+                    );
+                }
+                position += length;
+            }
+        }
+        Ok(position)
+    }
+}
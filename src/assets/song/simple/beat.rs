@@ -4,7 +4,7 @@ use midix::prelude::*;
 /// A struct to define what goes on within a beat
 pub struct Beat<'a> {
     pub(crate) song: &'a mut SimpleMidiSong,
-    pub(crate) beat_no: u64,
+    pub(crate) position: f64,
 }
 
 impl<'s> Beat<'s> {
@@ -26,11 +26,12 @@ pub struct ChannelBeat<'b, 's> {
 impl<'b, 's> ChannelBeat<'b, 's> {
     /// play a note for this channel. Does not override other notes that will be played.
     pub fn play(self, note: Note) -> &'b mut Beat<'s> {
-        let event =
-            ChannelVoiceMessage::new(self.channel, VoiceEvent::note_on(note, Velocity::MAX));
+        self.play_for(note, 1.)
+    }
 
-        self.beat.song.add_event(self.beat.beat_no, event);
-        self.beat
+    /// play a note for this channel, held for a number of beats.
+    pub fn play_for(self, note: Note, length: f64) -> &'b mut Beat<'s> {
+        self.play_notes_for([note], length)
     }
 
     /// play some notes for this channel. Does not override other notes that will be played.
@@ -38,10 +39,47 @@ impl<'b, 's> ChannelBeat<'b, 's> {
     where
         Notes: IntoIterator<Item = Note>,
     {
-        let events = notes.into_iter().map(|key| {
-            ChannelVoiceMessage::new(self.channel, VoiceEvent::note_on(key, Velocity::MAX))
-        });
-        self.beat.song.add_events(self.beat.beat_no, events);
+        self.play_notes_for(notes, 1.)
+    }
+
+    /// play some notes for this channel, held together for a number of beats.
+    ///
+    /// Notes are played with the velocity set for the channel.
+    pub fn play_notes_for<Notes>(self, notes: Notes, length: f64) -> &'b mut Beat<'s>
+    where
+        Notes: IntoIterator<Item = Note>,
+    {
+        let velocity = self.beat.song.channel_velocity(self.channel);
+        for note in notes {
+            let event = ChannelVoiceMessage::new(self.channel, VoiceEvent::note_on(note, velocity));
+            self.beat.song.add_note(self.beat.position, length, event);
+        }
         self.beat
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use crate::assets::SimpleMidiSong;
+
+    #[test]
+    fn notes_use_the_channel_velocity() {
+        let mut song = SimpleMidiSong::new(120.);
+        song.channel(Channel::One)
+            .set_volume(Velocity::new(64).unwrap())
+            .beat(2)
+            .play_notes([Note::from_databyte(62).unwrap()]);
+        song.beat(1)
+            .channel(Channel::One)
+            .play_notes_for([Note::from_databyte(60).unwrap()], 1.);
+        let velocities = song
+            .into_song()
+            .notes()
+            .iter()
+            .map(|note| (note.key, note.velocity))
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, vec![(60, 64), (62, 64)]);
+    }
+}
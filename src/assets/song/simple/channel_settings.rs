@@ -1,6 +1,7 @@
 use midix::prelude::*;
 
 use super::{SimpleMidiSong, SimpleSection};
+use crate::assets::Automation;
 
 /// A struct provided to update the settings of a particular channel for a song
 pub struct ChannelModifier<'a> {
@@ -24,65 +25,55 @@ impl<'s> ChannelModifier<'s> {
 
     /// Do something with the channel at this beat
     pub fn beat<'b>(&'b mut self, beat_no: u64) -> BeatChannel<'b, 's> {
+        self.at(beat_no as f64)
+    }
+    /// Do something with the channel at a position between beats. `2.5` is halfway through beat 2.
+    pub fn at<'b>(&'b mut self, position: f64) -> BeatChannel<'b, 's> {
         BeatChannel {
             channel_mod: self,
-            beat: beat_no,
+            position,
         }
     }
     /// Plays a section with an absolute offset from the start of the song
     pub fn play_section(&mut self, section: &SimpleSection, beat_offset: u64) -> &mut Self {
-        for (beat, event) in section.events() {
-            let absolute_beat = *beat + beat_offset;
-            let velocity = self
-                .song
-                .channel_presets
-                .get(&self.channel)
-                .copied()
-                .unwrap_or_default()
-                .velocity;
-            //TODO: look at this
-            self.song.add_events(
-                absolute_beat,
-                event.iter().map(|e| {
-                    let event = match e {
-                        VoiceEvent::NoteOn { note, .. } => VoiceEvent::NoteOn {
-                            note: *note,
-                            velocity,
-                        },
-                        _ => *e,
-                    };
-                    ChannelVoiceMessage::new(self.channel, event)
-                }),
-            );
-        }
+        let velocity = self.velocity();
+        self.song
+            .place_section(self.channel, section, beat_offset as f64, 0, velocity);
         self
     }
+
+    /// Render an automation lane, starting at a position in beats and lasting `length` beats.
+    ///
+    /// Velocity automation changes the notes of this channel that start during it.
+    /// Tempo automation changes the speed of the whole song. Positions are in beats, so every
+    /// note after it moves with the tempo.
+    pub fn automate(&mut self, position: f64, length: f64, automation: Automation) -> &mut Self {
+        self.song
+            .automate(self.channel, position, length, automation);
+        self
+    }
+
+    /// The velocity set for this channel
+    fn velocity(&self) -> Velocity {
+        self.song.channel_velocity(self.channel)
+    }
 }
 
 /// A struct that will tell a channel to do something at a particular beat
 pub struct BeatChannel<'b, 's> {
     channel_mod: &'b mut ChannelModifier<'s>,
-    beat: u64,
+    position: f64,
 }
 
 impl<'b, 's> BeatChannel<'b, 's> {
     /// play a note for this channel. Does not override other notes that will be played.
     pub fn play(self, note: Note) -> &'b mut ChannelModifier<'s> {
-        let velocity = self
-            .channel_mod
-            .song
-            .channel_presets
-            .get(&self.channel_mod.channel)
-            .copied()
-            .unwrap_or_default()
-            .velocity;
-        let event = ChannelVoiceMessage::new(
-            self.channel_mod.channel,
-            VoiceEvent::note_on(note, velocity),
-        );
+        self.play_for(note, 1.)
+    }
 
-        self.channel_mod.song.add_event(self.beat, event);
-        self.channel_mod
+    /// play a note for this channel, held for a number of beats.
+    pub fn play_for(self, note: Note, length: f64) -> &'b mut ChannelModifier<'s> {
+        self.play_notes_for([note], length)
     }
 
     /// play some notes for this channel. Does not override other notes that will be played.
@@ -90,13 +81,22 @@ impl<'b, 's> BeatChannel<'b, 's> {
     where
         Notes: IntoIterator<Item = Note>,
     {
-        let events = notes.into_iter().map(|note| {
-            ChannelVoiceMessage::new(
+        self.play_notes_for(notes, 1.)
+    }
+
+    /// play some notes for this channel, held together for a number of beats.
+    pub fn play_notes_for<Notes>(self, notes: Notes, length: f64) -> &'b mut ChannelModifier<'s>
+    where
+        Notes: IntoIterator<Item = Note>,
+    {
+        let velocity = self.channel_mod.velocity();
+        for note in notes {
+            let event = ChannelVoiceMessage::new(
                 self.channel_mod.channel,
-                VoiceEvent::note_on(note, Velocity::MAX),
-            )
-        });
-        self.channel_mod.song.add_events(self.beat, events);
+                VoiceEvent::note_on(note, velocity),
+            );
+            self.channel_mod.song.add_note(self.position, length, event);
+        }
         self.channel_mod
     }
 }
@@ -12,7 +12,11 @@ pub use channel_settings::*;
 mod section;
 pub use section::*;
 
-use super::MidiSong;
+mod arrangement;
+pub use arrangement::*;
+
+use super::{Automation, AutomationTarget, MidiSong, TempoChange, TempoMap};
+use crate::util;
 
 /// Presets for a channel for a simple song
 #[derive(Copy, Clone, Debug)]
@@ -31,11 +35,42 @@ impl Default for ChannelSettings {
     }
 }
 
+/// Lengths and offsets in beats, for notes that aren't a whole beat long.
+///
+/// A beat is a quarter note.
+pub mod beats {
+    /// Two beats
+    pub const HALF: f64 = 2.;
+    /// One beat
+    pub const QUARTER: f64 = 1.;
+    /// Half a beat
+    pub const EIGHTH: f64 = 0.5;
+    /// A quarter of a beat
+    pub const SIXTEENTH: f64 = 0.25;
+    /// A third of a beat, three of which fill a beat
+    pub const EIGHTH_TRIPLET: f64 = 1. / 3.;
+    /// A sixth of a beat, six of which fill a beat
+    pub const SIXTEENTH_TRIPLET: f64 = 1. / 6.;
+}
+
+/// An event of a simple song, at a position in beats
+#[derive(Copy, Clone, Debug)]
+struct SimpleEvent {
+    /// The beat, counted from 1. `2.5` is halfway through the second beat.
+    position: f64,
+    /// How many beats a note on is held for
+    length: f64,
+    event: ChannelVoiceMessage,
+}
+
 /// A builder designed to make simple songs.
 ///
 /// Add a few notes, and then call [`SimpleMidiSong::into_song`] to get a [`MidiSong`]
 ///
 /// Playing using the beat method, you can play a single tone for a whole beat.
+/// Use [`SimpleMidiSong::at`] for positions between beats, and the `play_for` methods
+/// for notes of other lengths, such as [`beats::EIGHTH`]. Held chords are notes of the
+/// same length, a tie is one longer note, and a rest is a beat where nothing is played.
 ///
 /// it will handle the rest.
 pub struct SimpleMidiSong {
@@ -43,8 +78,11 @@ pub struct SimpleMidiSong {
 
     pub(crate) channel_presets: HashMap<Channel, ChannelSettings>,
 
-    beats: HashMap<u64, Vec<ChannelVoiceMessage>>,
-    last_beat: u64,
+    events: Vec<SimpleEvent>,
+    /// Velocity automation as channels, start and end positions
+    velocity_lanes: Vec<(Channel, f64, f64, Automation)>,
+    /// Tempo changes as positions and beats per minute
+    tempo_changes: Vec<(f64, f64)>,
 }
 
 impl SimpleMidiSong {
@@ -55,8 +93,9 @@ impl SimpleMidiSong {
         Self {
             beats_per_minute,
             channel_presets: Default::default(),
-            beats: Default::default(),
-            last_beat: 0,
+            events: Default::default(),
+            velocity_lanes: Default::default(),
+            tempo_changes: Default::default(),
         }
     }
 
@@ -70,88 +109,179 @@ impl SimpleMidiSong {
 
     /// Do something on beat. Beats start at 1.
     pub fn beat(&mut self, beat_no: u64) -> Beat<'_> {
+        self.at(beat_no as f64)
+    }
+
+    /// Do something at a position between beats. `2.5` is halfway through beat 2.
+    pub fn at(&mut self, position: f64) -> Beat<'_> {
         Beat {
             song: self,
-            beat_no,
+            position,
         }
     }
 
-    /// Add an event
+    /// Add an event. Notes are held for one beat.
     pub fn add_event(&mut self, beat_no: u64, event: ChannelVoiceMessage) {
-        if beat_no > self.last_beat {
-            self.last_beat = beat_no
-        }
-        let current_beat = self.beats.entry(beat_no).or_default();
-        current_beat.push(event);
+        self.add_note(beat_no as f64, 1., event);
     }
 
-    /// Add a set of events toa beat.
+    /// Add a set of events toa beat. Notes are held for one beat.
     pub fn add_events<Msgs>(&mut self, beat_no: u64, events: Msgs)
     where
         Msgs: IntoIterator<Item = ChannelVoiceMessage>,
     {
-        if beat_no > self.last_beat {
-            self.last_beat = beat_no
+        for event in events {
+            self.add_event(beat_no, event);
         }
-        let current_beat = self.beats.entry(beat_no).or_default();
-        current_beat.extend(events);
     }
 
-    /// Turns this midi song into a song that can be used for the synth to handle
-    pub fn into_song(self) -> MidiSong {
-        let micros_per_beat = 60_000_000. / self.beats_per_minute;
+    /// Add an event at a position in beats. Note ons are held for `length` beats.
+    pub fn add_note(&mut self, position: f64, length: f64, event: ChannelVoiceMessage) {
+        self.events.push(SimpleEvent {
+            position,
+            length: length.max(0.),
+            event,
+        });
+    }
 
-        let mut next_beat_additions = Vec::new();
+    /// The velocity set for a channel
+    pub(crate) fn channel_velocity(&self, channel: Channel) -> Velocity {
+        self.channel_presets
+            .get(&channel)
+            .copied()
+            .unwrap_or_default()
+            .velocity
+    }
 
-        // we'll add the program change for any voices set to next_beat additions
-        if !self.channel_presets.is_empty() {
-            for (channel, settings) in self.channel_presets.iter() {
-                next_beat_additions.push(ChannelVoiceMessage::new(
-                    *channel,
-                    VoiceEvent::program_change(settings.program),
-                ));
+    /// Add the notes of a section, moved by `offset` beats and `transpose` semitones.
+    ///
+    /// Notes are transposed like [`MidiSong::transpose`](crate::assets::MidiSong::transpose),
+    /// so notes moved outside the range of MIDI keys are skipped, and drums aren't moved.
+    pub(crate) fn place_section(
+        &mut self,
+        channel: Channel,
+        section: &SimpleSection,
+        offset: f64,
+        transpose: i8,
+        velocity: Velocity,
+    ) {
+        for note in section.notes() {
+            let event = ChannelVoiceMessage::new(channel, VoiceEvent::note_on(note.note, velocity));
+            if let Some(event) = util::transpose(event, transpose) {
+                self.add_note(note.position + offset, note.length, event);
             }
         }
+    }
 
-        let mut commands = Vec::with_capacity(self.beats.len() * 6);
-
-        for i in 0..=self.last_beat {
-            let beat_no = i + 1;
-            let timestamp = i * micros_per_beat as u64;
-            let Some(events) = self.beats.get(&beat_no) else {
-                let iter = next_beat_additions
-                    .iter()
-                    .copied()
-                    .map(|nb| Timed::new(timestamp, nb))
-                    .collect::<Vec<_>>();
-
-                next_beat_additions.clear();
-                commands.extend(iter);
-                continue;
-            };
-
-            let additions_for_this_beat = next_beat_additions.clone();
-            next_beat_additions.clear();
-
-            for event in events.iter() {
-                // add off events for the next beat
-                if let Some(key) = event.is_note_on() {
-                    let channel = event.channel();
-
-                    next_beat_additions.push(ChannelVoiceMessage::new(
-                        channel,
-                        VoiceEvent::note_off(key, Velocity::MAX),
-                    ));
+    /// Render an automation on a channel, from a position for a number of beats
+    pub(crate) fn automate(
+        &mut self,
+        channel: Channel,
+        position: f64,
+        length: f64,
+        automation: Automation,
+    ) {
+        if !automation.is_finite() {
+            return;
+        }
+        let length = length.max(0.);
+        let seconds = length * 60. / self.beats_per_minute;
+        match automation.target {
+            AutomationTarget::Velocity => {
+                self.velocity_lanes
+                    .push((channel, position, position + length, automation));
+            }
+            AutomationTarget::Tempo => {
+                for t in automation.points(seconds) {
+                    self.tempo_changes
+                        .push((position + t * length, automation.value_at(t)));
                 }
             }
-            commands.extend(
-                additions_for_this_beat
-                    .into_iter()
-                    .chain(events.iter().copied())
-                    .map(|msg| Timed::new(timestamp, msg)),
+            _ => {
+                for (t, message) in automation.render(channel, seconds) {
+                    self.add_note(position + t * length, 0., message);
+                }
+            }
+        }
+    }
+
+    /// The tempo map of the song, with a beat being a quarter note
+    fn tempo_map(&self) -> TempoMap {
+        let mut tempo_map = TempoMap::default();
+        tempo_map.add_tempo(TempoChange::from_bpm(0, self.beats_per_minute));
+        for (position, bpm) in &self.tempo_changes {
+            tempo_map.add_tempo_at_quarters(
+                position.max(1.) - 1.,
+                TempoChange::from_bpm(0, *bpm).micros_per_quarter,
             );
         }
+        tempo_map
+    }
+
+    /// The note on with the velocity of any velocity automation
+    fn with_dynamics(&self, position: f64, event: ChannelVoiceMessage) -> ChannelVoiceMessage {
+        let Some(key) = util::note_on_key(&event) else {
+            return event;
+        };
+        let lane = self
+            .velocity_lanes
+            .iter()
+            .rev()
+            .find(|(channel, start, end, _)| {
+                *channel == event.channel() && (*start..=*end).contains(&position)
+            });
+        let Some((channel, start, end, automation)) = lane else {
+            return event;
+        };
+        let t = (position - start) / (end - start).max(f64::EPSILON);
+        util::voice_message(
+            util::STATUS_NOTE_ON,
+            *channel,
+            key,
+            automation.velocity_at(t),
+        )
+        .unwrap_or(event)
+    }
+
+    /// Turns this midi song into a song that can be used for the synth to handle
+    pub fn into_song(mut self) -> MidiSong {
+        self.tempo_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let tempo_map = self.tempo_map();
+        let timestamp = |position: f64| tempo_map.micros_at_quarters(position.max(1.) - 1.);
+
+        let mut commands = Vec::with_capacity(self.events.len() * 2 + 16);
+
+        // the program change for any voices set comes first
+        for (channel, settings) in self.channel_presets.iter() {
+            commands.push(Timed::new(
+                0,
+                ChannelVoiceMessage::new(*channel, VoiceEvent::program_change(settings.program)),
+            ));
+        }
+
+        for SimpleEvent {
+            position,
+            length,
+            event,
+        } in self.events.iter().copied()
+        {
+            let event = self.with_dynamics(position, event);
+            commands.push(Timed::new(timestamp(position), event));
+            if let Some(key) = event.is_note_on() {
+                commands.push(Timed::new(
+                    timestamp(position + length),
+                    ChannelVoiceMessage::new(
+                        event.channel(),
+                        VoiceEvent::note_off(key, Velocity::MAX),
+                    ),
+                ));
+            }
+        }
+        // a note that ends as the same note starts again is released first
+        commands.sort_by_key(|command| (command.timestamp, command.event.is_note_on().is_some()));
 
-        MidiSong::new(commands)
+        let mut song = MidiSong::new(commands);
+        song.tempo_map = tempo_map;
+        song
     }
 }
@@ -1,35 +1,117 @@
 use bevy::platform::collections::HashMap;
 use midix::prelude::*;
 
+/// A note of a [`SimpleSection`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SectionNote {
+    /// The beat in the section, counted from 1. `1.5` is halfway through the first beat.
+    pub position: f64,
+    /// How many beats the note is held for
+    pub length: f64,
+    /// The note
+    pub note: Note,
+}
+
 /// Create a section that can be used for looping
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct SimpleSection {
-    beat: HashMap<u64, Vec<VoiceEvent>>,
+    notes: Vec<SectionNote>,
+    length: Option<f64>,
 }
 impl SimpleSection {
     /// Get a [`SectionBeat`] to do things
     pub fn beat(&mut self, beat_no: u64) -> SectionBeat<'_> {
+        self.at(beat_no as f64)
+    }
+    /// Get a [`SectionBeat`] at a position between beats. `1.5` is halfway through beat 1.
+    pub fn at(&mut self, position: f64) -> SectionBeat<'_> {
         SectionBeat {
             section: self,
-            beat_no,
+            position,
         }
     }
+    /// get the notes of this section
+    pub fn notes(&self) -> &[SectionNote] {
+        &self.notes
+    }
     /// get the list of voice events
-    pub fn events(&self) -> &HashMap<u64, Vec<VoiceEvent>> {
-        &self.beat
+    ///
+    /// Only notes that start on a whole beat are listed, as note ons without their length.
+    #[deprecated(
+        note = "use `SimpleSection::notes`, which keeps the position and length of each note"
+    )]
+    pub fn events(&self) -> HashMap<u64, Vec<VoiceEvent>> {
+        let mut beats = HashMap::<u64, Vec<VoiceEvent>>::new();
+        for note in &self.notes {
+            if note.position.fract() == 0. && note.position >= 0. {
+                beats
+                    .entry(note.position as u64)
+                    .or_default()
+                    .push(VoiceEvent::note_on(note.note, Velocity::MAX));
+            }
+        }
+        beats
+    }
+    /// Set how many beats the section lasts, such as to end it with a rest
+    pub fn set_length(&mut self, beats: f64) -> &mut Self {
+        self.length = Some(beats.max(0.));
+        self
+    }
+    /// How many beats the section lasts.
+    ///
+    /// Unless set with [`SimpleSection::set_length`], this is up to the end of the
+    /// last note, rounded up to a whole beat.
+    pub fn length(&self) -> f64 {
+        self.length.unwrap_or_else(|| {
+            self.notes
+                .iter()
+                .map(|note| note.position - 1. + note.length)
+                .fold(0., f64::max)
+                .ceil()
+        })
     }
 }
 /// Configure a beat for a section
 pub struct SectionBeat<'a> {
     section: &'a mut SimpleSection,
-    beat_no: u64,
+    position: f64,
 }
 
 impl<'a> SectionBeat<'a> {
     /// Add one note to play for the beat
     pub fn play(self, note: Note) -> &'a mut SimpleSection {
-        let events = self.section.beat.entry(self.beat_no).or_default();
-        events.push(VoiceEvent::note_on(note, Velocity::MAX));
+        self.play_for(note, 1.)
+    }
+    /// Add one note to play, held for a number of beats
+    pub fn play_for(self, note: Note, length: f64) -> &'a mut SimpleSection {
+        self.section.notes.push(SectionNote {
+            position: self.position,
+            length: length.max(0.),
+            note,
+        });
         self.section
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::SimpleSection;
+
+    #[test]
+    #[allow(deprecated)]
+    fn events_list_notes_on_whole_beats() {
+        let c = Note::from_databyte(60).unwrap();
+        let e = Note::from_databyte(64).unwrap();
+        let mut section = SimpleSection::default();
+        section.beat(1).play(c);
+        section.at(1.5).play(e);
+        section.at(2.).play_for(e, 2.);
+
+        let events = section.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[&1], vec![VoiceEvent::note_on(c, Velocity::MAX)]);
+        assert_eq!(events[&2], vec![VoiceEvent::note_on(e, Velocity::MAX)]);
+    }
+}
@@ -2,7 +2,8 @@ use bevy::platform::collections::HashMap;
 use core::{hash::BuildHasher, iter};
 use midix::prelude::*;
 
-use super::SongId;
+use super::{MidiSong, SongId};
+use crate::assets::{SmfExportSettings, write_song};
 
 /// A structure used to write songs programatically
 pub trait SongWriter {
@@ -33,6 +34,13 @@ pub trait SongWriter {
 
         map
     }
+    /// Write the song as a Standard MIDI File, such as to open it in a DAW.
+    ///
+    /// Timestamps are converted to ticks at the tempo of the settings. Songs that have a
+    /// [tempo map](MidiSong::tempo_map) use it unless the settings override it.
+    fn to_smf(&self, settings: &SmfExportSettings) -> Vec<u8> {
+        write_song(&MidiSong::new(self.events().collect()), settings)
+    }
 }
 
 impl SongWriter for Timed<ChannelVoiceMessage> {
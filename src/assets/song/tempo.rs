@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+
+/// The tempo of a song without any tempo changes: 120 beats per minute.
+pub const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000;
+
+/// A change of tempo at a point in a song
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempoChange {
+    /// When the tempo changes, in microseconds
+    pub timestamp: u64,
+    /// The length of a quarter note from this point on
+    pub micros_per_quarter: u32,
+}
+
+impl TempoChange {
+    /// Create a tempo change from beats per minute
+    pub fn from_bpm(timestamp: u64, bpm: f64) -> Self {
+        Self {
+            timestamp,
+            micros_per_quarter: (60_000_000. / bpm) as u32,
+        }
+    }
+
+    /// The tempo in beats (quarter notes) per minute
+    pub fn bpm(&self) -> f64 {
+        60_000_000. / self.micros_per_quarter as f64
+    }
+}
+
+/// A change of time signature at a point in a song
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeSignatureChange {
+    /// When the time signature changes, in microseconds
+    pub timestamp: u64,
+    /// The number of beats in a bar
+    pub numerator: u8,
+    /// The note value of a beat, such as 4 for quarter notes
+    pub denominator: u8,
+}
+
+impl TimeSignatureChange {
+    /// The length of a bar in quarter notes
+    pub fn quarters_per_bar(&self) -> f64 {
+        self.numerator as f64 * 4. / self.denominator as f64
+    }
+}
+
+/// The tempo and meter changes of a song, in microseconds.
+///
+/// Before the first change, a song is 120 beats per minute in 4/4.
+/// Bars are counted from 1.
+#[derive(Clone, Debug, Default, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempoMap {
+    tempos: Vec<TempoChange>,
+    time_signatures: Vec<TimeSignatureChange>,
+}
+
+impl TempoMap {
+    /// A tempo map with a single tempo and time signature
+    pub fn constant(bpm: f64, numerator: u8, denominator: u8) -> Self {
+        let mut map = Self::default();
+        map.add_tempo(TempoChange::from_bpm(0, bpm));
+        map.add_time_signature(TimeSignatureChange {
+            timestamp: 0,
+            numerator,
+            denominator,
+        });
+        map
+    }
+
+    /// Add a tempo change. Replaces any tempo change at the same time.
+    pub fn add_tempo(&mut self, change: TempoChange) {
+        match self
+            .tempos
+            .binary_search_by_key(&change.timestamp, |c| c.timestamp)
+        {
+            Ok(index) => self.tempos[index] = change,
+            Err(index) => self.tempos.insert(index, change),
+        }
+    }
+
+    /// Add a time signature change. Replaces any time signature change at the same time.
+    pub fn add_time_signature(&mut self, change: TimeSignatureChange) {
+        match self
+            .time_signatures
+            .binary_search_by_key(&change.timestamp, |c| c.timestamp)
+        {
+            Ok(index) => self.time_signatures[index] = change,
+            Err(index) => self.time_signatures.insert(index, change),
+        }
+    }
+
+    /// Add a tempo change a number of quarter notes from the start, timed by the tempo
+    /// changes before it. Changes must be added in order.
+    pub(crate) fn add_tempo_at_quarters(&mut self, quarters: f64, micros_per_quarter: u32) {
+        let timestamp = self.micros_at_quarters(quarters);
+        self.add_tempo(TempoChange {
+            timestamp,
+            micros_per_quarter,
+        });
+    }
+
+    /// The same changes with every timestamp and quarter note length multiplied by `factor`.
+    ///
+    /// A map without tempo changes gets one at 0, so the default tempo is scaled too.
+    pub(crate) fn scaled(&self, factor: f64) -> TempoMap {
+        let scale = |value: f64| (value * factor) as u64;
+        let mut tempos = self.tempos.clone();
+        if tempos.is_empty() {
+            tempos.push(TempoChange {
+                timestamp: 0,
+                micros_per_quarter: DEFAULT_MICROS_PER_QUARTER,
+            });
+        }
+        TempoMap {
+            tempos: tempos
+                .into_iter()
+                .map(|change| TempoChange {
+                    timestamp: scale(change.timestamp as f64),
+                    micros_per_quarter: scale(change.micros_per_quarter as f64).max(1) as u32,
+                })
+                .collect(),
+            time_signatures: self
+                .time_signatures
+                .iter()
+                .map(|change| TimeSignatureChange {
+                    timestamp: scale(change.timestamp as f64),
+                    ..*change
+                })
+                .collect(),
+        }
+    }
+
+    /// Every tempo change, sorted by time
+    pub fn tempos(&self) -> &[TempoChange] {
+        &self.tempos
+    }
+
+    /// Every time signature change, sorted by time
+    pub fn time_signatures(&self) -> &[TimeSignatureChange] {
+        &self.time_signatures
+    }
+
+    /// True if there are no tempo or time signature changes
+    pub fn is_empty(&self) -> bool {
+        self.tempos.is_empty() && self.time_signatures.is_empty()
+    }
+
+    /// The length of a quarter note at this time
+    pub fn micros_per_quarter_at(&self, micros: u64) -> u32 {
+        self.tempos
+            .iter()
+            .rev()
+            .find(|change| change.timestamp <= micros)
+            .map_or(DEFAULT_MICROS_PER_QUARTER, |change| {
+                change.micros_per_quarter
+            })
+    }
+
+    /// The tempo in beats (quarter notes) per minute at this time
+    pub fn bpm_at(&self, micros: u64) -> f64 {
+        60_000_000. / self.micros_per_quarter_at(micros) as f64
+    }
+
+    /// The time signature at this time
+    pub fn time_signature_at(&self, micros: u64) -> TimeSignatureChange {
+        self.time_signatures
+            .iter()
+            .rev()
+            .find(|change| change.timestamp <= micros)
+            .copied()
+            .unwrap_or(TimeSignatureChange {
+                timestamp: 0,
+                numerator: 4,
+                denominator: 4,
+            })
+    }
+
+    /// The number of quarter notes that have passed at this time
+    pub fn quarters_at(&self, micros: u64) -> f64 {
+        let mut quarters = 0.;
+        let mut segment_start = 0;
+        let mut micros_per_quarter = DEFAULT_MICROS_PER_QUARTER;
+        for change in &self.tempos {
+            if change.timestamp >= micros {
+                break;
+            }
+            quarters += (change.timestamp - segment_start) as f64 / micros_per_quarter as f64;
+            segment_start = change.timestamp;
+            micros_per_quarter = change.micros_per_quarter;
+        }
+        quarters + (micros - segment_start) as f64 / micros_per_quarter as f64
+    }
+
+    /// The time at which this many quarter notes have passed
+    pub fn micros_at_quarters(&self, quarters: f64) -> u64 {
+        let mut passed = 0.;
+        let mut segment_start = 0;
+        let mut micros_per_quarter = DEFAULT_MICROS_PER_QUARTER;
+        for change in &self.tempos {
+            let segment = (change.timestamp - segment_start) as f64 / micros_per_quarter as f64;
+            if passed + segment >= quarters {
+                break;
+            }
+            passed += segment;
+            segment_start = change.timestamp;
+            micros_per_quarter = change.micros_per_quarter;
+        }
+        segment_start + ((quarters - passed).max(0.) * micros_per_quarter as f64).round() as u64
+    }
+
+    /// The bar at this time, counted from 1. The fraction is how far into the bar it is.
+    pub fn bar_at(&self, micros: u64) -> f64 {
+        let quarters = self.quarters_at(micros);
+        let mut bars = 1.;
+        for (signature, start, end) in self.meter_segments() {
+            let length = signature.quarters_per_bar();
+            if quarters < end {
+                return bars + (quarters - start) / length;
+            }
+            bars += (end - start) / length;
+        }
+        bars
+    }
+
+    /// The time at which a bar starts. Bars are counted from 1.
+    pub fn micros_at_bar(&self, bar: f64) -> u64 {
+        let target = (bar - 1.).max(0.);
+        let mut bars = 0.;
+        for (signature, start, end) in self.meter_segments() {
+            let length = signature.quarters_per_bar();
+            let segment = (end - start) / length;
+            if bars + segment > target {
+                return self.micros_at_quarters(start + (target - bars) * length);
+            }
+            bars += segment;
+        }
+        0
+    }
+
+    /// Each time signature with the quarter notes at which it starts and ends.
+    ///
+    /// The last segment never ends.
+    fn meter_segments(&self) -> impl Iterator<Item = (TimeSignatureChange, f64, f64)> + '_ {
+        let first = self.time_signature_at(0);
+        let starts = core::iter::once(first).chain(
+            self.time_signatures
+                .iter()
+                .copied()
+                .skip_while(|change| change.timestamp == 0),
+        );
+        let mut starts = starts
+            .map(|change| (change, self.quarters_at(change.timestamp)))
+            .peekable();
+        core::iter::from_fn(move || {
+            let (signature, start) = starts.next()?;
+            let end = starts.peek().map_or(f64::INFINITY, |(_, end)| *end);
+            Some((signature, start, end))
+        })
+    }
+}
@@ -0,0 +1,398 @@
+use super::MidiSong;
+use crate::util;
+use bevy::platform::collections::HashMap;
+
+/// How [`MidiSong::quantize`] moves notes onto a grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuantizeSettings {
+    /// The distance between grid lines, in quarter notes. `0.25` is a sixteenth note grid.
+    pub grid: f64,
+    /// How far notes are moved towards the grid, from 0 (not at all) to 1 (onto it)
+    pub strength: f64,
+    /// How far every second grid line is moved later, as a fraction of the grid from 0 to 1.
+    /// `1. / 3.` gives a triplet swing.
+    pub swing: f64,
+    /// If true, the ends of notes are quantized as well as their starts.
+    /// Otherwise notes keep their length.
+    pub ends: bool,
+}
+
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        Self::new(0.25)
+    }
+}
+
+impl QuantizeSettings {
+    /// Quantize fully to a grid of this many quarter notes, without swing
+    pub fn new(grid: f64) -> Self {
+        Self {
+            grid,
+            strength: 1.,
+            swing: 0.,
+            ends: true,
+        }
+    }
+    /// Move notes only part of the way to the grid
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+    /// Move every second grid line later
+    pub fn with_swing(mut self, swing: f64) -> Self {
+        self.swing = swing;
+        self
+    }
+    /// Quantize the ends of notes, or keep their length
+    pub fn with_ends(mut self, ends: bool) -> Self {
+        self.ends = ends;
+        self
+    }
+
+    /// The grid line closest to a position in quarter notes
+    fn snap(&self, quarters: f64) -> f64 {
+        let pair = self.grid * 2.;
+        let base = (quarters / pair).floor() * pair;
+        let swung = base + self.grid * (1. + self.swing.clamp(0., 1.));
+        [base, swung, base + pair]
+            .into_iter()
+            .min_by(|a, b| (a - quarters).abs().total_cmp(&(b - quarters).abs()))
+            .unwrap_or(quarters)
+    }
+
+    /// A position in quarter notes moved towards the grid
+    fn apply(&self, quarters: f64) -> f64 {
+        quarters + (self.snap(quarters) - quarters) * self.strength.clamp(0., 1.)
+    }
+}
+
+/// How [`MidiSong::humanize`] varies notes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HumanizeSettings {
+    /// The most a note is moved earlier or later, in microseconds
+    pub timing: u64,
+    /// The most the velocity of a note is raised or lowered
+    pub velocity: u8,
+    /// The seed of the random numbers. The same seed always varies a song the same way.
+    pub seed: u64,
+}
+
+impl Default for HumanizeSettings {
+    fn default() -> Self {
+        Self {
+            timing: 10_000,
+            velocity: 8,
+            seed: 0,
+        }
+    }
+}
+
+impl HumanizeSettings {
+    /// Vary notes by up to 10 milliseconds and 8 velocity, with a seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+    /// Move notes by up to this many microseconds
+    pub fn with_timing(mut self, timing: u64) -> Self {
+        self.timing = timing;
+        self
+    }
+    /// Change the velocity of notes by up to this much
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+}
+
+/// A small xorshift generator, so humanizing is repeatable without another dependency
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves zero, so mix the seed first
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number from `-max` to `max`
+    fn spread(&mut self, max: u64) -> i64 {
+        if max == 0 {
+            return 0;
+        }
+        let max = max.min(i64::MAX as u64 / 2);
+        (self.next_u64() % (max * 2 + 1)) as i64 - max as i64
+    }
+}
+
+impl MidiSong {
+    /// Move the starts, and optionally the ends, of notes towards a grid in musical time,
+    /// following the song's [tempo map](MidiSong::tempo_map).
+    ///
+    /// Other events are not moved. A note that would end before it starts is given
+    /// one grid step of length, and a note that would overlap the next note with the same
+    /// key on its channel is ended where that note starts.
+    ///
+    /// ```ignore
+    /// // tighten a recording to sixteenths, with a light swing
+    /// song.quantize(&QuantizeSettings::new(0.25).with_strength(0.8).with_swing(0.2));
+    /// ```
+    pub fn quantize(&mut self, settings: &QuantizeSettings) -> &mut Self {
+        if settings.grid <= 0. || !settings.grid.is_finite() {
+            return self;
+        }
+        let map = self.tempo_map.clone();
+        let pairs = self.note_pairs();
+        for &(on, off) in &pairs {
+            let start = map.quarters_at(self.events[on].timestamp);
+            let new_start = settings.apply(start);
+            self.events[on].timestamp = map.micros_at_quarters(new_start);
+
+            let Some(off) = off else {
+                continue;
+            };
+            let end = map.quarters_at(self.events[off].timestamp);
+            let mut new_end = if settings.ends {
+                settings.apply(end)
+            } else {
+                new_start + (end - start)
+            };
+            if new_end <= new_start {
+                new_end = new_start + settings.grid;
+            }
+            self.events[off].timestamp = map.micros_at_quarters(new_end);
+        }
+        self.separate_same_keys(&pairs);
+        self
+    }
+
+    /// Move notes randomly earlier or later and change their velocity, so the song sounds
+    /// less mechanical. Each note keeps its length, unless it would overlap the next note
+    /// with the same key on its channel, in which case it ends where that note starts.
+    ///
+    /// ```ignore
+    /// song.humanize(&HumanizeSettings::new(7).with_timing(15_000).with_velocity(12));
+    /// ```
+    pub fn humanize(&mut self, settings: &HumanizeSettings) -> &mut Self {
+        let mut random = XorShift::new(settings.seed);
+        let pairs = self.note_pairs();
+        for &(on, off) in &pairs {
+            let shift = random.spread(settings.timing);
+            let velocity_shift = random.spread(settings.velocity as u64);
+
+            let start = self.events[on].timestamp;
+            let shift = shift.max(-(start as i64));
+            for index in core::iter::once(on).chain(off) {
+                let event = &mut self.events[index];
+                event.timestamp = event.timestamp.saturating_add_signed(shift);
+            }
+
+            let message = self.events[on].event;
+            let [_, key, velocity] = util::voice_bytes(&message);
+            let velocity = (velocity as i64 + velocity_shift).clamp(1, 127) as u8;
+            if let Some(message) =
+                util::voice_message(util::STATUS_NOTE_ON, message.channel(), key, velocity)
+            {
+                self.events[on].event = message;
+            }
+        }
+        self.separate_same_keys(&pairs);
+        self
+    }
+
+    /// End notes that overlap the next note with the same key on their channel where that
+    /// note starts, then sort the events so a note off comes before a note on at the same time.
+    ///
+    /// Without this, moved notes of one key could be paired differently when played back.
+    fn separate_same_keys(&mut self, pairs: &[(usize, Option<usize>)]) {
+        let mut by_key = HashMap::<(usize, u8), Vec<(usize, Option<usize>)>>::new();
+        for &(on, off) in pairs {
+            let message = &self.events[on].event;
+            if let Some(key) = util::note_on_key(message) {
+                by_key
+                    .entry((util::channel_index(message.channel()), key))
+                    .or_default()
+                    .push((on, off));
+            }
+        }
+
+        // note offs first, then other events, then the offs of notes without length
+        let mut rank = vec![1; self.events.len()];
+        for notes in by_key.values_mut() {
+            notes.sort_by_key(|(on, _)| self.events[*on].timestamp);
+            for (index, &(on, off)) in notes.iter().enumerate() {
+                let Some(off) = off else {
+                    continue;
+                };
+                if let Some(&(next, _)) = notes.get(index + 1) {
+                    let next = self.events[next].timestamp;
+                    let end = &mut self.events[off].timestamp;
+                    *end = (*end).min(next);
+                }
+                rank[off] = if self.events[off].timestamp > self.events[on].timestamp {
+                    0
+                } else {
+                    2
+                };
+            }
+        }
+
+        let mut order = (0..self.events.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| (self.events[*index].timestamp, rank[*index]));
+        if self.tracks.len() == self.events.len() {
+            self.tracks = order.iter().map(|index| self.tracks[*index]).collect();
+        } else {
+            self.tracks.clear();
+        }
+        self.events = order.iter().map(|index| self.events[*index]).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::{HumanizeSettings, QuantizeSettings};
+    use crate::{assets::MidiSong, util};
+
+    // at the default 120 beats per minute, a quarter note is half a second
+    const QUARTER: u64 = 500_000;
+
+    fn on(timestamp: u64, key: u8) -> Timed<ChannelVoiceMessage> {
+        let message = util::voice_message(util::STATUS_NOTE_ON, Channel::One, key, 100).unwrap();
+        Timed::new(timestamp, message)
+    }
+
+    fn off(timestamp: u64, key: u8) -> Timed<ChannelVoiceMessage> {
+        let message = util::voice_message(util::STATUS_NOTE_OFF, Channel::One, key, 0).unwrap();
+        Timed::new(timestamp, message)
+    }
+
+    /// The start and end of each note
+    fn spans(song: &MidiSong) -> Vec<(u64, u64)> {
+        song.notes()
+            .iter()
+            .map(|note| (note.start, note.end()))
+            .collect()
+    }
+
+    #[test]
+    fn quantize_snaps_to_the_grid() {
+        let mut song = MidiSong::new(vec![
+            on(QUARTER / 10, 60),
+            off(QUARTER * 52 / 100, 60),
+            on(QUARTER * 7 / 10, 62),
+            off(QUARTER * 9 / 10, 62),
+        ]);
+        song.quantize(&QuantizeSettings::new(0.25));
+        assert_eq!(
+            spans(&song),
+            vec![(0, QUARTER / 2), (QUARTER * 3 / 4, QUARTER)]
+        );
+    }
+
+    #[test]
+    fn quantize_strength_moves_part_of_the_way() {
+        let mut song = MidiSong::new(vec![on(QUARTER / 5, 60), off(QUARTER / 2, 60)]);
+        song.quantize(&QuantizeSettings::new(0.25).with_strength(0.5));
+        // from 0.2 halfway to 0.25, and from 0.5 which is already on the grid
+        assert_eq!(spans(&song), vec![(QUARTER * 9 / 40, QUARTER / 2)]);
+    }
+
+    #[test]
+    fn quantize_swing_moves_every_second_grid_line() {
+        let mut song = MidiSong::new(vec![
+            on(QUARTER / 4, 60),
+            off(QUARTER / 2 - 1000, 60),
+            on(QUARTER / 2, 62),
+            off(QUARTER * 3 / 4, 62),
+        ]);
+        song.quantize(&QuantizeSettings::new(0.25).with_swing(0.5).with_ends(false));
+        // the off beat sixteenth moves to 0.375, the beat stays put
+        assert_eq!(
+            spans(&song),
+            vec![
+                (QUARTER * 3 / 8, QUARTER * 5 / 8 - 1000),
+                (QUARTER / 2, QUARTER * 3 / 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn quantize_does_not_overlap_notes_of_one_key() {
+        let mut song = MidiSong::new(vec![
+            on(0, 60),
+            on(QUARTER * 9 / 10, 60),
+            off(QUARTER * 11 / 10, 60),
+            off(QUARTER * 2, 60),
+        ]);
+        song.quantize(&QuantizeSettings::new(1.).with_ends(false));
+        // the first note would now end after the second starts
+        assert_eq!(
+            spans(&song),
+            vec![(0, QUARTER), (QUARTER, QUARTER * 21 / 10)]
+        );
+        let timestamps = song
+            .events()
+            .iter()
+            .map(|event| (event.timestamp, util::note_off_key(&event.event).is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timestamps,
+            vec![
+                (0, false),
+                (QUARTER, true),
+                (QUARTER, false),
+                (QUARTER * 21 / 10, true)
+            ]
+        );
+    }
+
+    fn humanized(seed: u64) -> MidiSong {
+        let mut events = Vec::new();
+        for step in 0..8 {
+            events.push(on(step * QUARTER, 60 + step as u8));
+            events.push(off(step * QUARTER + QUARTER / 2, 60 + step as u8));
+        }
+        let mut song = MidiSong::new(events);
+        song.humanize(&HumanizeSettings::new(seed).with_timing(20_000));
+        song
+    }
+
+    #[test]
+    fn humanize_is_repeatable_with_a_seed() {
+        assert_eq!(humanized(7).events(), humanized(7).events());
+        assert_ne!(humanized(7).events(), humanized(8).events());
+
+        for note in humanized(7).notes() {
+            assert_eq!(note.duration, QUARTER / 2);
+            assert!(note.start.abs_diff((note.key - 60) as u64 * QUARTER) <= 20_000);
+            assert!((92..=108).contains(&note.velocity));
+        }
+    }
+
+    #[test]
+    fn humanize_does_not_overlap_notes_of_one_key() {
+        let mut events = Vec::new();
+        for step in 0..16 {
+            events.push(on(step * 1000, 60));
+            events.push(off(step * 1000 + 999, 60));
+        }
+        let mut song = MidiSong::new(events);
+        song.humanize(&HumanizeSettings::new(3).with_timing(5_000));
+
+        let notes = song.notes();
+        assert_eq!(notes.len(), 16);
+        for pair in notes.windows(2) {
+            assert!(pair[0].end() <= pair[1].start);
+        }
+    }
+}
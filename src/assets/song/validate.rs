@@ -0,0 +1,464 @@
+use core::fmt;
+
+use bevy::platform::collections::{HashMap, HashSet};
+use midix::prelude::*;
+use thiserror::Error;
+
+use super::MidiSong;
+use crate::util;
+
+/// A problem found by [`MidiSong::validate`]. Indexes are into [`MidiSong::events`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SongIssue {
+    /// An event comes before the event at the index in front of it
+    #[error("event {index} at {timestamp}us comes before the previous event at {previous}us")]
+    UnsortedEvent {
+        /// The index of the event
+        index: usize,
+        /// The time of the event, in microseconds
+        timestamp: u64,
+        /// The time of the event in front of it, in microseconds
+        previous: u64,
+    },
+    /// A note off that doesn't end any note
+    #[error("note off {index} for key {key} on {channel:?} doesn't end a note")]
+    OrphanNoteOff {
+        /// The index of the note off
+        index: usize,
+        /// The channel of the note off
+        channel: Channel,
+        /// The key of the note off
+        key: u8,
+    },
+    /// A note on that is never ended
+    #[error("note on {index} for key {key} on {channel:?} never ends")]
+    StuckNote {
+        /// The index of the note on
+        index: usize,
+        /// The channel of the note
+        channel: Channel,
+        /// The key of the note
+        key: u8,
+    },
+    /// A note on at the same time, channel and key as another note on
+    #[error("note on {index} for key {key} on {channel:?} repeats note on {first}")]
+    DuplicateNoteOn {
+        /// The index of the repeated note on
+        index: usize,
+        /// The index of the first note on
+        first: usize,
+        /// The channel of the notes
+        channel: Channel,
+        /// The key of the notes
+        key: u8,
+    },
+    /// A channel mode controller, 120 to 127, with a value it doesn't accept.
+    ///
+    /// Only the channel mode controllers are checked, as every other controller accepts
+    /// any value from 0 to 127.
+    #[error("controller {controller} on {channel:?} can't be set to {value} (event {index})")]
+    ControllerOutOfRange {
+        /// The index of the control change
+        index: usize,
+        /// The channel of the control change
+        channel: Channel,
+        /// The controller number
+        controller: u8,
+        /// The value it was set to
+        value: u8,
+    },
+}
+
+impl SongIssue {
+    /// The index of the event with the problem
+    pub fn index(&self) -> usize {
+        match self {
+            SongIssue::UnsortedEvent { index, .. }
+            | SongIssue::OrphanNoteOff { index, .. }
+            | SongIssue::StuckNote { index, .. }
+            | SongIssue::DuplicateNoteOn { index, .. }
+            | SongIssue::ControllerOutOfRange { index, .. } => *index,
+        }
+    }
+}
+
+/// Every problem found by [`MidiSong::validate`], in the order of the events
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SongReport {
+    issues: Vec<SongIssue>,
+}
+
+impl SongReport {
+    /// True if no problems were found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+    /// The problems that were found
+    pub fn issues(&self) -> &[SongIssue] {
+        &self.issues
+    }
+    /// Take the problems that were found
+    pub fn into_issues(self) -> Vec<SongIssue> {
+        self.issues
+    }
+}
+
+impl fmt::Display for SongReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "no issues");
+        }
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// True if a channel mode controller accepts this value. Other controllers accept any value.
+fn controller_accepts(controller: u8, value: u8) -> bool {
+    match controller {
+        // local control is off or on
+        122 => value == 0 || value == 127,
+        // mono mode takes the number of channels, or 0 for as many as there are voices
+        126 => value <= 16,
+        120..=127 => value == 0,
+        _ => true,
+    }
+}
+
+impl MidiSong {
+    /// The control change at `index` if it sets a channel mode controller to a value
+    /// it doesn't accept
+    fn bad_controller(&self, index: usize) -> Option<SongIssue> {
+        let message = &self.events[index].event;
+        let [status, controller, value] = util::voice_bytes(message);
+        (status & 0xF0 == util::STATUS_CONTROL_CHANGE && !controller_accepts(controller, value))
+            .then_some(SongIssue::ControllerOutOfRange {
+                index,
+                channel: message.channel(),
+                controller,
+                value,
+            })
+    }
+
+    /// Note ons at the same time, channel and key as an earlier note on, with the index of
+    /// the earlier one
+    fn duplicate_note_ons(&self) -> Vec<(usize, usize)> {
+        let mut first = HashMap::<(u64, usize, u8), usize>::new();
+        let mut duplicates = Vec::new();
+        for (index, event) in self.events.iter().enumerate() {
+            let Some(key) = util::note_on_key(&event.event) else {
+                continue;
+            };
+            let channel = util::channel_index(event.event.channel());
+            match first.get(&(event.timestamp, channel, key)) {
+                Some(first) => duplicates.push((index, *first)),
+                None => {
+                    first.insert((event.timestamp, channel, key), index);
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Note offs that don't end any note
+    fn orphan_note_offs(&self, pairs: &[(usize, Option<usize>)]) -> Vec<usize> {
+        let paired = pairs
+            .iter()
+            .filter_map(|(_, off)| *off)
+            .collect::<HashSet<_>>();
+        (0..self.events.len())
+            .filter(|index| {
+                util::note_off_key(&self.events[*index].event).is_some() && !paired.contains(index)
+            })
+            .collect()
+    }
+
+    /// Look for problems that a song can have: events out of order, note offs that don't
+    /// end a note, notes that never end, note ons repeated at the same time, and channel
+    /// mode controllers (120 to 127) set to values they don't accept. Other controllers
+    /// aren't checked.
+    ///
+    /// [`MidiSong::normalize`] fixes all of these.
+    ///
+    /// ```ignore
+    /// let report = song.validate();
+    /// if !report.is_ok() {
+    ///     warn!("{report}");
+    /// }
+    /// ```
+    pub fn validate(&self) -> SongReport {
+        let mut issues = Vec::new();
+        let key_of = |index: usize| util::voice_bytes(&self.events[index].event)[1];
+        let channel_of = |index: usize| self.events[index].event.channel();
+
+        for (index, pair) in self.events.windows(2).enumerate() {
+            if pair[1].timestamp < pair[0].timestamp {
+                issues.push(SongIssue::UnsortedEvent {
+                    index: index + 1,
+                    timestamp: pair[1].timestamp,
+                    previous: pair[0].timestamp,
+                });
+            }
+        }
+
+        let pairs = self.note_pairs();
+        issues.extend(self.orphan_note_offs(&pairs).into_iter().map(|index| {
+            SongIssue::OrphanNoteOff {
+                index,
+                channel: channel_of(index),
+                key: key_of(index),
+            }
+        }));
+        issues.extend(
+            pairs
+                .iter()
+                .filter(|(_, off)| off.is_none())
+                .map(|(index, _)| SongIssue::StuckNote {
+                    index: *index,
+                    channel: channel_of(*index),
+                    key: key_of(*index),
+                }),
+        );
+        issues.extend(self.duplicate_note_ons().into_iter().map(|(index, first)| {
+            SongIssue::DuplicateNoteOn {
+                index,
+                first,
+                channel: channel_of(index),
+                key: key_of(index),
+            }
+        }));
+        issues.extend((0..self.events.len()).filter_map(|index| self.bad_controller(index)));
+
+        // stable, so issues of the same event keep the order above
+        issues.sort_by_key(SongIssue::index);
+        SongReport { issues }
+    }
+
+    /// Fix every problem [`MidiSong::validate`] can find.
+    ///
+    /// Events and meta events are sorted by time, repeated note ons and the note offs
+    /// left without a note are removed, notes that never end are ended at the end of the song,
+    /// and control changes that set channel mode controllers to values they don't accept are
+    /// removed.
+    pub fn normalize(&mut self) -> &mut Self {
+        self.sort_events();
+        self.meta.sort_by_key(|meta| meta.timestamp);
+
+        let removed = self
+            .duplicate_note_ons()
+            .into_iter()
+            .map(|(index, _)| index)
+            .chain((0..self.events.len()).filter(|index| self.bad_controller(*index).is_some()))
+            .collect::<HashSet<_>>();
+        self.retain_events(|index| !removed.contains(&index));
+
+        let pairs = self.note_pairs();
+        let orphans = self
+            .orphan_note_offs(&pairs)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let end = self.duration();
+        let mut tracks = (0..self.events.len())
+            .map(|index| self.event_track(index))
+            .collect::<Vec<_>>();
+        for (on, _) in pairs.iter().filter(|(_, off)| off.is_none()) {
+            let message = self.events[*on].event;
+            let [_, key, _] = util::voice_bytes(&message);
+            if let Some(off) = util::voice_message(util::STATUS_NOTE_OFF, message.channel(), key, 0)
+            {
+                self.events.push(Timed::new(end, off));
+                tracks.push(tracks[*on]);
+            }
+        }
+        self.tracks = tracks;
+        self.retain_events(|index| !orphans.contains(&index));
+        self.sort_events();
+        self
+    }
+
+    /// Keep only the events whose index passes `keep`, along with their tracks
+    fn retain_events(&mut self, keep: impl Fn(usize) -> bool) {
+        let has_tracks = self.tracks.len() == self.events.len();
+        let (events, tracks): (Vec<_>, Vec<_>) = core::mem::take(&mut self.events)
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| keep(*index))
+            .map(|(index, event)| {
+                let track = if has_tracks { self.tracks[index] } else { 0 };
+                (event, track)
+            })
+            .unzip();
+        self.events = events;
+        self.tracks = if has_tracks { tracks } else { Vec::new() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::SongIssue;
+    use crate::{assets::MidiSong, util};
+
+    fn message(status: u8, data_1: u8, data_2: u8) -> ChannelVoiceMessage {
+        util::voice_message(status, Channel::One, data_1, data_2).unwrap()
+    }
+
+    fn on(timestamp: u64, key: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_ON, key, 100))
+    }
+
+    fn off(timestamp: u64, key: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(timestamp, message(util::STATUS_NOTE_OFF, key, 0))
+    }
+
+    fn control(timestamp: u64, controller: u8, value: u8) -> Timed<ChannelVoiceMessage> {
+        Timed::new(
+            timestamp,
+            message(util::STATUS_CONTROL_CHANGE, controller, value),
+        )
+    }
+
+    fn issues(events: Vec<Timed<ChannelVoiceMessage>>) -> Vec<SongIssue> {
+        MidiSong::new(events).validate().into_issues()
+    }
+
+    #[test]
+    fn a_clean_song_has_no_issues() {
+        let report = MidiSong::new(vec![on(0, 60), control(50, 7, 127), off(100, 60)]).validate();
+        assert!(report.is_ok());
+        assert_eq!(report.to_string(), "no issues");
+    }
+
+    #[test]
+    fn unsorted_events_are_found() {
+        assert_eq!(
+            issues(vec![off(100, 60), on(50, 60), control(20, 7, 0)]),
+            vec![
+                SongIssue::UnsortedEvent {
+                    index: 1,
+                    timestamp: 50,
+                    previous: 100
+                },
+                SongIssue::UnsortedEvent {
+                    index: 2,
+                    timestamp: 20,
+                    previous: 50
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn orphan_note_offs_are_found() {
+        assert_eq!(
+            issues(vec![off(0, 62), on(10, 60), off(20, 60)]),
+            vec![SongIssue::OrphanNoteOff {
+                index: 0,
+                channel: Channel::One,
+                key: 62
+            }]
+        );
+    }
+
+    #[test]
+    fn stuck_notes_are_found() {
+        assert_eq!(
+            issues(vec![on(0, 60), on(10, 62), off(20, 60)]),
+            vec![SongIssue::StuckNote {
+                index: 1,
+                channel: Channel::One,
+                key: 62
+            }]
+        );
+    }
+
+    #[test]
+    fn duplicate_note_ons_are_found() {
+        assert_eq!(
+            issues(vec![on(0, 60), on(0, 60), off(20, 60), off(20, 60)]),
+            vec![SongIssue::DuplicateNoteOn {
+                index: 1,
+                first: 0,
+                channel: Channel::One,
+                key: 60
+            }]
+        );
+    }
+
+    #[test]
+    fn only_channel_mode_controllers_are_checked() {
+        assert_eq!(
+            issues(vec![
+                control(0, 7, 127),
+                control(0, 119, 127),
+                control(0, 122, 127),
+                control(0, 126, 16),
+                control(0, 123, 5),
+                control(0, 126, 17),
+            ]),
+            vec![
+                SongIssue::ControllerOutOfRange {
+                    index: 4,
+                    channel: Channel::One,
+                    controller: 123,
+                    value: 5
+                },
+                SongIssue::ControllerOutOfRange {
+                    index: 5,
+                    channel: Channel::One,
+                    controller: 126,
+                    value: 17
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_fixes_every_issue_once() {
+        let mut song = MidiSong::with_tracks(
+            vec![
+                off(100, 62),
+                on(0, 60),
+                on(0, 60),
+                control(50, 121, 3),
+                on(80, 64),
+                off(120, 60),
+                off(120, 60),
+            ],
+            vec![1, 2, 2, 3, 4, 2, 2],
+        );
+        assert!(!song.validate().is_ok());
+
+        song.normalize();
+        assert!(song.validate().is_ok(), "{}", song.validate());
+        let bytes = |song: &MidiSong| {
+            song.events()
+                .iter()
+                .enumerate()
+                .map(|(index, event)| {
+                    let bytes = util::voice_bytes(&event.event);
+                    (event.timestamp, bytes, song.event_track(index))
+                })
+                .collect::<Vec<_>>()
+        };
+        let normalized = bytes(&song);
+        assert_eq!(
+            normalized,
+            vec![
+                (0, util::voice_bytes(&on(0, 60).event), 2),
+                (80, util::voice_bytes(&on(80, 64).event), 4),
+                (120, util::voice_bytes(&off(120, 60).event), 2),
+                (120, util::voice_bytes(&off(120, 64).event), 4),
+            ]
+        );
+
+        song.normalize();
+        assert_eq!(bytes(&song), normalized);
+    }
+}
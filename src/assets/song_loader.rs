@@ -0,0 +1,162 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use midix::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use crate::assets::{SongDocument, SongDocumentError};
+use crate::{
+    assets::{MidiSong, SmfError},
+    util,
+};
+
+/// How a MIDI file is imported by the [`MidiSongLoader`].
+///
+/// These can be set in a `.mid.meta` file next to the song, or with
+/// [`AssetServer::load_with_settings`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiSongLoaderSettings {
+    /// Only the events of these tracks, counted from 0, are kept. Every track is kept if empty.
+    ///
+    /// Tempo and time signature changes are kept from every track.
+    pub tracks: Vec<u16>,
+    /// Only the events on these channels, counted from 1, are kept. Every channel is kept if empty.
+    pub channels: Vec<u8>,
+    /// Semitones to move every note by. Notes on channel 10, the drum channel, are not moved.
+    ///
+    /// Notes that would leave the range of MIDI keys are dropped, like [`MidiSong::transpose`].
+    pub transpose: i8,
+    /// A tempo, in beats per minute, that replaces every tempo change of the file
+    pub tempo_override: Option<f64>,
+    /// If false, text, lyric, marker and cue point events are dropped
+    pub keep_meta: bool,
+}
+
+impl Default for MidiSongLoaderSettings {
+    fn default() -> Self {
+        Self {
+            tracks: Vec::new(),
+            channels: Vec::new(),
+            transpose: 0,
+            tempo_override: None,
+            keep_meta: true,
+        }
+    }
+}
+
+impl MidiSongLoaderSettings {
+    /// True if events of this track are kept
+    pub(crate) fn keeps_track(&self, track: usize) -> bool {
+        self.tracks.is_empty() || self.tracks.iter().any(|kept| *kept as usize == track)
+    }
+
+    /// Applies the track and channel filters and the transpose to a message
+    pub(crate) fn import(
+        &self,
+        track: usize,
+        message: ChannelVoiceMessage,
+    ) -> Option<ChannelVoiceMessage> {
+        let channel = message.channel();
+        let number = util::channel_index(channel) as u8 + 1;
+        if !self.keeps_track(track)
+            || !(self.channels.is_empty() || self.channels.contains(&number))
+        {
+            return None;
+        }
+        util::transpose(message, self.transpose)
+    }
+}
+
+/// Possible errors that can be produced by [`MidiSongLoader`]
+#[derive(Debug, Error)]
+pub enum MidiSongLoadError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid MIDI file
+    #[error("Could not read MIDI file: {0}")]
+    Smf(#[from] SmfError),
+}
+
+/// Loader that reads MIDI files directly into [`MidiSong`]s, keeping their tempo map.
+///
+/// Load a song with `asset_server.load::<MidiSong>("song.mid")`.
+///
+/// `.mid` is shared with [`MidiFileLoader`](crate::assets::MidiFileLoader). Bevy picks the
+/// loader by the type of asset that is asked for, so `load::<MidiSong>` uses this loader and
+/// `load::<MidiFile>` uses the other. When the type isn't known, such as with
+/// `load_untyped`, this loader wins, as [`MidiAssetsPlugin`](crate::assets::MidiAssetsPlugin)
+/// registers it last. `.midi` files are only read by this loader.
+#[derive(Default)]
+pub struct MidiSongLoader;
+
+impl AssetLoader for MidiSongLoader {
+    type Asset = MidiSong;
+    type Settings = MidiSongLoaderSettings;
+    type Error = MidiSongLoadError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &MidiSongLoaderSettings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(MidiSong::from_smf_with(&bytes, settings)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mid", "midi"]
+    }
+}
+
+/// Possible errors that can be produced by [`SongDocumentLoader`]
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum SongDocumentLoadError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not valid RON, or doesn't match [`SongDocument`]
+    #[error("Could not parse song: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    /// The document has an invalid note, channel or time signature
+    #[error("Invalid song: {0}")]
+    Document(#[from] SongDocumentError),
+}
+
+/// Loader for songs written by hand as [`SongDocument`]s in `.song.ron` files.
+///
+/// With bevy's `file_watcher` feature, songs are reloaded when their file changes,
+/// so they can be tweaked without recompiling.
+#[cfg(feature = "serde")]
+#[derive(Default)]
+pub struct SongDocumentLoader;
+
+#[cfg(feature = "serde")]
+impl AssetLoader for SongDocumentLoader {
+    type Asset = MidiSong;
+    type Settings = ();
+    type Error = SongDocumentLoadError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let document: SongDocument = ron::de::from_bytes(&bytes)?;
+        Ok(document.to_song()?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["song.ron"]
+    }
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use midix::prelude::*;
+
+/// System set for processing synthesizer commands.
+///
+/// Systems that need to process MIDI commands for the synthesizer or an output sink should
+/// be added to this set to ensure proper ordering and synchronization.
+///
+/// If you need to do something before the synth commands are processed,
+/// schedule them before this set.
+#[derive(SystemSet, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct ProcessSynthCommands;
+
+/// Component for sending MIDI commands to a synthesizer node or output port via ECS.
+///
+/// This is automatically added to any [`MidiSynthNode`](crate::synth::MidiSynthNode),
+/// [`SynthPlayer`](crate::synth::SynthPlayer) or [`MidiOutputSink`](crate::output::MidiOutputSink).
+/// It doesn't need the `synth` feature, so output sinks work without it.
+#[derive(Component, Default)]
+#[cfg_attr(feature = "assets", require(crate::playback::SynthChannels))]
+pub struct SynthCommands {
+    /// Queue of MIDI commands to send
+    pub queue: Vec<ChannelVoiceMessage>,
+}
+
+impl SynthCommands {
+    /// Add a MIDI command to the queue
+    pub fn send(&mut self, command: ChannelVoiceMessage) {
+        self.queue.push(command);
+    }
+
+    /// Add multiple MIDI commands to the queue
+    pub fn send_batch(&mut self, commands: impl IntoIterator<Item = ChannelVoiceMessage>) {
+        self.queue.extend(commands);
+    }
+
+    /// Queue All Notes Off, All Sound Off and Reset All Controllers on all 16 channels.
+    ///
+    /// See [`MidiPanic`](crate::panic::MidiPanic) to silence every synth at once.
+    pub fn panic(&mut self) {
+        self.queue.extend(crate::panic::panic_messages());
+    }
+
+    /// Take all commands, leaving the queue empty
+    pub fn take(&mut self) -> Vec<ChannelVoiceMessage> {
+        std::mem::take(&mut self.queue)
+    }
+}
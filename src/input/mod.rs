@@ -1,7 +1,7 @@
-use bevy::prelude::*;
+use bevy::{platform::cell::SyncCell, prelude::*};
 
 mod settings;
-use midix::{UMicros, events::LiveEvent, prelude::ChannelVoiceMessage};
+use midix::{UMicros, events::LiveEvent};
 pub use settings::*;
 
 mod error;
@@ -12,12 +12,15 @@ mod state;
 mod plugin;
 pub use plugin::*;
 
+mod routing;
+pub use routing::*;
+
 use midir::MidiInputPort;
 use trotcast::prelude::*;
 
 use crate::{
     data::MidiData,
-    input::state::{MidiInputConnectionHandler, MidiInputState},
+    input::state::{MidiInputConnectionHandler, MidiInputState, PortWatcher},
 };
 
 /// Trait for converting raw MIDI input events into custom data types.
@@ -45,7 +48,7 @@ pub trait FromMidiInputData: Send + Sync + Clone + 'static {
     /// Returns `Some` if this data represents a channel voice message (like
     /// note on/off, pitch bend, etc.), or `None` if it represents other
     /// types of MIDI data. This is primarily used by the synth module.
-    fn to_channel_voice_message(&self) -> Option<ChannelVoiceMessage>;
+    fn to_channel_voice_message(&self) -> Option<midix::prelude::ChannelVoiceMessage>;
 
     /// You can use this to configure stuff for your type in bevy,
     ///
@@ -64,7 +67,9 @@ pub trait FromMidiInputData: Send + Sync + Clone + 'static {
 #[derive(Resource)]
 pub struct MidiInput<D: FromMidiInputData = MidiData> {
     channel: Channel<D>,
-    state: Option<MidiInputState>,
+    router: MidiRouter<D>,
+    state: Option<MidiInputState<D>>,
+    watcher: Option<SyncCell<PortWatcher>>,
     ports: Vec<MidiInputPort>,
     client_name: String,
     port_name: String,
@@ -87,7 +92,9 @@ impl<D: FromMidiInputData> MidiInput<D> {
         let ports = listener.ports();
         Self {
             channel: Channel::new(settings.channel_size),
+            router: MidiRouter::new(settings.channel_size),
             state: Some(MidiInputState::Listening(listener)),
+            watcher: None,
             client_name: settings.client_name,
             port_name: settings.port_name,
             ignore: settings.ignore,
@@ -95,11 +102,31 @@ impl<D: FromMidiInputData> MidiInput<D> {
         }
     }
 
+    /// A midi input without a client, for tests that can't open a MIDI driver
+    #[cfg(all(test, feature = "synth"))]
+    pub(crate) fn without_client(settings: MidiInputSettings) -> Self {
+        Self {
+            channel: Channel::new(settings.channel_size),
+            router: MidiRouter::new(settings.channel_size),
+            state: None,
+            watcher: None,
+            client_name: settings.client_name,
+            port_name: settings.port_name,
+            ignore: settings.ignore,
+            ports: Vec::new(),
+        }
+    }
+
     /// The channel use to send and receive midi data
     pub fn channel(&self) -> &Channel<D> {
         &self.channel
     }
 
+    /// The thru patchbay used to forward incoming data to outputs and synths
+    pub fn router(&self) -> &MidiRouter<D> {
+        &self.router
+    }
+
     /// Return a list of ports updated since calling [`MidiInput::new`] or
     /// [`MidiInput::refresh_ports`]
     pub fn ports(&self) -> &[MidiInputPort] {
@@ -130,9 +157,14 @@ impl<D: FromMidiInputData> MidiInput<D> {
         let MidiInputState::Listening(listener) = self.state.take().unwrap() else {
             unreachable!()
         };
-        let handler =
-            MidiInputConnectionHandler::new(listener, port, &self.port_name, self.channel.clone())
-                .unwrap();
+        let handler = MidiInputConnectionHandler::new(
+            listener,
+            port,
+            &self.port_name,
+            self.channel.clone(),
+            self.router.clone(),
+        )
+        .unwrap();
 
         self.state = Some(MidiInputState::Active(handler));
         Ok(())
@@ -170,8 +202,14 @@ impl<D: FromMidiInputData> MidiInput<D> {
         };
 
         self.state = Some(MidiInputState::Active(
-            MidiInputConnectionHandler::new(listener, port, &self.port_name, self.channel.clone())
-                .unwrap(),
+            MidiInputConnectionHandler::new(
+                listener,
+                port,
+                &self.port_name,
+                self.channel.clone(),
+                self.router.clone(),
+            )
+            .unwrap(),
         ));
         Ok(())
     }
@@ -201,8 +239,14 @@ impl<D: FromMidiInputData> MidiInput<D> {
             return Err(MidiInputError::port_not_found(id));
         };
         self.state = Some(MidiInputState::Active(
-            MidiInputConnectionHandler::new(listener, &port, &self.port_name, self.channel.clone())
-                .unwrap(),
+            MidiInputConnectionHandler::new(
+                listener,
+                &port,
+                &self.port_name,
+                self.channel.clone(),
+                self.router.clone(),
+            )
+            .unwrap(),
         ));
         Ok(())
     }
@@ -232,8 +276,52 @@ impl<D: FromMidiInputData> MidiInput<D> {
         Some(&self.ports)
     }
 
+    /// Sends a note off for every note, and releases every sustain pedal, that is currently held
+    /// down on the connected device.
+    ///
+    /// This is done automatically by [`MidiInput::disconnect`], and by
+    /// [`MidiInput::check_connection`] when the device goes away. Call this if you notice the
+    /// device has stopped sending while connected.
+    ///
+    /// Does nothing if [`MidiInput::is_active`] is false.
+    pub fn release_held_notes(&self) {
+        if let Some(MidiInputState::Active(conn)) = &self.state {
+            conn.release_held();
+        }
+    }
+
+    /// Disconnects if the connected port can no longer be found, such as when the device has
+    /// been unplugged, releasing everything that was held down on it. Returns true if it
+    /// disconnected.
+    ///
+    /// `midir` doesn't report unplugged devices, so [`MidiIoPlugin`] calls this once a second.
+    /// Does nothing if [`MidiInput::is_active`] is false.
+    pub fn check_connection(&mut self) -> bool {
+        let Some(MidiInputState::Active(conn)) = &self.state else {
+            return false;
+        };
+        if self.watcher.is_none() {
+            self.watcher = PortWatcher::new(&self.client_name).map(SyncCell::new);
+        }
+        let Some(watcher) = self.watcher.as_mut().map(SyncCell::get) else {
+            return false;
+        };
+        if watcher.has_port(conn.port_id()) {
+            return false;
+        }
+        warn!(
+            "MIDI input port {} went away, disconnecting",
+            conn.port_id()
+        );
+        self.disconnect();
+        true
+    }
+
     /// Disconnects from the active device
     ///
+    /// Notes and sustain pedals still held down on the device are released,
+    /// so that synths fed by [`MidiInput::channel`] don't drone.
+    ///
     /// Does nothing if the [`MidiInput::is_listening`] is true.
     pub fn disconnect(&mut self) {
         if self
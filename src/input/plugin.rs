@@ -1,4 +1,6 @@
-use bevy::prelude::*;
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
 
 use crate::{
     data::MidiDataSettings,
@@ -48,6 +50,15 @@ pub(crate) fn midi_io_plugin_inner<D: FromMidiInputData>(
     data_settings: &D::Settings,
     app: &mut App,
 ) {
-    app.insert_resource(MidiInput::<D>::new(input_settings));
+    app.insert_resource(MidiInput::<D>::new(input_settings))
+        .add_systems(
+            Update,
+            check_input_connection::<D>.run_if(on_timer(Duration::from_secs(1))),
+        );
     D::configure_plugin(data_settings, app);
 }
+
+/// System that disconnects from an input device that has gone away, releasing its held notes
+fn check_input_connection<D: FromMidiInputData>(mut input: ResMut<MidiInput<D>>) {
+    input.check_connection();
+}
@@ -0,0 +1,497 @@
+use std::sync::{Arc, RwLock};
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use midix::{UMicros, events::LiveEvent, prelude::*};
+use trotcast::Channel as DataChannel;
+
+use crate::{
+    input::FromMidiInputData,
+    output::{MidiOutputError, MidiOutputHandle},
+    util::{self, CHANNELS},
+};
+
+/// Identifies a route added to a [`MidiRouter`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RouteId(u64);
+
+/// Which input port a route listens to
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum RouteSource {
+    /// Whatever port [`MidiInput`](super::MidiInput) is connected to
+    #[default]
+    AnyPort,
+    /// Only the input port with this id
+    Port(String),
+}
+
+impl RouteSource {
+    fn matches(&self, port_id: &str) -> bool {
+        match self {
+            RouteSource::AnyPort => true,
+            RouteSource::Port(id) => id == port_id,
+        }
+    }
+}
+
+/// Where a route forwards its messages to
+#[derive(Clone)]
+pub enum RouteTarget {
+    /// An output port. See [`MidiOutput::handle`](crate::output::MidiOutput::handle).
+    Output(MidiOutputHandle),
+    /// A [`SynthPlayer`](crate::prelude::SynthPlayer) entity.
+    ///
+    /// Once a synth is routed to, it only plays what its routes send it, and no longer
+    /// hears the [`MidiInput`](super::MidiInput)'s main channel, even if it was created with
+    /// MIDI input enabled. If the synth's node was already created, it is created again,
+    /// cutting off any notes it was playing. Once the last route to the synth is removed,
+    /// its node is created again as it was before. Only channel voice messages are forwarded.
+    Synth(Entity),
+}
+
+/// Decides which messages pass through a route
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteFilter {
+    /// Bitmask of the channels to forward. The lowest bit is [`Channel::One`].
+    pub channels: u16,
+    /// Forward note on, note off and polyphonic aftertouch
+    pub notes: bool,
+    /// Forward control changes
+    pub controllers: bool,
+    /// Forward program changes
+    pub programs: bool,
+    /// Forward pitch bends
+    pub pitch_bend: bool,
+    /// Forward channel pressure
+    pub pressure: bool,
+    /// Forward everything that isn't a channel voice message, such as clock and sysex.
+    ///
+    /// These are only sent to output ports.
+    pub system: bool,
+}
+
+impl Default for RouteFilter {
+    /// Forwards every channel voice message, but no system messages.
+    fn default() -> Self {
+        Self {
+            channels: u16::MAX,
+            notes: true,
+            controllers: true,
+            programs: true,
+            pitch_bend: true,
+            pressure: true,
+            system: false,
+        }
+    }
+}
+
+impl RouteFilter {
+    /// Only forward these channels
+    pub fn only_channels(mut self, channels: impl IntoIterator<Item = Channel>) -> Self {
+        self.channels = channels
+            .into_iter()
+            .fold(0, |mask, channel| mask | 1 << util::channel_index(channel));
+        self
+    }
+
+    /// Forward only notes
+    pub fn only_notes(self) -> Self {
+        Self {
+            controllers: false,
+            programs: false,
+            pitch_bend: false,
+            pressure: false,
+            ..self
+        }
+    }
+
+    /// Set whether system messages should be forwarded
+    pub fn with_system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// True if this filter lets the message through
+    pub fn allows(&self, message: &ChannelVoiceMessage) -> bool {
+        if self.channels & (1 << util::channel_index(message.channel())) == 0 {
+            return false;
+        }
+        match message.event() {
+            VoiceEvent::NoteOff { .. }
+            | VoiceEvent::NoteOn { .. }
+            | VoiceEvent::Aftertouch { .. } => self.notes,
+            VoiceEvent::ControlChange(_) => self.controllers,
+            VoiceEvent::ProgramChange { .. } => self.programs,
+            VoiceEvent::PitchBend(_) => self.pitch_bend,
+            VoiceEvent::ChannelPressureAfterTouch { .. } => self.pressure,
+        }
+    }
+}
+
+/// Moves messages from one channel to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelRemap([Channel; 16]);
+
+impl Default for ChannelRemap {
+    /// Every channel maps to itself
+    fn default() -> Self {
+        Self(CHANNELS)
+    }
+}
+
+impl ChannelRemap {
+    /// Send messages on `from` to `to` instead
+    pub fn map(mut self, from: Channel, to: Channel) -> Self {
+        self.0[util::channel_index(from)] = to;
+        self
+    }
+
+    /// Send messages on every channel to `to`
+    pub fn all_to(to: Channel) -> Self {
+        Self([to; 16])
+    }
+
+    /// The channel that `channel` is mapped to
+    pub fn get(&self, channel: Channel) -> Channel {
+        self.0[util::channel_index(channel)]
+    }
+
+    /// Returns the message moved to its mapped channel
+    pub fn apply(&self, message: ChannelVoiceMessage) -> ChannelVoiceMessage {
+        ChannelVoiceMessage::new(self.get(message.channel()), *message.event())
+    }
+}
+
+/// A single connection in the [`MidiRouter`]'s patchbay
+#[derive(Clone)]
+pub struct MidiRoute {
+    /// The input port to listen to
+    pub source: RouteSource,
+    /// Where messages are sent
+    pub target: RouteTarget,
+    /// Which messages are sent
+    pub filter: RouteFilter,
+    /// Which channels messages are sent on
+    pub remap: ChannelRemap,
+}
+
+impl MidiRoute {
+    /// Forward everything from any input port to the target
+    pub fn new(target: RouteTarget) -> Self {
+        Self {
+            source: RouteSource::default(),
+            target,
+            filter: RouteFilter::default(),
+            remap: ChannelRemap::default(),
+        }
+    }
+
+    /// Forward everything from any input port to an output port
+    pub fn to_output(handle: MidiOutputHandle) -> Self {
+        Self::new(RouteTarget::Output(handle))
+    }
+
+    /// Forward everything from any input port to a synth entity
+    pub fn to_synth(entity: Entity) -> Self {
+        Self::new(RouteTarget::Synth(entity))
+    }
+
+    /// Only listen to the input port with this id
+    pub fn from_port(mut self, id: impl Into<String>) -> Self {
+        self.source = RouteSource::Port(id.into());
+        self
+    }
+
+    /// Set the filter for this route
+    pub fn with_filter(mut self, filter: RouteFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Set the channel remap for this route
+    pub fn with_remap(mut self, remap: ChannelRemap) -> Self {
+        self.remap = remap;
+        self
+    }
+}
+
+/// A configurable MIDI thru patchbay, owned by [`MidiInput`](super::MidiInput).
+///
+/// Routes are evaluated inside the input connection's callback, so forwarded
+/// messages never wait on the app's frame rate. Every message is still sent
+/// to the input's [`channel`](super::MidiInput::channel) as usual.
+///
+/// The router can be cloned and modified from any thread.
+pub struct MidiRouter<D: FromMidiInputData> {
+    inner: Arc<RwLock<RouterInner<D>>>,
+    channel_size: usize,
+}
+
+impl<D: FromMidiInputData> Clone for MidiRouter<D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            channel_size: self.channel_size,
+        }
+    }
+}
+
+struct RouterInner<D: FromMidiInputData> {
+    next_id: u64,
+    routes: Vec<(RouteId, MidiRoute)>,
+    /// The channel of every synth with a route to it, with the route that opened the channel
+    synths: HashMap<Entity, (RouteId, DataChannel<D>)>,
+}
+
+impl<D: FromMidiInputData> RouterInner<D> {
+    /// Close the channels of synths that no route goes to anymore
+    fn close_unrouted_synths(&mut self) {
+        let routes = &self.routes;
+        self.synths.retain(|entity, _| {
+            routes
+                .iter()
+                .any(|(_, route)| matches!(route.target, RouteTarget::Synth(target) if target == *entity))
+        });
+    }
+}
+
+impl<D: FromMidiInputData> MidiRouter<D> {
+    pub(crate) fn new(channel_size: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(RouterInner {
+                next_id: 0,
+                routes: Vec::new(),
+                synths: HashMap::default(),
+            })),
+            channel_size,
+        }
+    }
+
+    /// Add a route to the patchbay
+    pub fn add_route(&self, route: MidiRoute) -> RouteId {
+        let mut inner = self.inner.write().unwrap();
+        let id = RouteId(inner.next_id);
+        inner.next_id += 1;
+        if let RouteTarget::Synth(entity) = &route.target {
+            inner
+                .synths
+                .entry(*entity)
+                .or_insert_with(|| (id, DataChannel::new(self.channel_size)));
+        }
+        inner.routes.push((id, route));
+        id
+    }
+
+    /// Remove a route from the patchbay. Returns false if it didn't exist.
+    pub fn remove_route(&self, id: RouteId) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        let len = inner.routes.len();
+        inner.routes.retain(|(route_id, _)| *route_id != id);
+        inner.close_unrouted_synths();
+        inner.routes.len() != len
+    }
+
+    /// Remove every route
+    pub fn clear(&self) {
+        let mut inner = self.inner.write().unwrap();
+        inner.routes.clear();
+        inner.synths.clear();
+    }
+
+    /// The number of routes in the patchbay
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().routes.len()
+    }
+
+    /// True if there are no routes
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Identifies the channel of a synth entity, if any route goes to it.
+    ///
+    /// This changes when the synth's routes are all removed and it is routed to again.
+    #[cfg(feature = "synth")]
+    pub(crate) fn synth_channel_id(&self, entity: Entity) -> Option<RouteId> {
+        let inner = self.inner.read().unwrap();
+        inner.synths.get(&entity).map(|(id, _)| *id)
+    }
+
+    /// The channel messages are forwarded to for a synth entity, if any route goes to it.
+    #[cfg(feature = "synth")]
+    pub(crate) fn synth_channel(&self, entity: Entity) -> Option<(RouteId, DataChannel<D>)> {
+        self.inner.read().unwrap().synths.get(&entity).cloned()
+    }
+
+    /// Forward a message received on `port_id` through every matching route
+    pub(crate) fn dispatch(
+        &self,
+        port_id: &str,
+        timestamp: u64,
+        data: &[u8],
+        event: &LiveEvent<'static>,
+    ) {
+        let inner = self.inner.read().unwrap();
+        let voice = event.channel_voice().copied();
+
+        for (_, route) in &inner.routes {
+            if !route.source.matches(port_id) {
+                continue;
+            }
+            let result = match (voice, &route.target) {
+                (Some(voice), target) => {
+                    if !route.filter.allows(&voice) {
+                        continue;
+                    }
+                    let voice = route.remap.apply(voice);
+                    match target {
+                        RouteTarget::Output(handle) => handle.send(voice),
+                        RouteTarget::Synth(entity) => {
+                            if let Some((_, channel)) = inner.synths.get(entity) {
+                                // the synth may not have been spawned yet
+                                let _ = channel.send(D::from_midi_data(
+                                    UMicros::new(timestamp),
+                                    LiveEvent::ChannelVoice(voice),
+                                ));
+                            }
+                            Ok(())
+                        }
+                    }
+                }
+                (None, RouteTarget::Output(handle)) if route.filter.system => {
+                    handle.send_bytes(data)
+                }
+                (None, _) => continue,
+            };
+            if let Err(MidiOutputError::SendError(e)) = result {
+                warn!("Error forwarding MIDI data! {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::{ChannelRemap, RouteFilter};
+    use crate::util;
+
+    fn message(status: u8, channel: Channel) -> ChannelVoiceMessage {
+        util::voice_message(status, channel, 60, 100).unwrap()
+    }
+
+    #[test]
+    fn default_filter_allows_every_voice_message() {
+        let filter = RouteFilter::default();
+        for status in [
+            util::STATUS_NOTE_OFF,
+            util::STATUS_NOTE_ON,
+            util::STATUS_POLY_PRESSURE,
+            util::STATUS_CONTROL_CHANGE,
+            util::STATUS_PROGRAM_CHANGE,
+            util::STATUS_CHANNEL_PRESSURE,
+            util::STATUS_PITCH_BEND,
+        ] {
+            for channel in util::CHANNELS {
+                assert!(filter.allows(&message(status, channel)));
+            }
+        }
+        assert!(!filter.system);
+    }
+
+    #[test]
+    fn filter_only_channels_blocks_the_others() {
+        let filter = RouteFilter::default().only_channels([Channel::Two, Channel::Sixteen]);
+        assert_eq!(filter.channels, 1 << 1 | 1 << 15);
+        for channel in util::CHANNELS {
+            let allowed = matches!(channel, Channel::Two | Channel::Sixteen);
+            assert_eq!(
+                filter.allows(&message(util::STATUS_NOTE_ON, channel)),
+                allowed,
+                "{channel:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn filter_only_notes_blocks_everything_else() {
+        let filter = RouteFilter::default().only_notes();
+        assert!(filter.allows(&message(util::STATUS_NOTE_ON, Channel::One)));
+        assert!(filter.allows(&message(util::STATUS_NOTE_OFF, Channel::One)));
+        assert!(filter.allows(&message(util::STATUS_POLY_PRESSURE, Channel::One)));
+        assert!(!filter.allows(&message(util::STATUS_CONTROL_CHANGE, Channel::One)));
+        assert!(!filter.allows(&message(util::STATUS_PROGRAM_CHANGE, Channel::One)));
+        assert!(!filter.allows(&message(util::STATUS_CHANNEL_PRESSURE, Channel::One)));
+        assert!(!filter.allows(&message(util::STATUS_PITCH_BEND, Channel::One)));
+    }
+
+    #[test]
+    fn filter_checks_channel_and_kind_together() {
+        let filter = RouteFilter {
+            programs: false,
+            ..RouteFilter::default().only_channels([Channel::Ten])
+        };
+        assert!(filter.allows(&message(util::STATUS_NOTE_ON, Channel::Ten)));
+        assert!(!filter.allows(&message(util::STATUS_PROGRAM_CHANGE, Channel::Ten)));
+        assert!(!filter.allows(&message(util::STATUS_NOTE_ON, Channel::One)));
+        assert!(RouteFilter::default().with_system(true).system);
+    }
+
+    #[test]
+    fn default_remap_keeps_every_channel() {
+        let remap = ChannelRemap::default();
+        for channel in util::CHANNELS {
+            assert_eq!(remap.get(channel), channel);
+        }
+    }
+
+    #[test]
+    fn remap_moves_only_the_mapped_channel() {
+        let remap = ChannelRemap::default().map(Channel::One, Channel::Three);
+        assert_eq!(remap.get(Channel::One), Channel::Three);
+        assert_eq!(remap.get(Channel::Two), Channel::Two);
+        assert_eq!(remap.get(Channel::Three), Channel::Three);
+
+        let moved = remap.apply(message(util::STATUS_NOTE_ON, Channel::One));
+        assert_eq!(moved.channel(), Channel::Three);
+        assert_eq!(
+            util::voice_bytes(&moved)[1..],
+            util::voice_bytes(&message(util::STATUS_NOTE_ON, Channel::One))[1..]
+        );
+    }
+
+    #[test]
+    fn remap_all_to_moves_every_channel() {
+        let remap = ChannelRemap::all_to(Channel::Five);
+        for channel in util::CHANNELS {
+            let moved = remap.apply(message(util::STATUS_CONTROL_CHANGE, channel));
+            assert_eq!(moved.channel(), Channel::Five);
+        }
+    }
+
+    #[cfg(feature = "synth")]
+    #[test]
+    fn removing_the_last_route_closes_a_synths_channel() {
+        use bevy::prelude::Entity;
+
+        use super::{MidiRoute, MidiRouter};
+        use crate::data::MidiData;
+
+        let router = MidiRouter::<MidiData>::new(8);
+        let synth = Entity::from_raw_u32(1).unwrap();
+        let first = router.add_route(MidiRoute::to_synth(synth));
+        let second = router.add_route(MidiRoute::to_synth(synth));
+        assert_eq!(router.synth_channel_id(synth), Some(first));
+
+        assert!(router.remove_route(first));
+        assert_eq!(router.synth_channel_id(synth), Some(first));
+        assert!(router.remove_route(second));
+        assert_eq!(router.synth_channel_id(synth), None);
+
+        // routing again opens a new channel
+        let third = router.add_route(MidiRoute::to_synth(synth));
+        assert_eq!(router.synth_channel_id(synth), Some(third));
+        router.clear();
+        assert_eq!(router.synth_channel_id(synth), None);
+    }
+}
@@ -1,46 +1,156 @@
+use std::sync::{Arc, Mutex};
+
 use bevy::prelude::*;
 use midir::MidiInputPort;
 use midix::{
     UMicros,
     events::{FromLiveEventBytes, LiveEvent},
+    prelude::ChannelVoiceMessage,
 };
 use trotcast::Channel;
 
-use crate::input::{FromMidiInputData, MidiInputError};
+use crate::{
+    input::{FromMidiInputData, MidiInputError, MidiRouter},
+    util::{self, ActiveNotes, CHANNELS},
+};
+
+const SUSTAIN_PEDAL: u8 = 64;
 
-pub(crate) struct MidiInputConnectionHandler {
+pub(crate) struct MidiInputConnectionHandler<D: FromMidiInputData> {
     conn: midir::MidiInputConnection<()>,
+    state: Arc<ConnectionState<D>>,
+}
+
+/// Everything the connection callback needs, shared with the handler
+/// so that held notes can be released once the connection is closed.
+struct ConnectionState<D: FromMidiInputData> {
+    sender: Channel<D>,
+    router: MidiRouter<D>,
+    port_id: String,
+    held: Mutex<HeldInput>,
+}
+
+/// The notes and sustain pedals held down on the device
+#[derive(Default)]
+struct HeldInput {
+    notes: ActiveNotes,
+    sustain: u16,
+    last_timestamp: u64,
+}
+
+impl<D: FromMidiInputData> ConnectionState<D> {
+    fn receive(&self, timestamp: u64, data: &[u8]) {
+        let Ok(message) = LiveEvent::from_bytes(data) else {
+            return;
+        };
+        if let Some(voice) = message.channel_voice() {
+            self.held.lock().unwrap().track(timestamp, voice);
+        }
+        self.router
+            .dispatch(&self.port_id, timestamp, data, &message);
+        if let Err(e) = self
+            .sender
+            .send(D::from_midi_data(UMicros::new(timestamp), message))
+        {
+            warn!("Error sending MIDI data! {e:?}");
+        }
+    }
+
+    /// Send note offs and sustain pedal releases for everything still held down
+    fn release(&self) {
+        let (timestamp, messages) = self.held.lock().unwrap().release();
+        for message in messages {
+            util::with_voice_bytes(&message, |bytes| {
+                self.router.dispatch(
+                    &self.port_id,
+                    timestamp,
+                    bytes,
+                    &LiveEvent::ChannelVoice(message),
+                );
+            });
+            if let Err(e) = self.sender.send(D::from_midi_data(
+                UMicros::new(timestamp),
+                LiveEvent::ChannelVoice(message),
+            )) {
+                warn!("Error sending MIDI data! {e:?}");
+            }
+        }
+    }
 }
 
-impl MidiInputConnectionHandler {
-    pub fn new<D: FromMidiInputData>(
+impl HeldInput {
+    fn track(&mut self, timestamp: u64, message: &ChannelVoiceMessage) {
+        self.last_timestamp = timestamp;
+        self.notes.track(message);
+
+        let [status, controller, value] = util::voice_bytes(message);
+        if status & 0xF0 == util::STATUS_CONTROL_CHANGE && controller == SUSTAIN_PEDAL {
+            let bit = 1 << util::channel_index(message.channel());
+            if value >= 64 {
+                self.sustain |= bit;
+            } else {
+                self.sustain &= !bit;
+            }
+        }
+    }
+
+    fn release(&mut self) -> (u64, Vec<ChannelVoiceMessage>) {
+        let mut messages = self.notes.release_all();
+        let sustain = std::mem::take(&mut self.sustain);
+        messages.extend(
+            CHANNELS
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| sustain & (1 << index) != 0)
+                .filter_map(|(_, channel)| {
+                    util::voice_message(util::STATUS_CONTROL_CHANGE, channel, SUSTAIN_PEDAL, 0)
+                }),
+        );
+        (self.last_timestamp, messages)
+    }
+}
+
+impl<D: FromMidiInputData> MidiInputConnectionHandler<D> {
+    pub fn new(
         midir_input: midir::MidiInput,
         port: &MidiInputPort,
         port_name: &str,
         sender: Channel<D>,
+        router: MidiRouter<D>,
     ) -> Result<Self, MidiInputError> {
+        let state = Arc::new(ConnectionState {
+            sender,
+            router,
+            port_id: port.id(),
+            held: Mutex::new(HeldInput::default()),
+        });
         let conn = midir_input.connect(
             port,
             port_name,
             {
-                move |timestamp, data, _| {
-                    let Ok(message) = LiveEvent::from_bytes(data) else {
-                        return;
-                    };
-                    if let Err(e) = sender.send(D::from_midi_data(UMicros::new(timestamp), message))
-                    {
-                        warn!("Error sending MIDI data! {e:?}");
-                    }
-                }
+                let state = Arc::clone(&state);
+                move |timestamp, data, _| state.receive(timestamp, data)
             },
             (),
         )?;
 
-        Ok(Self { conn })
+        Ok(Self { conn, state })
+    }
+
+    /// The id of the connected port
+    pub fn port_id(&self) -> &str {
+        &self.state.port_id
+    }
+
+    /// Release every note and sustain pedal held down on the device
+    pub fn release_held(&self) {
+        self.state.release();
     }
 
+    /// Close the connection. Anything still held down on the device is released.
     pub fn close(self) -> midir::MidiInput {
         let (listener, _) = self.conn.close();
+        self.state.release();
         listener
     }
 }
@@ -1,15 +1,19 @@
 mod connection;
 pub(crate) use connection::*;
 
+use crate::input::FromMidiInputData;
+
 // you can't actually have multiple MidiInputs on one device, it's really strange.
-pub enum MidiInputState {
+pub enum MidiInputState<D: FromMidiInputData> {
     Listening(midir::MidiInput),
-    Active(MidiInputConnectionHandler),
+    Active(MidiInputConnectionHandler<D>),
 }
 
 /// SAFETY: This applies to linux alsa.
 ///
-/// There is only one instance of MidiInput at any time using this crate.
+/// There is only one instance of MidiInput at any time using this crate, besides the
+/// [`PortWatcher`], which is a separate client that shares nothing with this one and is
+/// never used from two threads at once.
 ///
 /// However, this may not satisfy the requirements for safety. If another instance of
 /// MidiInput exists in the external program, then UB is possible.
@@ -17,5 +21,37 @@ pub enum MidiInputState {
 /// Therefore, the assumption is, that when using this crate, that the user
 /// will NOT instantiate another [`midir::MidiInput`] at any point while
 /// [`MidiInput`] has been inserted as a resource
-unsafe impl Sync for MidiInputState {}
-unsafe impl Send for MidiInputState {}
+unsafe impl<D: FromMidiInputData> Sync for MidiInputState<D> {}
+unsafe impl<D: FromMidiInputData> Send for MidiInputState<D> {}
+
+/// A second client used to list ports while the main one is connected,
+/// to notice when the connected device goes away.
+///
+/// Listing ports needs a `MidiInput`, and the main one is taken by the connection
+/// until it is closed, so this has its own.
+pub struct PortWatcher(midir::MidiInput);
+
+impl PortWatcher {
+    pub fn new(client_name: &str) -> Option<Self> {
+        match midir::MidiInput::new(client_name) {
+            Ok(input) => Some(Self(input)),
+            Err(e) => {
+                bevy::log::warn!("Failed to create a client to watch MIDI input ports! {e:?}");
+                None
+            }
+        }
+    }
+
+    /// True if a port with this id can be found
+    pub fn has_port(&self, id: &str) -> bool {
+        self.0.ports().iter().any(|port| port.id() == id)
+    }
+}
+
+/// SAFETY: The watcher opens its own ALSA sequencer client, so it shares no state with the
+/// [`MidiInputState`] client. A client may be moved between threads as long as it is only
+/// used from one at a time, which is guaranteed by keeping the watcher in a [`SyncCell`]
+/// that only hands it out through `&mut`.
+///
+/// [`SyncCell`]: bevy::platform::cell::SyncCell
+unsafe impl Send for PortWatcher {}
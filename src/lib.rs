@@ -119,6 +119,7 @@ use bevy::app::Plugin;
 use crate::{
     data::{MidiData, MidiDataSettings},
     input::{FromMidiInputData, MidiInputSettings},
+    output::MidiOutputSettings,
 };
 
 /// MIDI input handling and event processing.
@@ -127,9 +128,24 @@ use crate::{
 /// and converting it into Bevy-compatible events.
 pub mod input;
 
+/// MIDI output handling.
+///
+/// This module provides connections to external MIDI devices, and a
+/// [`MidiOutputSink`](crate::output::MidiOutputSink) to play synth commands on them.
+pub mod output;
+
 /// Common implementations of [`FromMidiInputData`]
 pub mod data;
 
+/// Contains the [`MidiPanic`](crate::panic::MidiPanic) message to silence stuck notes.
+pub mod panic;
+
+/// Contains [`SynthCommands`](crate::commands::SynthCommands), the queue of messages for a
+/// synth or output sink.
+pub mod commands;
+
+mod util;
+
 /// Contains the [`MidiAssetsPlugin`](crate::assets::MidiAssetsPlugin) and other types.
 #[cfg(feature = "assets")]
 pub mod assets;
@@ -138,6 +154,10 @@ pub mod assets;
 #[cfg(feature = "synth")]
 pub mod synth;
 
+/// Contains the [`SongPlayer`](crate::playback::SongPlayer) and other types.
+#[cfg(feature = "assets")]
+pub mod playback;
+
 /// Main plugin for integrating MIDI functionality into your Bevy application.
 ///
 /// This plugin sets up MIDI input handling and optionally enables asset loading
@@ -147,6 +167,8 @@ pub mod synth;
 pub struct MidiPlugin<D: FromMidiInputData = MidiData> {
     /// Configuration for MIDI input devices and connections.
     pub input_settings: MidiInputSettings,
+    /// Configuration for MIDI output devices and connections.
+    pub output_settings: MidiOutputSettings,
     /// Settings specific to how MIDI data is processed and converted.
     pub data_settings: D::Settings,
     /// By default, the synth feature is enabled.
@@ -159,6 +181,7 @@ impl Default for MidiPlugin {
     fn default() -> Self {
         Self {
             input_settings: MidiInputSettings::default(),
+            output_settings: MidiOutputSettings::default(),
             data_settings: MidiDataSettings::default(),
             #[cfg(feature = "synth")]
             enable_synth: true,
@@ -167,6 +190,8 @@ impl Default for MidiPlugin {
 }
 impl<D: FromMidiInputData> MidiPlugin<D> {
     /// Creates a new MidiPlugin with the specified input and data processing settings.
+    ///
+    /// Output settings are left at their defaults.
     pub fn new(
         input_settings: MidiInputSettings,
         data_settings: D::Settings,
@@ -174,6 +199,7 @@ impl<D: FromMidiInputData> MidiPlugin<D> {
     ) -> Self {
         Self {
             input_settings,
+            output_settings: MidiOutputSettings::default(),
             data_settings,
             #[cfg(feature = "synth")]
             enable_synth,
@@ -184,10 +210,15 @@ impl<D: FromMidiInputData> MidiPlugin<D> {
 impl<D: FromMidiInputData> Plugin for MidiPlugin<D> {
     fn build(&self, app: &mut bevy::app::App) {
         input::midi_io_plugin_inner::<D>(self.input_settings.clone(), &self.data_settings, app);
+        output::midi_output_plugin_inner(self.output_settings.clone(), app);
+        app.add_plugins(panic::MidiPanicPlugin);
 
         #[cfg(feature = "assets")]
         app.add_plugins(crate::assets::MidiAssetsPlugin);
 
+        #[cfg(feature = "assets")]
+        app.add_plugins(crate::playback::SongPlaybackPlugin);
+
         #[cfg(feature = "synth")]
         if self.enable_synth {
             app.add_plugins(crate::synth::SynthPlugin::<D>::new());
@@ -202,12 +233,21 @@ impl<D: FromMidiInputData> Plugin for MidiPlugin<D> {
 pub mod prelude {
     pub use crate::input::*;
 
+    pub use crate::output::*;
+
+    pub use crate::panic::*;
+
+    pub use crate::commands::*;
+
     #[cfg(feature = "assets")]
     pub use crate::assets::*;
 
     #[cfg(feature = "synth")]
     pub use crate::synth::*;
 
+    #[cfg(feature = "assets")]
+    pub use crate::playback::*;
+
     pub use crate::MidiPlugin;
 
     pub use midix::prelude::*;
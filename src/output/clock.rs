@@ -0,0 +1,437 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::output::MidiOutputHandle;
+
+/// MIDI clock pulses per quarter note
+pub const CLOCK_PPQN: u64 = 24;
+
+const TIMING_CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+const SONG_POSITION_POINTER: u8 = 0xF2;
+
+/// How long before a pulse the timing thread stops sleeping and starts spinning
+const SPIN_WINDOW: Duration = Duration::from_millis(1);
+
+/// What drives the transport of the [`MidiClock`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The clock is only controlled through [`MidiClock`]'s methods
+    #[default]
+    Manual,
+    /// The clock starts, stops and locates along with a [`SongPlayer`](crate::playback::SongPlayer).
+    ///
+    /// The clock follows the song's tempo, or 120 BPM if it has no tempo map, multiplied by
+    /// the player's [rate](crate::playback::SongPlayer::rate).
+    #[cfg(feature = "assets")]
+    Song(Entity),
+}
+
+enum ClockCommand {
+    Start,
+    Stop,
+    Continue,
+    Locate(u16),
+}
+
+struct ClockShared {
+    /// The length of a pulse in nanoseconds, as the bits of an `f64`
+    nanos_per_pulse: AtomicU64,
+    running: AtomicBool,
+    pulses: AtomicU64,
+    targets: Mutex<Vec<MidiOutputHandle>>,
+    shutdown: AtomicBool,
+}
+
+impl ClockShared {
+    fn nanos_per_pulse(&self) -> f64 {
+        f64::from_bits(self.nanos_per_pulse.load(Ordering::Relaxed))
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        for target in self.targets.lock().unwrap().iter() {
+            // disconnected targets are expected, the port may come back.
+            let _ = target.send_bytes(bytes);
+        }
+    }
+
+    /// Send a Song Position Pointer and move the pulse count to it
+    fn locate(&self, sixteenths: u16) {
+        self.send(&[
+            SONG_POSITION_POINTER,
+            (sixteenths & 0x7F) as u8,
+            (sixteenths >> 7) as u8,
+        ]);
+        self.pulses
+            .store(sixteenths as u64 * CLOCK_PPQN / 4, Ordering::Relaxed);
+    }
+}
+
+/// Resource that makes the app a MIDI clock master.
+///
+/// Timing Clock (24 pulses per quarter note), Start, Stop, Continue and Song Position Pointer
+/// are sent to every added output. Pulses are generated on a dedicated thread, so they stay
+/// steady when the frame rate drops. The thread is only spawned once an output is added or
+/// the clock is started.
+///
+/// By default, the clock is controlled manually. Use [`MidiClock::follow`] to drive it
+/// from a playing song instead.
+#[derive(Resource)]
+pub struct MidiClock {
+    shared: Arc<ClockShared>,
+    /// Sends to the timing thread, once it has been spawned
+    commands: Option<mpsc::Sender<ClockCommand>>,
+    bpm: f64,
+    pub(crate) source: ClockSource,
+}
+
+impl Default for MidiClock {
+    fn default() -> Self {
+        Self::new(120.)
+    }
+}
+
+impl MidiClock {
+    /// Create a stopped clock. Its timing thread is spawned when it's first needed.
+    pub fn new(bpm: f64) -> Self {
+        let shared = Arc::new(ClockShared {
+            nanos_per_pulse: AtomicU64::new(nanos_per_pulse(bpm).to_bits()),
+            running: AtomicBool::new(false),
+            pulses: AtomicU64::new(0),
+            targets: Mutex::new(Vec::new()),
+            shutdown: AtomicBool::new(false),
+        });
+
+        Self {
+            shared,
+            commands: None,
+            bpm,
+            source: ClockSource::Manual,
+        }
+    }
+
+    /// The sender to the timing thread, spawning the thread if it isn't running yet
+    fn commands(&mut self) -> &mpsc::Sender<ClockCommand> {
+        self.commands.get_or_insert_with(|| {
+            let (commands, rx) = mpsc::channel();
+            let thread_shared = Arc::clone(&self.shared);
+            thread::Builder::new()
+                .name("bevy_midix clock".to_string())
+                .spawn(move || run_clock(thread_shared, rx))
+                .expect("Failed to spawn the MIDI clock thread");
+            commands
+        })
+    }
+
+    /// Send a command to the timing thread. Before the thread exists, the clock is stopped,
+    /// so anything but Start and Continue is handled here without spawning it.
+    fn send(&mut self, command: ClockCommand) {
+        if self.commands.is_none() {
+            match command {
+                ClockCommand::Stop => return self.shared.send(&[STOP]),
+                ClockCommand::Locate(sixteenths) => return self.shared.locate(sixteenths),
+                ClockCommand::Start | ClockCommand::Continue => {}
+            }
+        }
+        let _ = self.commands().send(command);
+    }
+
+    /// Send the clock to this output
+    pub fn add_output(&mut self, handle: MidiOutputHandle) {
+        self.shared.targets.lock().unwrap().push(handle);
+        self.commands();
+    }
+
+    /// Stop sending the clock to any output
+    pub fn clear_outputs(&mut self) {
+        self.shared.targets.lock().unwrap().clear();
+    }
+
+    /// Set what drives the transport of the clock
+    pub fn follow(&mut self, source: ClockSource) {
+        self.source = source;
+    }
+
+    /// What drives the transport of the clock
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// The current tempo, in beats per minute
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Set the tempo, in beats per minute. Takes effect on the next pulse.
+    pub fn set_bpm(&mut self, bpm: f64) {
+        if bpm <= 0. || bpm == self.bpm {
+            return;
+        }
+        self.bpm = bpm;
+        self.shared
+            .nanos_per_pulse
+            .store(nanos_per_pulse(bpm).to_bits(), Ordering::Relaxed);
+    }
+
+    /// True if pulses are being sent
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Relaxed)
+    }
+
+    /// The number of pulses sent since the start of the song
+    pub fn pulses(&self) -> u64 {
+        self.shared.pulses.load(Ordering::Relaxed)
+    }
+
+    /// Send Start and begin pulsing from the start of the song
+    pub fn start(&mut self) {
+        self.send(ClockCommand::Start);
+    }
+
+    /// Send Stop and stop pulsing. The position is kept.
+    pub fn stop(&mut self) {
+        self.send(ClockCommand::Stop);
+    }
+
+    /// Send Continue and resume pulsing from the current position
+    pub fn resume(&mut self) {
+        self.send(ClockCommand::Continue);
+    }
+
+    /// Send a Song Position Pointer. The position is in sixteenth notes.
+    ///
+    /// Receivers should only be located while the clock is stopped.
+    pub fn locate(&mut self, sixteenths: u16) {
+        self.send(ClockCommand::Locate(sixteenths.min(0x3FFF)));
+    }
+}
+
+impl Drop for MidiClock {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+fn nanos_per_pulse(bpm: f64) -> f64 {
+    60_000_000_000. / (bpm * CLOCK_PPQN as f64)
+}
+
+/// When the timing thread sends each pulse.
+///
+/// Pulses are timed from the pulse the clock started or last changed tempo on, rather than
+/// by adding up rounded periods, so the clock doesn't drift from its tempo.
+struct PulseSchedule {
+    /// The time of the pulse the schedule counts from
+    anchor: Instant,
+    /// The number of pulses since the anchor
+    count: u64,
+    nanos_per_pulse: f64,
+}
+
+impl PulseSchedule {
+    fn new(anchor: Instant, nanos_per_pulse: f64) -> Self {
+        Self {
+            anchor,
+            count: 0,
+            nanos_per_pulse,
+        }
+    }
+
+    /// When the next pulse is due
+    fn next_pulse(&self) -> Instant {
+        let offset = (self.count as f64 * self.nanos_per_pulse).round() as u64;
+        self.anchor + Duration::from_nanos(offset)
+    }
+
+    /// Move on to the pulse after the one that is due. A new tempo starts from the pulse
+    /// that is due.
+    fn advance(&mut self, nanos_per_pulse: f64) {
+        if nanos_per_pulse != self.nanos_per_pulse {
+            *self = Self::new(self.next_pulse(), nanos_per_pulse);
+        }
+        self.count += 1;
+    }
+}
+
+fn run_clock(shared: Arc<ClockShared>, commands: mpsc::Receiver<ClockCommand>) {
+    let mut schedule = PulseSchedule::new(Instant::now(), shared.nanos_per_pulse());
+    while !shared.shutdown.load(Ordering::Relaxed) {
+        let running = shared.running.load(Ordering::Relaxed);
+        let command = if running {
+            commands.try_recv().ok()
+        } else {
+            match commands.recv_timeout(Duration::from_millis(10)) {
+                Ok(command) => Some(command),
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        };
+
+        if let Some(command) = command {
+            match command {
+                ClockCommand::Start => {
+                    shared.pulses.store(0, Ordering::Relaxed);
+                    shared.send(&[START]);
+                    shared.running.store(true, Ordering::Relaxed);
+                    schedule = PulseSchedule::new(Instant::now(), shared.nanos_per_pulse());
+                }
+                ClockCommand::Continue => {
+                    shared.send(&[CONTINUE]);
+                    shared.running.store(true, Ordering::Relaxed);
+                    schedule = PulseSchedule::new(Instant::now(), shared.nanos_per_pulse());
+                }
+                ClockCommand::Stop => {
+                    shared.send(&[STOP]);
+                    shared.running.store(false, Ordering::Relaxed);
+                }
+                ClockCommand::Locate(sixteenths) => shared.locate(sixteenths),
+            }
+            continue;
+        }
+
+        let now = Instant::now();
+        let next_pulse = schedule.next_pulse();
+        if now < next_pulse {
+            let remaining = next_pulse - now;
+            if remaining > SPIN_WINDOW {
+                thread::sleep(remaining - SPIN_WINDOW);
+            } else {
+                thread::yield_now();
+            }
+            continue;
+        }
+
+        shared.send(&[TIMING_CLOCK]);
+        shared.pulses.fetch_add(1, Ordering::Relaxed);
+
+        let nanos_per_pulse = shared.nanos_per_pulse();
+        schedule.advance(nanos_per_pulse);
+        // if we've fallen far behind (such as the process being suspended), don't burst.
+        let period = Duration::from_nanos(nanos_per_pulse as u64);
+        if now.duration_since(schedule.next_pulse()) > period * CLOCK_PPQN as u32 {
+            schedule = PulseSchedule::new(now, nanos_per_pulse);
+            schedule.advance(nanos_per_pulse);
+        }
+    }
+}
+
+/// System that drives the clock's transport from a [`SongPlayer`](crate::playback::SongPlayer)
+/// when it follows [`ClockSource::Song`]
+#[cfg(feature = "assets")]
+pub(crate) fn follow_song_clock(
+    mut clock: ResMut<MidiClock>,
+    players: Query<&crate::playback::SongPlayer>,
+    mut last: Local<Option<(crate::playback::PlaybackState, u64)>>,
+) {
+    use crate::playback::PlaybackState;
+
+    let ClockSource::Song(entity) = clock.source else {
+        *last = None;
+        return;
+    };
+    let Ok(player) = players.get(entity) else {
+        return;
+    };
+
+    // the song's tempo, or 120 BPM without a tempo map, sped up by the player's rate
+    // as it is now, part way through a ramp
+    let tempo_map = player.song().tempo_map();
+    clock.set_bpm(tempo_map.bpm_at(player.position()) * player.rate());
+
+    let state = player.state();
+    let last_state = last.map(|(state, _)| state);
+    let seeked = last.is_some_and(|(_, seeks)| seeks != player.seeks);
+    *last = Some((state, player.seeks));
+    if last_state == Some(state) && !seeked {
+        return;
+    }
+
+    let sixteenths = || (tempo_map.quarters_at(player.position()) * 4.) as u16;
+
+    match state {
+        PlaybackState::Playing if player.position() == 0 => clock.start(),
+        PlaybackState::Playing => {
+            let position = sixteenths();
+            if last_state == Some(PlaybackState::Playing) {
+                // receivers should only be located while stopped
+                clock.stop();
+            }
+            clock.locate(position);
+            clock.resume();
+        }
+        PlaybackState::Paused | PlaybackState::Stopped => {
+            if last_state == Some(PlaybackState::Playing) {
+                clock.stop();
+            }
+            if seeked {
+                let position = sixteenths();
+                clock.locate(position);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{CLOCK_PPQN, PulseSchedule, nanos_per_pulse};
+
+    /// How long after the start each of the first `pulses` pulses is due
+    fn pulse_offsets(schedule: &mut PulseSchedule, start: Instant, pulses: u64) -> Vec<Duration> {
+        let nanos = schedule.nanos_per_pulse;
+        (0..pulses)
+            .map(|_| {
+                let offset = schedule.next_pulse() - start;
+                schedule.advance(nanos);
+                offset
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pulses_follow_the_tempo_without_drifting() {
+        for bpm in [120., 97., 133.3] {
+            let start = Instant::now();
+            let mut schedule = PulseSchedule::new(start, nanos_per_pulse(bpm));
+            // a minute of pulses
+            let beats = bpm.floor() as u64;
+            let offsets = pulse_offsets(&mut schedule, start, beats * CLOCK_PPQN + 1);
+
+            for (beat, offset) in offsets.iter().step_by(CLOCK_PPQN as usize).enumerate() {
+                let expected = Duration::from_secs_f64(beat as f64 * 60. / bpm);
+                assert!(
+                    offset.abs_diff(expected) <= Duration::from_nanos(1),
+                    "beat {beat} at {bpm} BPM was at {offset:?}, expected {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_new_tempo_starts_from_the_due_pulse() {
+        let start = Instant::now();
+        let mut schedule = PulseSchedule::new(start, nanos_per_pulse(120.));
+        pulse_offsets(&mut schedule, start, CLOCK_PPQN);
+        assert_eq!(schedule.next_pulse() - start, Duration::from_millis(500));
+
+        // a quarter note at 60 BPM takes a second
+        schedule.advance(nanos_per_pulse(60.));
+        let offsets = pulse_offsets(&mut schedule, start, CLOCK_PPQN);
+        assert_eq!(
+            offsets[CLOCK_PPQN as usize - 1],
+            Duration::from_millis(1500)
+        );
+    }
+}
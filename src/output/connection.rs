@@ -0,0 +1,69 @@
+use std::sync::{Arc, Mutex};
+
+use midix::prelude::ChannelVoiceMessage;
+
+use crate::{output::MidiOutputError, util};
+
+/// A cloneable handle to a connection made by [`MidiOutput`](super::MidiOutput).
+///
+/// Handles are created per port id and outlive the connection itself: if the port
+/// is disconnected, sending through the handle returns an error, and if the port is
+/// connected again, the same handle starts sending again.
+///
+/// Because the handle is `Send + Sync`, it can be moved off of the main schedule,
+/// such as into a timing thread or an input callback.
+#[derive(Clone, Default)]
+pub struct MidiOutputHandle {
+    conn: Arc<Mutex<Option<OutputConnection>>>,
+}
+
+struct OutputConnection(midir::MidiOutputConnection);
+
+/// SAFETY: This applies to linux alsa.
+///
+/// The connection is only ever accessed behind the handle's mutex, so it is never
+/// used from two threads at the same time.
+unsafe impl Send for OutputConnection {}
+
+impl MidiOutputHandle {
+    /// True if this handle currently has an open connection
+    pub fn is_connected(&self) -> bool {
+        self.conn.lock().unwrap().is_some()
+    }
+
+    /// Send raw bytes to the port
+    ///
+    /// # Errors
+    /// - If the handle is not connected
+    /// - If the underlying connection fails to send
+    pub fn send_bytes(&self, bytes: &[u8]) -> Result<(), MidiOutputError> {
+        let mut conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_mut() else {
+            return Err(MidiOutputError::invalid("Cannot send: not connected!"));
+        };
+        conn.0.send(bytes)?;
+        Ok(())
+    }
+
+    /// Send a channel voice message to the port
+    ///
+    /// # Errors
+    /// - If the handle is not connected
+    /// - If the underlying connection fails to send
+    pub fn send(&self, message: ChannelVoiceMessage) -> Result<(), MidiOutputError> {
+        util::with_voice_bytes(&message, |bytes| self.send_bytes(bytes))
+    }
+
+    pub(crate) fn replace(&self, conn: midir::MidiOutputConnection) {
+        let previous = self.conn.lock().unwrap().replace(OutputConnection(conn));
+        if let Some(previous) = previous {
+            previous.0.close();
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        if let Some(conn) = self.conn.lock().unwrap().take() {
+            conn.0.close();
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+use midir::{ConnectError, ConnectErrorKind, SendError};
+use thiserror::Error;
+
+/// The [`Error`] type for midi output operations, accessible as an [`Event`].
+#[derive(Debug, Event, Error)]
+pub enum MidiOutputError {
+    /// There was something wrong connecting to the output
+    #[error("Couldn't connect to output port: {0}")]
+    ConnectionError(ConnectErrorKind),
+
+    /// The port, passed by id, was not found.
+    #[error("Port not found (id: {0})")]
+    PortNotFound(String),
+
+    /// The message could not be sent to the port
+    #[error("Couldn't send message: {0}")]
+    SendError(#[from] SendError),
+
+    /// Invalid state
+    #[error("Invalid State: {0}")]
+    InvalidState(String),
+}
+
+impl MidiOutputError {
+    pub(crate) fn invalid(msg: impl ToString) -> Self {
+        Self::InvalidState(msg.to_string())
+    }
+    pub(crate) fn port_not_found(id: impl Into<String>) -> Self {
+        Self::PortNotFound(id.into())
+    }
+}
+
+impl From<ConnectError<midir::MidiOutput>> for MidiOutputError {
+    fn from(value: ConnectError<midir::MidiOutput>) -> Self {
+        Self::ConnectionError(value.kind())
+    }
+}
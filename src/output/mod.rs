@@ -0,0 +1,177 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+mod settings;
+use midir::MidiOutputPort;
+use midix::prelude::ChannelVoiceMessage;
+pub use settings::*;
+
+mod error;
+pub use error::*;
+
+mod connection;
+pub use connection::*;
+
+mod clock;
+pub use clock::*;
+
+mod plugin;
+pub use plugin::*;
+
+mod sink;
+pub use sink::*;
+
+/// The central resource for interacting with midi outputs
+///
+/// `MidiOutput` does many things:
+/// - Fetches a list of ports with connected midi devices
+/// - Allows one to connect to one or more midi devices and send them messages
+/// - Close those connections and search for other devices
+///
+/// Unlike [`MidiInput`](crate::input::MidiInput), many ports may be connected at once.
+/// Connections are identified by their port id.
+#[derive(Resource)]
+pub struct MidiOutput {
+    listener: OutputListener,
+    ports: Vec<MidiOutputPort>,
+    connections: HashMap<String, MidiOutputHandle>,
+    client_name: String,
+    port_name: String,
+}
+
+struct OutputListener(midir::MidiOutput);
+
+/// SAFETY: This applies to linux alsa.
+///
+/// The listener is only used to enumerate ports from the main world. See the
+/// notes on [`MidiInput`](crate::input::MidiInput) for the same assumption.
+unsafe impl Sync for OutputListener {}
+unsafe impl Send for OutputListener {}
+
+impl MidiOutput {
+    /// Creates a new midi output with the provided settings. This is done automatically
+    /// by [`MidiOutputPlugin`].
+    pub fn new(settings: MidiOutputSettings) -> Self {
+        let listener = match midir::MidiOutput::new(&settings.client_name) {
+            Ok(output) => output,
+            Err(e) => {
+                panic!("Error initializing midi output! {e:?}");
+            }
+        };
+
+        let ports = listener.ports();
+        Self {
+            listener: OutputListener(listener),
+            ports,
+            connections: HashMap::default(),
+            client_name: settings.client_name,
+            port_name: settings.port_name,
+        }
+    }
+
+    /// Return a list of ports updated since calling [`MidiOutput::new`] or
+    /// [`MidiOutput::refresh_ports`]
+    pub fn ports(&self) -> &[MidiOutputPort] {
+        &self.ports
+    }
+
+    /// Refreshes the available port list
+    pub fn refresh_ports(&mut self) -> &[MidiOutputPort] {
+        self.ports = self.listener.0.ports();
+        &self.ports
+    }
+
+    /// Attempts to connects to the port at the given index returned by [`MidiOutput::ports`]
+    ///
+    /// # Errors
+    /// - If the index is out of bounds
+    /// - An output connection cannot be established
+    pub fn connect_to_index(&mut self, index: usize) -> Result<(), MidiOutputError> {
+        let Some(port) = self.ports.get(index).cloned() else {
+            return Err(MidiOutputError::port_not_found(format!(
+                "Port was not found at {index}!"
+            )));
+        };
+        self.connect_to_port(&port)
+    }
+
+    /// Attempts to connects to the passed port.
+    ///
+    /// If the port is already connected, the previous connection is replaced.
+    ///
+    /// # Errors
+    /// - An output connection cannot be established
+    pub fn connect_to_port(&mut self, port: &MidiOutputPort) -> Result<(), MidiOutputError> {
+        let client = midir::MidiOutput::new(&self.client_name).map_err(MidiOutputError::invalid)?;
+        let conn = client.connect(port, &self.port_name)?;
+
+        self.handle(port.id()).replace(conn);
+        Ok(())
+    }
+
+    /// Attempts to connects to the passed port id
+    ///
+    /// # Errors
+    /// - If the port ID cannot be currently found
+    /// - An output connection cannot be established
+    pub fn connect_to_id(&mut self, id: String) -> Result<(), MidiOutputError> {
+        let Some(port) = self.listener.0.find_port_by_id(id.clone()) else {
+            return Err(MidiOutputError::port_not_found(id));
+        };
+        self.connect_to_port(&port)
+    }
+
+    /// Returns the handle for a port id, creating a disconnected one if it doesn't exist.
+    ///
+    /// The handle will send to the port whenever it is connected.
+    pub fn handle(&mut self, id: impl Into<String>) -> MidiOutputHandle {
+        self.connections.entry(id.into()).or_default().clone()
+    }
+
+    /// Returns the handle for a port id if one has been created
+    pub fn get_handle(&self, id: &str) -> Option<&MidiOutputHandle> {
+        self.connections.get(id)
+    }
+
+    /// True if the port with this id is currently connected
+    pub fn is_connected(&self, id: &str) -> bool {
+        self.connections
+            .get(id)
+            .is_some_and(MidiOutputHandle::is_connected)
+    }
+
+    /// Iterate over the ids and handles of all connected ports
+    pub fn connections(&self) -> impl Iterator<Item = (&str, &MidiOutputHandle)> {
+        self.connections
+            .iter()
+            .filter(|(_, handle)| handle.is_connected())
+            .map(|(id, handle)| (id.as_str(), handle))
+    }
+
+    /// Send a message to the port with this id
+    ///
+    /// # Errors
+    /// - If the port is not connected
+    /// - If the underlying connection fails to send
+    pub fn send(&self, id: &str, message: ChannelVoiceMessage) -> Result<(), MidiOutputError> {
+        let Some(handle) = self.connections.get(id) else {
+            return Err(MidiOutputError::port_not_found(id));
+        };
+        handle.send(message)
+    }
+
+    /// Disconnects from the port with this id
+    ///
+    /// Does nothing if the port is not connected.
+    pub fn disconnect(&mut self, id: &str) {
+        if let Some(handle) = self.connections.get(id) {
+            handle.close();
+        }
+    }
+
+    /// Disconnects from every connected port
+    pub fn disconnect_all(&mut self) {
+        for handle in self.connections.values() {
+            handle.close();
+        }
+    }
+}
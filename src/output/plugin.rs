@@ -0,0 +1,46 @@
+use bevy::prelude::*;
+
+use crate::{
+    commands::ProcessSynthCommands,
+    output::{MidiClock, MidiOutput, MidiOutputSettings},
+};
+
+/// Plugin for managing MIDI output connections.
+///
+/// It's typically used internally by `MidiPlugin`, but can be used directly if
+/// you only need to send MIDI to external devices.
+#[derive(Default)]
+pub struct MidiOutputPlugin {
+    /// Settings for MIDI output device configuration.
+    pub settings: MidiOutputSettings,
+}
+
+impl MidiOutputPlugin {
+    /// Creates a new MidiOutputPlugin with the specified settings.
+    pub fn new(settings: MidiOutputSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl Plugin for MidiOutputPlugin {
+    fn build(&self, app: &mut App) {
+        midi_output_plugin_inner(self.settings.clone(), app);
+    }
+}
+
+pub(crate) fn midi_output_plugin_inner(settings: MidiOutputSettings, app: &mut App) {
+    app.insert_resource(MidiOutput::new(settings))
+        .init_resource::<MidiClock>();
+
+    app.configure_sets(Update, ProcessSynthCommands)
+        .add_systems(
+            Update,
+            super::sink::send_to_output_sinks.in_set(ProcessSynthCommands),
+        );
+
+    #[cfg(feature = "assets")]
+    app.add_systems(
+        Update,
+        super::clock::follow_song_clock.after(crate::playback::SongPlayback),
+    );
+}
@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+
+/// Settings for [`MidiOutputPlugin`](crate::prelude::MidiOutputPlugin).
+#[derive(Resource, Clone, Debug)]
+pub struct MidiOutputSettings {
+    /// The name of the sending client
+    pub client_name: String,
+
+    /// The port name of the sending client.
+    ///
+    /// This is appended to the port name of a connection essentially.
+    pub port_name: String,
+}
+
+impl Default for MidiOutputSettings {
+    /// Assigns client name and port name to `bevy_midix`
+    fn default() -> Self {
+        Self {
+            client_name: "bevy_midix".to_string(),
+            port_name: "bevy_midix".to_string(),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use crate::{commands::SynthCommands, output::MidiOutput};
+
+/// Component that sends this entity's [`SynthCommands`] to an external [`MidiOutput`] port.
+///
+/// This is an alternative to [`SynthPlayer`](crate::synth::SynthPlayer): the same code that
+/// writes to [`SynthCommands`] can drive either the soundfont synthesizer or a hardware
+/// module, depending on which component the entity has.
+///
+/// Commands are dropped while the port is not connected.
+#[derive(Component, Debug, Clone)]
+#[require(SynthCommands)]
+pub struct MidiOutputSink {
+    pub(crate) port_id: String,
+}
+
+impl MidiOutputSink {
+    /// Creates a sink sending to the port with this id.
+    ///
+    /// See [`MidiOutput::connect_to_id`].
+    pub fn new(port_id: impl Into<String>) -> Self {
+        Self {
+            port_id: port_id.into(),
+        }
+    }
+
+    /// The id of the port this sink sends to
+    pub fn port_id(&self) -> &str {
+        &self.port_id
+    }
+
+    /// Send to a different port
+    pub fn set_port_id(&mut self, port_id: impl Into<String>) {
+        self.port_id = port_id.into();
+    }
+}
+
+/// System that drains the commands of every sink into its output port
+pub(crate) fn send_to_output_sinks(
+    output: Res<MidiOutput>,
+    mut sinks: Query<(&MidiOutputSink, &mut SynthCommands)>,
+) {
+    for (sink, mut commands) in &mut sinks {
+        if commands.queue.is_empty() {
+            continue;
+        }
+        let pending = commands.take();
+
+        if !output.is_connected(&sink.port_id) {
+            continue;
+        }
+
+        for command in pending {
+            if let Err(e) = output.send(&sink.port_id, command) {
+                warn!("Error sending MIDI data to {}! {e:?}", sink.port_id);
+                break;
+            }
+        }
+    }
+}
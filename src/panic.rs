@@ -0,0 +1,209 @@
+use bevy::{platform::collections::HashSet, prelude::*, window::WindowFocused};
+use midix::prelude::*;
+
+use crate::{
+    commands::{ProcessSynthCommands, SynthCommands},
+    output::{MidiOutput, MidiOutputSink},
+    util::{self, CHANNELS},
+};
+
+const ALL_SOUND_OFF: u8 = 120;
+const RESET_ALL_CONTROLLERS: u8 = 121;
+const ALL_NOTES_OFF: u8 = 123;
+
+/// Message that silences stuck notes.
+///
+/// All Notes Off, All Sound Off and Reset All Controllers are sent on all 16 channels,
+/// or only on [`MidiPanic::channels`].
+#[derive(Message, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MidiPanic {
+    /// The entity whose [`SynthCommands`](crate::commands::SynthCommands) should be silenced.
+    ///
+    /// If `None`, every synth, output sink and connected [`MidiOutput`] port is silenced.
+    /// Ports that a [`MidiOutputSink`] sends to are silenced once, through the sink.
+    pub target: Option<Entity>,
+    /// The channels to silence, with a bit for each channel from channel 1 in the lowest bit.
+    ///
+    /// If `None`, every channel is silenced.
+    pub channels: Option<u16>,
+}
+
+impl MidiPanic {
+    /// Silence every synth and output
+    pub fn all() -> Self {
+        Self {
+            target: None,
+            channels: None,
+        }
+    }
+
+    /// Silence a single entity
+    pub fn entity(entity: Entity) -> Self {
+        Self {
+            target: Some(entity),
+            channels: None,
+        }
+    }
+
+    /// Silence only some channels of a single entity, leaving the others playing
+    pub fn entity_channels(entity: Entity, channels: &[Channel]) -> Self {
+        let mask = channels.iter().fold(0u16, |mask, channel| {
+            mask | 1 << util::channel_index(*channel)
+        });
+        Self {
+            target: Some(entity),
+            channels: Some(mask),
+        }
+    }
+
+    /// The messages sent by this panic
+    pub fn messages(&self) -> impl Iterator<Item = ChannelVoiceMessage> + use<> {
+        let mask = self.channels.unwrap_or(u16::MAX);
+        panic_messages_on(
+            CHANNELS
+                .into_iter()
+                .filter(move |channel| mask & 1 << util::channel_index(*channel) != 0),
+        )
+    }
+}
+
+/// Decides when a [`MidiPanic`] is sent automatically
+#[derive(Resource, Clone, Debug, Default)]
+pub struct MidiPanicSettings {
+    /// Silence everything when a window loses focus
+    pub on_focus_lost: bool,
+    /// Silence the channels a [`SongPlayer`](crate::playback::SongPlayer) played on when its
+    /// song stops, on its [`SongTarget`](crate::playback::SongTarget) if it has one
+    #[cfg(feature = "assets")]
+    pub on_song_stopped: bool,
+}
+
+/// Returns the messages sent by a [`MidiPanic`], for all 16 channels
+pub fn panic_messages() -> impl Iterator<Item = ChannelVoiceMessage> {
+    panic_messages_on(CHANNELS)
+}
+
+/// Returns the messages sent by a [`MidiPanic`], for some channels
+pub fn panic_messages_on(
+    channels: impl IntoIterator<Item = Channel>,
+) -> impl Iterator<Item = ChannelVoiceMessage> {
+    channels.into_iter().flat_map(|channel| {
+        [ALL_NOTES_OFF, ALL_SOUND_OFF, RESET_ALL_CONTROLLERS]
+            .into_iter()
+            .filter_map(move |controller| {
+                util::voice_message(util::STATUS_CONTROL_CHANGE, channel, controller, 0)
+            })
+    })
+}
+
+/// Plugin that handles [`MidiPanic`]s
+pub struct MidiPanicPlugin;
+
+impl Plugin for MidiPanicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<MidiPanic>()
+            .init_resource::<MidiPanicSettings>();
+
+        #[cfg(feature = "assets")]
+        app.add_systems(
+            Update,
+            (panic_on_focus_lost, panic_on_song_stopped, handle_panics)
+                .chain()
+                .after(crate::playback::SongPlayback)
+                .before(ProcessSynthCommands),
+        );
+
+        #[cfg(not(feature = "assets"))]
+        app.add_systems(
+            Update,
+            (panic_on_focus_lost, handle_panics)
+                .chain()
+                .before(ProcessSynthCommands),
+        );
+    }
+}
+
+fn panic_on_focus_lost(
+    settings: Res<MidiPanicSettings>,
+    mut focus: MessageReader<WindowFocused>,
+    mut panics: MessageWriter<MidiPanic>,
+) {
+    if focus.read().any(|focus| !focus.focused) && settings.on_focus_lost {
+        panics.write(MidiPanic::all());
+    }
+}
+
+#[cfg(feature = "assets")]
+fn panic_on_song_stopped(
+    settings: Res<MidiPanicSettings>,
+    mut stopped: MessageReader<crate::playback::SongStopped>,
+    players: Query<(
+        &crate::playback::SongPlayer,
+        Option<&crate::playback::SongTarget>,
+    )>,
+    synth_channels: Query<&crate::playback::SynthChannels>,
+    mut panics: MessageWriter<MidiPanic>,
+) {
+    for stopped in stopped.read() {
+        if !settings.on_song_stopped {
+            continue;
+        }
+        let Ok((player, target)) = players.get(stopped.entity) else {
+            continue;
+        };
+        let synth = target.map_or(stopped.entity, |target| target.0);
+        // other songs can share the synth, so only silence the channels this one played on
+        let claimed = synth_channels
+            .get(synth)
+            .map(|channels| {
+                CHANNELS
+                    .into_iter()
+                    .filter(|channel| channels.owner(*channel) == Some(stopped.entity))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let channels = if claimed.is_empty() {
+            player.song().channels()
+        } else {
+            claimed
+        };
+        panics.write(MidiPanic::entity_channels(synth, &channels));
+    }
+}
+
+fn handle_panics(
+    mut panics: MessageReader<MidiPanic>,
+    output: Res<MidiOutput>,
+    mut synths: Query<(&mut SynthCommands, Option<&MidiOutputSink>)>,
+) {
+    for panic in panics.read() {
+        match panic.target {
+            None => {
+                // the ports of sinks get the panic through the sink, after what it has queued
+                let mut sink_ports = HashSet::new();
+                for (mut commands, sink) in &mut synths {
+                    commands.send_batch(panic.messages());
+                    if let Some(sink) = sink {
+                        sink_ports.insert(sink.port_id.clone());
+                    }
+                }
+                for (id, handle) in output.connections() {
+                    if sink_ports.contains(id) {
+                        continue;
+                    }
+                    for message in panic.messages() {
+                        if let Err(e) = handle.send(message) {
+                            warn!("Error sending panic to {id}! {e:?}");
+                            break;
+                        }
+                    }
+                }
+            }
+            Some(entity) => {
+                if let Ok((mut commands, _)) = synths.get_mut(entity) {
+                    commands.send_batch(panic.messages());
+                }
+            }
+        }
+    }
+}
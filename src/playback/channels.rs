@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use midix::prelude::*;
+
+use crate::{
+    input::ChannelRemap,
+    util::{self, CHANNELS},
+};
+
+/// The drum channel in General MIDI. [`ChannelAllocation::Auto`] keeps it for drums.
+const DRUM_CHANNEL: usize = 9;
+
+/// Component that makes a [`SongPlayer`](super::SongPlayer) play into another entity's
+/// [`SynthCommands`](crate::commands::SynthCommands) rather than its own.
+///
+/// Many players can target the same synth. Use [`ChannelAllocation`] to keep their
+/// channels from overlapping.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SongTarget(pub Entity);
+
+/// Decides which of the synth's channels a [`SongPlayer`](super::SongPlayer) plays on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ChannelAllocation {
+    /// Play on the channels written in the song, sharing them with anything else
+    #[default]
+    Song,
+    /// Move each channel of the song to a channel that no other song has claimed.
+    ///
+    /// [`Channel::Ten`] is only used for the song's own channel ten, so drums stay drums.
+    Auto,
+    /// Claim these channels, and move the song's channels onto them in order
+    Reserved(Vec<Channel>),
+}
+
+/// Component that tracks which [`SongPlayer`](super::SongPlayer) has claimed each channel
+/// of a synth.
+///
+/// This is automatically added to any entity with [`SynthCommands`](crate::commands::SynthCommands).
+#[derive(Component, Debug, Default)]
+pub struct SynthChannels {
+    owners: [Option<Entity>; 16],
+}
+
+impl SynthChannels {
+    /// The player that has claimed a channel
+    pub fn owner(&self, channel: Channel) -> Option<Entity> {
+        self.owners[util::channel_index(channel)]
+    }
+
+    /// The channels that no player has claimed
+    pub fn free(&self) -> impl Iterator<Item = Channel> + '_ {
+        CHANNELS
+            .into_iter()
+            .filter(|channel| self.owner(*channel).is_none())
+    }
+
+    fn is_free_for(&self, index: usize, player: Entity) -> bool {
+        self.owners[index].is_none_or(|owner| owner == player)
+    }
+
+    /// Claim channels for a player, returning how its song's channels are moved.
+    ///
+    /// Returns the number of channels that could be claimed if there aren't enough.
+    pub(crate) fn claim(
+        &mut self,
+        player: Entity,
+        allocation: &ChannelAllocation,
+        used: &[Channel],
+    ) -> Result<ChannelRemap, usize> {
+        let mut remap = ChannelRemap::default();
+        let mut claimed = Vec::new();
+        match allocation {
+            ChannelAllocation::Song => return Ok(remap),
+            ChannelAllocation::Auto => {
+                for channel in used {
+                    let from = util::channel_index(*channel);
+                    let slot = if from == DRUM_CHANNEL {
+                        self.is_free_for(DRUM_CHANNEL, player)
+                            .then_some(DRUM_CHANNEL)
+                    } else {
+                        (0..16).find(|index| {
+                            *index != DRUM_CHANNEL
+                                && !claimed.contains(index)
+                                && self.is_free_for(*index, player)
+                        })
+                    };
+                    let Some(slot) = slot else {
+                        return Err(self.free().count());
+                    };
+                    claimed.push(slot);
+                    remap = remap.map(*channel, CHANNELS[slot]);
+                }
+            }
+            ChannelAllocation::Reserved(reserved) => {
+                let available = reserved
+                    .iter()
+                    .map(|channel| util::channel_index(*channel))
+                    .filter(|index| self.is_free_for(*index, player))
+                    .collect::<Vec<_>>();
+                if available.len() < reserved.len() || used.len() > reserved.len() {
+                    return Err(available.len());
+                }
+                for (channel, slot) in used.iter().zip(&available) {
+                    remap = remap.map(*channel, CHANNELS[*slot]);
+                }
+                claimed = available;
+            }
+        }
+        for slot in claimed {
+            self.owners[slot] = Some(player);
+        }
+        Ok(remap)
+    }
+
+    /// Give back every channel claimed by a player
+    pub(crate) fn release(&mut self, player: Entity) {
+        for owner in &mut self.owners {
+            if *owner == Some(player) {
+                *owner = None;
+            }
+        }
+    }
+}
+
+/// Message written when a [`SongPlayer`](super::SongPlayer) can't claim enough channels
+/// on its synth. The player is stopped.
+#[derive(Message, Debug, Clone, PartialEq, Eq)]
+pub struct SongChannelError {
+    /// The entity of the player
+    pub entity: Entity,
+    /// The entity of the synth
+    pub target: Entity,
+    /// The number of channels the song needs
+    pub needed: usize,
+    /// The number of channels that were free
+    pub available: usize,
+}
@@ -0,0 +1,114 @@
+use midix::prelude::*;
+
+use crate::util::{self, CHANNELS};
+
+const BANK_SELECT: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+/// Controllers from here on are channel mode messages, which aren't chased
+const CHANNEL_MODE: u8 = 120;
+const RESET_ALL_CONTROLLERS: u8 = 121;
+const ALL_NOTES_OFF: u8 = 123;
+
+/// True if Reset All Controllers resets this controller.
+///
+/// Following the MIDI recommended practice RP-015, bank select, volume, pan, sound
+/// controllers, effect depths and parameter numbers are kept.
+fn is_reset(controller: u8) -> bool {
+    !matches!(controller, 0 | 7 | 10 | 32 | 70..=79 | 91..=95 | 98..=101)
+}
+
+/// The state of every channel at a point in a song: the last program, controller values,
+/// pitch bend and channel pressure, and the notes that are still sounding with their tracks.
+pub(crate) struct ChaseState {
+    programs: [Option<ChannelVoiceMessage>; 16],
+    controllers: Vec<Option<ChannelVoiceMessage>>,
+    pitch_bends: [Option<ChannelVoiceMessage>; 16],
+    pressures: [Option<ChannelVoiceMessage>; 16],
+    notes: Vec<Option<(ChannelVoiceMessage, u16)>>,
+}
+
+impl ChaseState {
+    pub(crate) fn new() -> Self {
+        Self {
+            programs: [None; 16],
+            controllers: vec![None; 16 * 128],
+            pitch_bends: [None; 16],
+            pressures: [None; 16],
+            notes: vec![None; 16 * 128],
+        }
+    }
+
+    /// Update the state with a message of a track, in the order they are played
+    pub(crate) fn track(&mut self, message: &ChannelVoiceMessage, track: u16) {
+        let channel = util::channel_index(message.channel());
+        let [status, data_1, _] = util::voice_bytes(message);
+        match status & 0xF0 {
+            util::STATUS_NOTE_ON | util::STATUS_NOTE_OFF => {
+                let slot = &mut self.notes[channel * 128 + data_1 as usize];
+                if util::note_on_key(message).is_some() {
+                    *slot = Some((*message, track));
+                } else {
+                    *slot = None;
+                }
+            }
+            util::STATUS_CONTROL_CHANGE if data_1 < CHANNEL_MODE => {
+                self.controllers[channel * 128 + data_1 as usize] = Some(*message);
+            }
+            util::STATUS_CONTROL_CHANGE if data_1 == RESET_ALL_CONTROLLERS => {
+                let controllers = &mut self.controllers[channel * 128..(channel + 1) * 128];
+                for (controller, slot) in controllers.iter_mut().enumerate() {
+                    if is_reset(controller as u8) {
+                        *slot = None;
+                    }
+                }
+                self.pitch_bends[channel] = None;
+                self.pressures[channel] = None;
+            }
+            // All Notes Off, and the mode changes after it, end every note of the channel.
+            // All Sound Off doesn't, as the notes are still held.
+            util::STATUS_CONTROL_CHANGE if data_1 >= ALL_NOTES_OFF => {
+                self.notes[channel * 128..(channel + 1) * 128].fill(None);
+            }
+            util::STATUS_PROGRAM_CHANGE => self.programs[channel] = Some(*message),
+            util::STATUS_PITCH_BEND => self.pitch_bends[channel] = Some(*message),
+            util::STATUS_CHANNEL_PRESSURE => self.pressures[channel] = Some(*message),
+            _ => {}
+        }
+    }
+
+    /// The messages that recreate this state, with the track of each restarted note.
+    ///
+    /// Bank selects are sent before program changes, which are sent before other controllers.
+    pub(crate) fn messages(&self, restart_notes: bool) -> Vec<(ChannelVoiceMessage, Option<u16>)> {
+        let mut messages = Vec::new();
+        for (index, _) in CHANNELS.iter().enumerate() {
+            let mut state = Vec::new();
+            let controllers = &self.controllers[index * 128..(index + 1) * 128];
+            state.extend(controllers[BANK_SELECT as usize]);
+            state.extend(controllers[BANK_SELECT_LSB as usize]);
+            state.extend(self.programs[index]);
+            state.extend(
+                controllers
+                    .iter()
+                    .enumerate()
+                    .filter(|(controller, _)| {
+                        *controller != BANK_SELECT as usize
+                            && *controller != BANK_SELECT_LSB as usize
+                    })
+                    .filter_map(|(_, message)| *message),
+            );
+            state.extend(self.pitch_bends[index]);
+            state.extend(self.pressures[index]);
+            messages.extend(state.into_iter().map(|message| (message, None)));
+            if restart_notes {
+                messages.extend(
+                    self.notes[index * 128..(index + 1) * 128]
+                        .iter()
+                        .flatten()
+                        .map(|(message, track)| (*message, Some(*track))),
+                );
+            }
+        }
+        messages
+    }
+}
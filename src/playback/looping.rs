@@ -0,0 +1,110 @@
+use thiserror::Error;
+
+use crate::assets::{MidiSong, SongMeta};
+
+/// The marker name that starts a loop by convention
+pub const LOOP_START_MARKER: &str = "loopStart";
+/// The marker name that ends a loop by convention
+pub const LOOP_END_MARKER: &str = "loopEnd";
+
+/// A point in a song that a loop starts or ends at
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoopPoint {
+    /// A time in microseconds
+    Time(u64),
+    /// The start of a bar, counted from 1. Uses the song's [tempo map](MidiSong::tempo_map).
+    Bar(u32),
+    /// The first [marker](SongMeta::Marker) with this text
+    Marker(String),
+}
+
+impl LoopPoint {
+    /// The time of this point in a song, in microseconds
+    ///
+    /// # Errors
+    /// If the point is a marker that the song doesn't have
+    pub fn resolve(&self, song: &MidiSong) -> Result<u64, LoopError> {
+        match self {
+            LoopPoint::Time(micros) => Ok(*micros),
+            LoopPoint::Bar(bar) => Ok(song.tempo_map().micros_at_bar(*bar as f64)),
+            LoopPoint::Marker(name) => song
+                .meta_events()
+                .iter()
+                .filter(|event| matches!(&event.meta, SongMeta::Marker(text) if text == name))
+                .map(|event| event.timestamp)
+                .min()
+                .ok_or_else(|| LoopError::MarkerNotFound(name.clone())),
+        }
+    }
+}
+
+/// A section of a song that repeats once playback reaches its end.
+///
+/// Anything before the start is only played once, like an intro.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopRegion {
+    /// Where playback returns to
+    pub start: LoopPoint,
+    /// Where playback returns from
+    pub end: LoopPoint,
+}
+
+impl LoopRegion {
+    /// Loop between two points
+    pub fn new(start: LoopPoint, end: LoopPoint) -> Self {
+        Self { start, end }
+    }
+
+    /// Loop between two times in microseconds
+    pub fn time(start: u64, end: u64) -> Self {
+        Self::new(LoopPoint::Time(start), LoopPoint::Time(end))
+    }
+
+    /// Loop from the start of one bar to the start of another. Bars are counted from 1.
+    pub fn bars(start: u32, end: u32) -> Self {
+        Self::new(LoopPoint::Bar(start), LoopPoint::Bar(end))
+    }
+
+    /// Loop between two markers
+    pub fn markers(start: impl Into<String>, end: impl Into<String>) -> Self {
+        Self::new(
+            LoopPoint::Marker(start.into()),
+            LoopPoint::Marker(end.into()),
+        )
+    }
+
+    /// Loop between the [`LOOP_START_MARKER`] and [`LOOP_END_MARKER`] markers
+    pub fn from_markers() -> Self {
+        Self::markers(LOOP_START_MARKER, LOOP_END_MARKER)
+    }
+
+    /// The start and end of this region in a song, in microseconds
+    ///
+    /// # Errors
+    /// - If a point is a marker that the song doesn't have
+    /// - If the region doesn't end after it starts
+    pub fn resolve(&self, song: &MidiSong) -> Result<(u64, u64), LoopError> {
+        let start = self.start.resolve(song)?;
+        let end = self.end.resolve(song)?;
+        if end <= start {
+            return Err(LoopError::Empty { start, end });
+        }
+        Ok((start, end))
+    }
+}
+
+/// Possible errors when setting a [`LoopRegion`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum LoopError {
+    /// The song has no marker with this text
+    #[error("Marker not found: {0}")]
+    MarkerNotFound(String),
+    /// The loop doesn't end after it starts
+    #[error("Loop ends ({end}) before it starts ({start})")]
+    Empty {
+        /// The start of the loop in microseconds
+        start: u64,
+        /// The end of the loop in microseconds
+        end: u64,
+    },
+}
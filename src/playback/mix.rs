@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use midix::prelude::*;
+
+use crate::util;
+
+/// The mute, solo and volume of one channel or track of a [`SongPlayer`](super::SongPlayer)
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct MixStrip {
+    /// Notes are not played
+    pub muted: bool,
+    /// If any channel (or track) is soloed, only soloed channels (or tracks) are played
+    pub solo: bool,
+    /// Scales the velocity of notes. `1.0` plays them as written.
+    pub volume: f32,
+}
+
+impl Default for MixStrip {
+    fn default() -> Self {
+        Self {
+            muted: false,
+            solo: false,
+            volume: 1.,
+        }
+    }
+}
+
+/// The mix of every channel and track of a song
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SongMix {
+    channels: [MixStrip; 16],
+    tracks: Vec<MixStrip>,
+    /// Set when something was muted, so that sounding notes can be released
+    changed: bool,
+}
+
+impl SongMix {
+    pub(crate) fn channel(&self, channel: Channel) -> MixStrip {
+        self.channels[util::channel_index(channel)]
+    }
+
+    pub(crate) fn track(&self, track: u16) -> MixStrip {
+        self.tracks.get(track as usize).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn channel_mut(&mut self, channel: Channel) -> &mut MixStrip {
+        self.changed = true;
+        &mut self.channels[util::channel_index(channel)]
+    }
+
+    pub(crate) fn track_mut(&mut self, track: u16) -> &mut MixStrip {
+        self.changed = true;
+        let index = track as usize;
+        if self.tracks.len() <= index {
+            self.tracks.resize(index + 1, MixStrip::default());
+        }
+        &mut self.tracks[index]
+    }
+
+    /// Returns true once after the mix has changed
+    pub(crate) fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+
+    /// True if notes on this channel of the song, and in this track, are played.
+    ///
+    /// Track rules are ignored if `track` is `None`.
+    pub(crate) fn is_audible(&self, channel: Channel, track: Option<u16>) -> bool {
+        let strip = self.channel(channel);
+        let channel_solo = self.channels.iter().any(|strip| strip.solo);
+        if strip.muted || (channel_solo && !strip.solo) {
+            return false;
+        }
+        let Some(track) = track else {
+            return true;
+        };
+        let strip = self.track(track);
+        let track_solo = self.tracks.iter().any(|strip| strip.solo);
+        !strip.muted && (!track_solo || strip.solo)
+    }
+
+    /// Mixes a message of the song. Note ons that aren't audible, or are scaled to
+    /// silence, are dropped. Every other message is kept, so unmuting sounds right.
+    pub(crate) fn apply(
+        &self,
+        message: ChannelVoiceMessage,
+        track: Option<u16>,
+    ) -> Option<ChannelVoiceMessage> {
+        let Some(key) = util::note_on_key(&message) else {
+            return Some(message);
+        };
+        let channel = message.channel();
+        if !self.is_audible(channel, track) {
+            return None;
+        }
+        let volume =
+            self.channel(channel).volume * track.map_or(1., |track| self.track(track).volume);
+        if volume == 1. {
+            return Some(message);
+        }
+        let [_, _, velocity] = util::voice_bytes(&message);
+        let velocity = (velocity as f32 * volume.max(0.)).round().min(127.) as u8;
+        if velocity == 0 {
+            return None;
+        }
+        util::voice_message(util::STATUS_NOTE_ON, channel, key, velocity)
+    }
+}
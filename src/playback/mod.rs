@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+mod player;
+pub use player::*;
+
+mod chase;
+
+mod looping;
+pub use looping::*;
+
+mod channels;
+pub use channels::*;
+
+mod mix;
+pub use mix::MixStrip;
+
+use crate::{
+    assets::{SongMeta, SongMetaEvent},
+    commands::{ProcessSynthCommands, SynthCommands},
+};
+
+/// Plugin that plays [`MidiSong`](crate::assets::MidiSong)s with a [`SongPlayer`].
+///
+/// This is added by [`MidiPlugin`](crate::MidiPlugin) whenever the `assets` feature is enabled.
+/// Songs can be played on a [`MidiOutputSink`](crate::output::MidiOutputSink) without the
+/// `synth` feature.
+pub struct SongPlaybackPlugin;
+
+/// System set that advances every [`SongPlayer`].
+///
+/// This runs before [`ProcessSynthCommands`].
+#[derive(SystemSet, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct SongPlayback;
+
+/// Message written when a [`SongPlayer`]'s song stops, either by reaching its end or by
+/// calling [`SongPlayer::stop`].
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SongStopped {
+    /// The entity of the [`SongPlayer`]
+    pub entity: Entity,
+}
+
+/// Message written when a [`SongPlayer`] reaches one of its song's
+/// [meta events](crate::assets::MidiSong::meta_events), such as a lyric or marker.
+#[derive(Message, Debug, Clone, PartialEq, Eq)]
+pub struct SongMetaMessage {
+    /// The entity of the [`SongPlayer`]
+    pub entity: Entity,
+    /// When the meta event happens in the song, in microseconds
+    pub timestamp: u64,
+    /// The meta event
+    pub meta: SongMeta,
+}
+
+impl Plugin for SongPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SongStopped>()
+            .add_message::<SongMetaMessage>()
+            .add_message::<SongChannelError>();
+
+        app.configure_sets(Update, SongPlayback.before(ProcessSynthCommands));
+
+        app.add_systems(
+            Update,
+            (
+                release_song_channels,
+                allocate_song_channels,
+                advance_song_players,
+            )
+                .chain()
+                .in_set(SongPlayback),
+        );
+    }
+}
+
+/// System that claims channels on the synth for players that need them
+fn allocate_song_channels(
+    mut players: Query<(Entity, &mut SongPlayer, Option<&SongTarget>)>,
+    mut synths: Query<&mut SynthChannels>,
+    mut errors: MessageWriter<SongChannelError>,
+) {
+    for (entity, mut player, target) in &mut players {
+        if player.channel_map.is_some() || player.allocation_failed {
+            continue;
+        }
+        let target = target.map_or(entity, |target| target.0);
+        let Ok(mut channels) = synths.get_mut(target) else {
+            continue;
+        };
+        channels.release(entity);
+
+        let used = player.song.channels();
+        match channels.claim(entity, &player.allocation, &used) {
+            Ok(map) => player.channel_map = Some(map),
+            Err(available) => {
+                warn!(
+                    "Song on {entity} needs {} channels on {target}, but only {available} are free",
+                    used.len()
+                );
+                player.allocation_failed = true;
+                player.stop();
+                errors.write(SongChannelError {
+                    entity,
+                    target,
+                    needed: used.len(),
+                    available,
+                });
+            }
+        }
+    }
+}
+
+/// System that gives back the channels of removed players
+fn release_song_channels(
+    mut removed: RemovedComponents<SongPlayer>,
+    mut synths: Query<&mut SynthChannels>,
+) {
+    for entity in removed.read() {
+        for mut channels in &mut synths {
+            channels.release(entity);
+        }
+    }
+}
+
+/// System that sends the events of every playing song that are due this frame
+fn advance_song_players(
+    real_time: Res<Time<Real>>,
+    virtual_time: Res<Time<Virtual>>,
+    mut players: Query<(Entity, &mut SongPlayer, Option<&SongTarget>)>,
+    mut synths: Query<&mut SynthCommands>,
+    mut stopped: MessageWriter<SongStopped>,
+    mut meta_messages: MessageWriter<SongMetaMessage>,
+    mut meta: Local<Vec<SongMetaEvent>>,
+) {
+    let real_delta = real_time.delta().as_micros() as u64;
+    let virtual_delta = virtual_time.delta().as_micros() as u64;
+    for (entity, mut player, target) in &mut players {
+        if !player.is_allocated() {
+            continue;
+        }
+        let Ok(mut commands) = synths.get_mut(target.map_or(entity, |target| target.0)) else {
+            continue;
+        };
+        let delta = if player.follow_virtual_time {
+            virtual_delta
+        } else {
+            real_delta
+        };
+        player.advance(delta, &mut commands, &mut meta);
+        meta_messages.write_batch(meta.drain(..).map(|event| SongMetaMessage {
+            entity,
+            timestamp: event.timestamp,
+            meta: event.meta,
+        }));
+
+        let state = player.state;
+        if state == PlaybackState::Stopped && player.reported_state != PlaybackState::Stopped {
+            stopped.write(SongStopped { entity });
+        }
+        player.reported_state = state;
+    }
+}
@@ -0,0 +1,700 @@
+use core::time::Duration;
+
+use bevy::prelude::*;
+use midix::prelude::{Channel, ChannelVoiceMessage};
+
+use crate::{
+    assets::{MidiSong, SongMetaEvent},
+    commands::SynthCommands,
+    input::ChannelRemap,
+    playback::{
+        ChannelAllocation, LoopError, LoopRegion, MixStrip, chase::ChaseState, mix::SongMix,
+    },
+    util::{ActiveNotes, CHANNELS},
+};
+
+/// The playback state of a [`SongPlayer`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum PlaybackState {
+    /// The song is advancing
+    #[default]
+    Playing,
+    /// The song keeps its position, but doesn't advance
+    Paused,
+    /// The song has finished, or was stopped, and will play from the start
+    Stopped,
+}
+
+/// Component that plays a [`MidiSong`] into this entity's [`SynthCommands`].
+///
+/// This works with any sink for [`SynthCommands`], such as a
+/// [`SynthPlayer`](crate::synth::SynthPlayer) or a
+/// [`MidiOutputSink`](crate::output::MidiOutputSink).
+///
+/// Notes that are sounding when the song is paused or stopped are released.
+///
+/// A section of the song can repeat with [`SongPlayer::set_loop`]. If the song has
+/// [`LOOP_START_MARKER`](super::LOOP_START_MARKER) and [`LOOP_END_MARKER`](super::LOOP_END_MARKER)
+/// markers, it loops between them by default. Otherwise, if [`MidiSong::looped`] is set,
+/// the whole song repeats.
+///
+/// Add a [`SongTarget`](super::SongTarget) to play into another entity's [`SynthCommands`],
+/// such as to play many songs on one synth. See [`SongPlayer::set_channel_allocation`].
+///
+/// Channels and tracks of the song can be muted, soloed and scaled while it plays,
+/// see [`SongPlayer::set_channel_muted`] and [`SongPlayer::set_track_muted`].
+///
+/// The speed of playback is a parameter of the player, see [`SongPlayer::set_rate`].
+/// The song's events are never modified.
+///
+/// The song's [meta events](MidiSong::meta_events) are written as
+/// [`SongMetaMessage`](super::SongMetaMessage)s as they are reached.
+#[derive(Component, Debug)]
+#[require(SynthCommands)]
+pub struct SongPlayer {
+    pub(crate) song: MidiSong,
+    pub(crate) position: u64,
+    pub(crate) cursor: usize,
+    pub(crate) meta_cursor: usize,
+    pub(crate) state: PlaybackState,
+    pub(crate) reported_state: PlaybackState,
+    pub(crate) sounding: ActiveNotes,
+    /// The notes sounding from each track, on the song's own channels
+    pub(crate) track_sounding: Vec<ActiveNotes>,
+    pub(crate) mix: SongMix,
+    pub(crate) rate: f64,
+    pub(crate) rate_ramp: Option<RateRamp>,
+    /// The part of a microsecond of song time that hasn't been played yet
+    pub(crate) remainder: f64,
+    pub(crate) follow_virtual_time: bool,
+    pub(crate) pending_seek: Option<u64>,
+    pub(crate) restart_notes_on_seek: bool,
+    /// How many seeks have been applied, so that followers can tell the position jumped.
+    pub(crate) seeks: u64,
+    pub(crate) loop_region: Option<(u64, u64)>,
+    pub(crate) allocation: ChannelAllocation,
+    /// How the song's channels are moved, once they have been claimed on the synth
+    pub(crate) channel_map: Option<ChannelRemap>,
+    pub(crate) allocation_failed: bool,
+}
+
+/// A linear change of rate over real time
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RateRamp {
+    from: f64,
+    to: f64,
+    elapsed: u64,
+    duration: u64,
+}
+
+impl RateRamp {
+    fn rate_at(&self, elapsed: u64) -> f64 {
+        let t = (elapsed as f64 / self.duration as f64).min(1.);
+        self.from + (self.to - self.from) * t
+    }
+}
+
+impl SongPlayer {
+    /// Create a player for a song.
+    ///
+    /// The song starts playing immediately unless [`MidiSong::paused`] is set.
+    pub fn new(mut song: MidiSong) -> Self {
+        song.sort_events();
+        let tracks = song.track_count() as usize;
+        song.meta.sort_by_key(|event| event.timestamp);
+        let state = if song.paused {
+            PlaybackState::Paused
+        } else {
+            PlaybackState::Playing
+        };
+        Self {
+            song,
+            position: 0,
+            cursor: 0,
+            meta_cursor: 0,
+            state,
+            reported_state: state,
+            sounding: ActiveNotes::default(),
+            track_sounding: vec![ActiveNotes::default(); tracks],
+            mix: SongMix::default(),
+            rate: 1.,
+            rate_ramp: None,
+            remainder: 0.,
+            follow_virtual_time: false,
+            pending_seek: None,
+            restart_notes_on_seek: false,
+            seeks: 0,
+            loop_region: None,
+            allocation: ChannelAllocation::Song,
+            channel_map: None,
+            allocation_failed: false,
+        }
+        .with_marker_loop()
+    }
+
+    /// Loop between the conventional loop markers, if the song has them
+    fn with_marker_loop(mut self) -> Self {
+        self.loop_region = LoopRegion::from_markers().resolve(&self.song).ok();
+        self
+    }
+
+    /// The song being played
+    pub fn song(&self) -> &MidiSong {
+        &self.song
+    }
+
+    /// The current position in the song, in microseconds
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The current state of playback
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    /// True if the song is advancing
+    pub fn is_playing(&self) -> bool {
+        self.state == PlaybackState::Playing
+    }
+
+    /// The current playback rate. `1.0` is the song's original speed.
+    ///
+    /// While [`SongPlayer::ramp_rate`] is in progress, this is the rate reached so far.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Set the playback rate. `2.0` plays twice as fast.
+    ///
+    /// Negative rates are clamped to zero. Cancels any ramp in progress.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate.max(0.);
+        self.rate_ramp = None;
+    }
+
+    /// Smoothly change the playback rate to `rate` over a duration of real time.
+    pub fn ramp_rate(&mut self, rate: f64, over: Duration) {
+        let duration = over.as_micros() as u64;
+        if duration == 0 {
+            self.set_rate(rate);
+            return;
+        }
+        self.rate_ramp = Some(RateRamp {
+            from: self.rate,
+            to: rate.max(0.),
+            elapsed: 0,
+            duration,
+        });
+    }
+
+    /// True if the song advances with [`Time<Virtual>`] rather than [`Time<Real>`]
+    pub fn follows_virtual_time(&self) -> bool {
+        self.follow_virtual_time
+    }
+
+    /// If true, the song advances with [`Time<Virtual>`], so its relative speed and
+    /// pausing apply on top of [`SongPlayer::rate`]. This is useful for slow motion effects.
+    ///
+    /// By default, the song advances with [`Time<Real>`].
+    pub fn set_follow_virtual_time(&mut self, follow: bool) {
+        self.follow_virtual_time = follow;
+    }
+
+    /// Jump to a time in the song, in microseconds.
+    ///
+    /// Sounding notes are released, and the program changes, controller values, pitch bends
+    /// and channel pressure in effect at that time are sent, so that instruments sound as if
+    /// the song had played up to that point. See [`SongPlayer::set_restart_notes_on_seek`].
+    ///
+    /// The seek happens the next time players advance, even if the song is paused.
+    pub fn seek(&mut self, micros: u64) {
+        self.pending_seek = Some(micros);
+    }
+
+    /// Jump to the start of a bar, counted from 1, using the song's
+    /// [tempo map](MidiSong::tempo_map). See [`SongPlayer::seek`].
+    pub fn seek_to_bar(&mut self, bar: u32) {
+        self.seek(self.song.tempo_map.micros_at_bar(bar as f64));
+    }
+
+    /// If true, notes that started before a seek's target time and are still held at
+    /// that time are played again. Otherwise, they stay silent until their next note on.
+    ///
+    /// False by default.
+    pub fn set_restart_notes_on_seek(&mut self, restart: bool) {
+        self.restart_notes_on_seek = restart;
+    }
+
+    /// Repeat a section of the song. Playback jumps back to the start of the region
+    /// whenever it reaches its end, releasing notes that would cross the boundary.
+    ///
+    /// # Errors
+    /// If the region can't be found in the song. The current loop is kept.
+    pub fn set_loop(&mut self, region: LoopRegion) -> Result<(), LoopError> {
+        self.loop_region = Some(region.resolve(&self.song)?);
+        Ok(())
+    }
+
+    /// Stop repeating a section of the song.
+    ///
+    /// [`MidiSong::looped`] still repeats the whole song.
+    pub fn clear_loop(&mut self) {
+        self.loop_region = None;
+    }
+
+    /// The start and end of the repeated section, in microseconds
+    pub fn loop_region(&self) -> Option<(u64, u64)> {
+        self.loop_region
+    }
+
+    /// Decide which channels of the synth this song plays on.
+    ///
+    /// With [`ChannelAllocation::Auto`] or [`ChannelAllocation::Reserved`], the song doesn't
+    /// play until its channels have been claimed. If there aren't enough free channels,
+    /// a [`SongChannelError`](super::SongChannelError) is written and the song is stopped.
+    pub fn set_channel_allocation(&mut self, allocation: ChannelAllocation) {
+        self.allocation = allocation;
+        self.channel_map = None;
+        self.allocation_failed = false;
+    }
+
+    /// How this song claims channels of the synth
+    pub fn channel_allocation(&self) -> &ChannelAllocation {
+        &self.allocation
+    }
+
+    /// How the song's channels are moved on the synth, once they have been claimed
+    pub fn channel_map(&self) -> Option<&ChannelRemap> {
+        self.channel_map.as_ref()
+    }
+
+    /// True if the song may play, which is once its channels have been claimed
+    pub(crate) fn is_allocated(&self) -> bool {
+        self.allocation == ChannelAllocation::Song || self.channel_map.is_some()
+    }
+
+    /// Mute or unmute a channel of the song. Sounding notes on a muted channel are released.
+    ///
+    /// This is the song's own channel, before any [channel allocation](SongPlayer::set_channel_allocation).
+    pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) {
+        self.mix.channel_mut(channel).muted = muted;
+    }
+
+    /// Solo a channel of the song. While any channel is soloed, only soloed channels play.
+    pub fn set_channel_solo(&mut self, channel: Channel, solo: bool) {
+        self.mix.channel_mut(channel).solo = solo;
+    }
+
+    /// Scale the velocity of notes on a channel of the song. `1.0` plays them as written.
+    ///
+    /// This applies to notes played from now on.
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.mix.channel_mut(channel).volume = volume;
+    }
+
+    /// The mute, solo and volume of a channel of the song
+    pub fn channel_mix(&self, channel: Channel) -> MixStrip {
+        self.mix.channel(channel)
+    }
+
+    /// Mute or unmute an original track of the song, see [`MidiSong::event_track`].
+    /// Sounding notes from a muted track are released.
+    pub fn set_track_muted(&mut self, track: u16, muted: bool) {
+        self.mix.track_mut(track).muted = muted;
+    }
+
+    /// Solo a track of the song. While any track is soloed, only soloed tracks play.
+    pub fn set_track_solo(&mut self, track: u16, solo: bool) {
+        self.mix.track_mut(track).solo = solo;
+    }
+
+    /// Scale the velocity of notes from a track of the song. `1.0` plays them as written.
+    pub fn set_track_volume(&mut self, track: u16, volume: f32) {
+        self.mix.track_mut(track).volume = volume;
+    }
+
+    /// The mute, solo and volume of a track of the song
+    pub fn track_mix(&self, track: u16) -> MixStrip {
+        self.mix.track(track)
+    }
+
+    /// Unmute, unsolo and reset the volume of every channel and track
+    pub fn reset_mix(&mut self) {
+        self.mix = SongMix::default();
+    }
+
+    /// Play the song. A stopped song will start from the beginning.
+    pub fn play(&mut self) {
+        self.state = PlaybackState::Playing;
+        self.allocation_failed = false;
+    }
+
+    /// Pause the song, keeping its position
+    pub fn pause(&mut self) {
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+        }
+    }
+
+    /// Stop the song and rewind it to the beginning
+    pub fn stop(&mut self) {
+        self.state = PlaybackState::Stopped;
+        self.position = 0;
+        self.cursor = 0;
+        self.meta_cursor = 0;
+    }
+
+    /// Send everything that happens in the next `delta` microseconds of the song.
+    ///
+    /// Meta events that are reached are pushed to `meta`.
+    pub(crate) fn advance(
+        &mut self,
+        delta: u64,
+        commands: &mut SynthCommands,
+        meta: &mut Vec<SongMetaEvent>,
+    ) {
+        if let Some(target) = self.pending_seek.take() {
+            self.apply_seek(target, commands);
+        }
+        if self.state != PlaybackState::Playing {
+            if !self.sounding.is_empty() {
+                self.release_all(commands);
+            }
+            return;
+        }
+        if self.mix.take_changed() {
+            self.release_inaudible(commands);
+        }
+
+        let delta = self.song_delta(delta);
+        let duration = self.song.duration();
+        let mut end = self.position + delta;
+        loop {
+            if let Some((loop_start, loop_end)) = self.loop_region
+                && self.position < loop_end
+                && end >= loop_end
+            {
+                self.send_until(loop_end, commands, meta);
+                self.release_all(commands);
+                end = loop_start + (end - loop_end);
+                self.wrap_to(loop_start);
+                continue;
+            }
+            self.send_until(end, commands, meta);
+            if self.cursor < self.song.events.len() || self.meta_cursor < self.song.meta.len() {
+                self.position = end;
+                return;
+            }
+            // the loop can end after the last event, so keep going until it wraps
+            if self
+                .loop_region
+                .is_some_and(|(_, loop_end)| self.position < loop_end)
+            {
+                self.position = end;
+                return;
+            }
+            if !self.song.looped || duration == 0 {
+                self.release_all(commands);
+                self.stop();
+                return;
+            }
+            self.release_all(commands);
+            // a looped song with a loop region plays the intro only once
+            let start = self.loop_region.map_or(0, |(loop_start, _)| loop_start);
+            end = start + end.saturating_sub(duration);
+            self.wrap_to(start);
+        }
+    }
+
+    /// Move to `target`, and send the state of every channel at that time
+    fn apply_seek(&mut self, target: u64, commands: &mut SynthCommands) {
+        let target = target.min(self.song.duration());
+        self.release_all(commands);
+
+        let cursor = self
+            .song
+            .events
+            .partition_point(|event| event.timestamp < target);
+        let mut chase = ChaseState::new();
+        for (index, event) in self.song.events[..cursor].iter().enumerate() {
+            chase.track(&event.event, self.song.event_track(index));
+        }
+        // notes restarted while paused would be released straight away
+        let restart_notes = self.restart_notes_on_seek && self.state == PlaybackState::Playing;
+        for (message, track) in chase.messages(restart_notes) {
+            let Some(message) = self.mix.apply(message, track) else {
+                continue;
+            };
+            if let Some(notes) = track.and_then(|track| self.track_sounding.get_mut(track as usize))
+            {
+                notes.track(&message);
+            }
+            let message = self.output(message);
+            self.sounding.track(&message);
+            commands.send(message);
+        }
+
+        self.move_to(target);
+        self.remainder = 0.;
+        self.seeks += 1;
+        if self.state == PlaybackState::Stopped {
+            self.state = PlaybackState::Paused;
+        }
+    }
+
+    /// Release every sounding note
+    fn release_all(&mut self, commands: &mut SynthCommands) {
+        commands.send_batch(self.sounding.release_all());
+        for notes in &mut self.track_sounding {
+            *notes = ActiveNotes::default();
+        }
+    }
+
+    /// Release the notes of every channel and track that was just muted
+    fn release_inaudible(&mut self, commands: &mut SynthCommands) {
+        for track in 0..self.track_sounding.len() {
+            for channel in CHANNELS {
+                if self.mix.is_audible(channel, Some(track as u16)) {
+                    continue;
+                }
+                for message in self.track_sounding[track].release_channel(channel) {
+                    let message = self.output(message);
+                    self.sounding.track(&message);
+                    commands.send(message);
+                }
+            }
+        }
+    }
+
+    /// Moves a message of the song to the channel it's played on
+    fn output(&self, message: ChannelVoiceMessage) -> ChannelVoiceMessage {
+        match &self.channel_map {
+            Some(map) => map.apply(message),
+            None => message,
+        }
+    }
+
+    /// Jump back to the start of a loop. Counts as a seek, so followers such as the
+    /// [`MidiClock`](crate::output::MidiClock) relocate.
+    fn wrap_to(&mut self, position: u64) {
+        self.move_to(position);
+        self.seeks += 1;
+    }
+
+    /// Set the position, and the cursors to the first events at or after it
+    fn move_to(&mut self, position: u64) {
+        self.position = position;
+        self.cursor = self
+            .song
+            .events
+            .partition_point(|event| event.timestamp < position);
+        self.meta_cursor = self
+            .song
+            .meta
+            .partition_point(|event| event.timestamp < position);
+    }
+
+    /// Converts real (or virtual) microseconds into song microseconds, applying the rate
+    fn song_delta(&mut self, delta: u64) -> u64 {
+        let rate = match &mut self.rate_ramp {
+            Some(ramp) => {
+                let start = ramp.rate_at(ramp.elapsed);
+                ramp.elapsed += delta;
+                let end = ramp.rate_at(ramp.elapsed);
+                self.rate = end;
+                if ramp.elapsed >= ramp.duration {
+                    self.rate_ramp = None;
+                }
+                (start + end) / 2.
+            }
+            None => self.rate,
+        };
+        let song_delta = delta as f64 * rate + self.remainder;
+        self.remainder = song_delta.fract();
+        song_delta as u64
+    }
+
+    /// Send every event before `end`
+    fn send_until(
+        &mut self,
+        end: u64,
+        commands: &mut SynthCommands,
+        meta: &mut Vec<SongMetaEvent>,
+    ) {
+        while let Some(event) = self.song.events.get(self.cursor) {
+            if event.timestamp >= end {
+                break;
+            }
+            let track = self.song.event_track(self.cursor);
+            self.cursor += 1;
+            let Some(message) = self.mix.apply(event.event, Some(track)) else {
+                continue;
+            };
+            if let Some(notes) = self.track_sounding.get_mut(track as usize) {
+                notes.track(&message);
+            }
+            let message = self.output(message);
+            self.sounding.track(&message);
+            commands.send(message);
+        }
+        while let Some(event) = self.song.meta.get(self.meta_cursor) {
+            if event.timestamp >= end {
+                break;
+            }
+            meta.push(event.clone());
+            self.meta_cursor += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midix::prelude::*;
+
+    use super::SongPlayer;
+    use crate::{assets::MidiSong, commands::SynthCommands, playback::LoopRegion, util};
+
+    fn message(status: u8, channel: Channel, data_1: u8, data_2: u8) -> ChannelVoiceMessage {
+        util::voice_message(status, channel, data_1, data_2).unwrap()
+    }
+
+    fn on(channel: Channel, key: u8) -> ChannelVoiceMessage {
+        message(util::STATUS_NOTE_ON, channel, key, 100)
+    }
+
+    fn off(channel: Channel, key: u8) -> ChannelVoiceMessage {
+        message(util::STATUS_NOTE_OFF, channel, key, 0)
+    }
+
+    fn control(controller: u8, value: u8) -> ChannelVoiceMessage {
+        message(util::STATUS_CONTROL_CHANGE, Channel::One, controller, value)
+    }
+
+    /// Advance the player, returning the messages it sent
+    fn advance(player: &mut SongPlayer, delta: u64) -> Vec<ChannelVoiceMessage> {
+        let mut commands = SynthCommands::default();
+        player.advance(delta, &mut commands, &mut Vec::new());
+        commands.take()
+    }
+
+    #[test]
+    fn seeking_chases_channel_state() {
+        let program = message(util::STATUS_PROGRAM_CHANGE, Channel::One, 5, 0);
+        let bend = message(util::STATUS_PITCH_BEND, Channel::One, 0, 80);
+        let song = MidiSong::new(vec![
+            Timed::new(0, program),
+            Timed::new(0, control(7, 90)),
+            Timed::new(0, control(1, 64)),
+            Timed::new(0, bend),
+            Timed::new(0, on(Channel::One, 60)),
+            Timed::new(2_000_000, off(Channel::One, 60)),
+        ]);
+        let mut player = SongPlayer::new(song);
+        player.seek(1_000_000);
+        let sent = advance(&mut player, 0);
+        assert_eq!(sent, vec![program, control(1, 64), control(7, 90), bend]);
+        assert_eq!(player.position(), 1_000_000);
+    }
+
+    #[test]
+    fn reset_all_controllers_clears_chased_controllers() {
+        let bend = message(util::STATUS_PITCH_BEND, Channel::One, 0, 80);
+        let song = MidiSong::new(vec![
+            Timed::new(0, control(7, 90)),
+            Timed::new(0, control(1, 64)),
+            Timed::new(0, bend),
+            Timed::new(500_000, control(121, 0)),
+            Timed::new(2_000_000, control(1, 10)),
+        ]);
+        let mut player = SongPlayer::new(song);
+        player.seek(1_000_000);
+        assert_eq!(advance(&mut player, 0), vec![control(7, 90)]);
+    }
+
+    #[test]
+    fn restarted_notes_belong_to_their_track() {
+        let song = MidiSong::with_tracks(
+            vec![
+                Timed::new(0, on(Channel::One, 60)),
+                Timed::new(0, on(Channel::One, 64)),
+                Timed::new(2_000_000, off(Channel::One, 60)),
+                Timed::new(2_000_000, off(Channel::One, 64)),
+            ],
+            vec![0, 1, 0, 1],
+        );
+        let mut player = SongPlayer::new(song);
+        player.set_restart_notes_on_seek(true);
+        player.seek(1_000_000);
+        assert_eq!(
+            advance(&mut player, 0),
+            vec![on(Channel::One, 60), on(Channel::One, 64)]
+        );
+
+        player.set_track_muted(1, true);
+        assert_eq!(advance(&mut player, 0), vec![off(Channel::One, 64)]);
+    }
+
+    #[test]
+    fn notes_are_not_restarted_by_default() {
+        let song = MidiSong::new(vec![
+            Timed::new(0, on(Channel::One, 60)),
+            Timed::new(2_000_000, off(Channel::One, 60)),
+        ]);
+        let mut player = SongPlayer::new(song);
+        player.seek(1_000_000);
+        assert!(advance(&mut player, 0).is_empty());
+    }
+
+    #[test]
+    fn loops_release_notes_and_wrap() {
+        let song = MidiSong::new(vec![
+            Timed::new(0, on(Channel::One, 60)),
+            Timed::new(2_000_000, off(Channel::One, 60)),
+        ]);
+        let mut player = SongPlayer::new(song);
+        player.set_loop(LoopRegion::time(0, 1_000_000)).unwrap();
+        assert_eq!(
+            advance(&mut player, 1_200_000),
+            vec![
+                on(Channel::One, 60),
+                off(Channel::One, 60),
+                on(Channel::One, 60)
+            ]
+        );
+        assert_eq!(player.position(), 200_000);
+        assert!(player.is_playing());
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let song = MidiSong::new(vec![
+            Timed::new(0, on(Channel::One, 60)),
+            Timed::new(0, on(Channel::Two, 64)),
+            Timed::new(1_000_000, off(Channel::One, 60)),
+            Timed::new(1_000_000, off(Channel::Two, 64)),
+        ]);
+        let mut player = SongPlayer::new(song);
+        player.set_channel_muted(Channel::One, true);
+        assert_eq!(advance(&mut player, 1), vec![on(Channel::Two, 64)]);
+
+        player.set_channel_solo(Channel::One, true);
+        assert_eq!(advance(&mut player, 1), vec![off(Channel::Two, 64)]);
+    }
+
+    #[test]
+    fn soloed_tracks_silence_the_others() {
+        let song = MidiSong::with_tracks(
+            vec![
+                Timed::new(0, on(Channel::One, 60)),
+                Timed::new(0, on(Channel::One, 64)),
+                Timed::new(1_000_000, off(Channel::One, 60)),
+                Timed::new(1_000_000, off(Channel::One, 64)),
+            ],
+            vec![0, 1, 0, 1],
+        );
+        let mut player = SongPlayer::new(song);
+        player.set_track_solo(1, true);
+        assert_eq!(advance(&mut player, 1), vec![on(Channel::One, 64)]);
+    }
+}
@@ -8,6 +8,8 @@ use firewheel::{diff::EventQueue, event::NodeEventType};
 mod player_and_commands;
 pub use player_and_commands::*;
 
+pub use crate::commands::{ProcessSynthCommands, SynthCommands};
+
 use bevy::prelude::*;
 use bevy_seedling::prelude::*;
 use trotcast::Channel;
@@ -38,16 +40,6 @@ impl<D: FromMidiInputData> SynthPlugin<D> {
     }
 }
 
-/// System set for processing synthesizer commands.
-///
-/// Systems that need to process MIDI commands for the synthesizer should
-/// be added to this set to ensure proper ordering and synchronization.
-///
-/// If you need to do something before the synth commands are processed,
-/// schedule them before this set.
-#[derive(SystemSet, Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub struct ProcessSynthCommands;
-
 impl<D: FromMidiInputData> Plugin for SynthPlugin<D> {
     fn build(&self, app: &mut App) {
         // Register our custom node type with bevy_seedling
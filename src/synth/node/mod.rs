@@ -128,6 +128,6 @@ impl AudioNodeProcessor for MidiSynthProcessor {
         // Render audio from the synthesizer
         self.synthesizer
             .render(&mut left[0][..frames], &mut right[0][..frames]);
-        ProcessStatus::outputs_not_silent()
+        ProcessStatus::OutputsModified
     }
 }
@@ -1,19 +1,53 @@
 use std::sync::Arc;
 
 use bevy::prelude::*;
-use bevy_seedling::{prelude::AudioEvents, time::Audio};
+use bevy_seedling::{
+    prelude::{AudioEvents, FirewheelNode},
+    time::Audio,
+};
+use trotcast::Channel as DataChannel;
 
 use crate::{
     assets::SoundFontAsset,
-    input::{FromMidiInputData, MidiInput},
+    input::{FromMidiInputData, MidiInput, RouteId},
     synth::{SynthPlayer, node::MidiSynthNode},
 };
 
+/// Marks a synth whose node has been spawned, and the routed channel it listens to
 #[derive(Component)]
-struct NodeSpawned;
+struct NodeSpawned {
+    channel: Option<RouteId>,
+}
 
 pub fn plugin<D: FromMidiInputData>(app: &mut App) {
-    app.add_systems(Update, spawn_midi_nodes::<D>);
+    app.add_systems(
+        Update,
+        (respawn_routed_nodes::<D>, spawn_midi_nodes::<D>).chain(),
+    );
+}
+
+/// System that respawns the node of a synth whose routes changed after its node was spawned,
+/// so it listens to its routes, or to what it listened to before it was routed to.
+/// Notes sounding on the old node are cut off.
+///
+/// Removing the [`FirewheelNode`] removes the old node from the audio graph, and lets
+/// `bevy_seedling` add the new one.
+fn respawn_routed_nodes<D: FromMidiInputData>(
+    mut commands: Commands,
+    query: Query<(Entity, &NodeSpawned)>,
+    midi_io: Res<MidiInput<D>>,
+) {
+    for (entity, spawned) in &query {
+        if spawned.channel == midi_io.router().synth_channel_id(entity) {
+            continue;
+        }
+        commands.entity(entity).remove::<(
+            MidiSynthNode,
+            MidiSynthNode<DataChannel<D>>,
+            FirewheelNode,
+            NodeSpawned,
+        )>();
+    }
 }
 
 /// System that spawns MIDI synthesizer nodes for entities with soundfonts
@@ -35,7 +69,15 @@ fn spawn_midi_nodes<D: FromMidiInputData>(
         // Get config or use defaults
         let mut entity_commands = commands.entity(entity);
 
-        if synth_player.midi_input_enabled {
+        let routed = midi_io.router().synth_channel(entity);
+        let channel_id = routed.as_ref().map(|(id, _)| *id);
+        if let Some((_, channel)) = routed {
+            // a routed synth only hears its routes, not the input's main channel
+            let node =
+                MidiSynthNode::new_with_channel(Arc::clone(soundfont_asset.file()), true, channel);
+
+            entity_commands.insert(node);
+        } else if synth_player.midi_input_enabled {
             let node = MidiSynthNode::new_with_channel(
                 Arc::clone(soundfont_asset.file()),
                 true,
@@ -52,6 +94,209 @@ fn spawn_midi_nodes<D: FromMidiInputData>(
             // bevy_seedling will automatically handle node creation and connection
             entity_commands.insert(node);
         }
-        entity_commands.insert((AudioEvents::new(&time), NodeSpawned));
+        entity_commands.insert((
+            AudioEvents::new(&time),
+            NodeSpawned {
+                channel: channel_id,
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, num::NonZeroU32, sync::Arc};
+
+    use bevy::{ecs::system::RunSystemOnce, prelude::*};
+    use bevy_seedling::{configuration::GraphConfiguration, prelude::*};
+    use firewheel::{
+        StreamInfo,
+        backend::{AudioBackend, DeviceInfo},
+        processor::FirewheelProcessor,
+    };
+    use midix_synth::prelude::SoundFont;
+
+    use super::NodeSpawned;
+    use crate::{
+        assets::SoundFontAsset,
+        data::MidiData,
+        input::{MidiInput, MidiInputSettings, MidiRoute},
+        synth::{SynthPlayer, SynthPlugin},
+    };
+
+    /// A backend that never processes audio, so the graph can be inspected.
+    ///
+    /// The processor is kept so the stream isn't seen as stopped.
+    struct SilentBackend(Option<FirewheelProcessor<Self>>);
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("silent backend")]
+    struct SilentError;
+
+    impl AudioBackend for SilentBackend {
+        type Config = ();
+        type StartStreamError = SilentError;
+        type StreamError = SilentError;
+        type Instant = std::time::Instant;
+
+        fn available_output_devices() -> Vec<DeviceInfo> {
+            vec![DeviceInfo {
+                name: "silent".into(),
+                num_channels: 2,
+                is_default: true,
+            }]
+        }
+
+        fn start_stream(_: Self::Config) -> Result<(Self, StreamInfo), Self::StartStreamError> {
+            let sample_rate = NonZeroU32::new(48_000).unwrap();
+            Ok((
+                Self(None),
+                StreamInfo {
+                    prev_sample_rate: sample_rate,
+                    sample_rate,
+                    sample_rate_recip: 1. / sample_rate.get() as f64,
+                    max_block_frames: NonZeroU32::new(128).unwrap(),
+                    num_stream_in_channels: 0,
+                    num_stream_out_channels: 2,
+                    declick_frames: NonZeroU32::new(16).unwrap(),
+                    input_device_name: None,
+                    output_device_name: Some("silent".into()),
+                    input_to_output_latency_seconds: 0.,
+                },
+            ))
+        }
+
+        fn set_processor(&mut self, processor: FirewheelProcessor<Self>) {
+            self.0 = Some(processor);
+        }
+
+        fn poll_status(&mut self) -> Result<(), Self::StreamError> {
+            Ok(())
+        }
+
+        fn delay_from_last_process(&self, _: Self::Instant) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    /// The smallest soundfont that loads: one preset and one instrument, both without regions
+    fn empty_soundfont() -> SoundFont {
+        fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+            let mut bytes = id.to_vec();
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(data);
+            bytes
+        }
+        fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+            chunk(b"LIST", &[&kind[..], &chunks.concat()].concat())
+        }
+        // a named record whose zones start at `zone`, padded to `len` bytes
+        fn record(zone: u16, len: usize) -> Vec<u8> {
+            let mut bytes = vec![0; len];
+            let at = if len == 38 { 24 } else { 20 };
+            bytes[at..at + 2].copy_from_slice(&zone.to_le_bytes());
+            bytes
+        }
+
+        let info = list(b"INFO", &[]);
+        let samples = list(b"sdta", &[chunk(b"smpl", &[0; 16])]);
+        let parameters = list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &[record(0, 38), record(1, 38)].concat()),
+                chunk(b"pbag", &[0; 8]),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &[0; 4]),
+                chunk(b"inst", &[record(0, 22), record(1, 22)].concat()),
+                chunk(b"ibag", &[0; 8]),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &[0; 4]),
+                chunk(b"shdr", &[0; 46]),
+            ],
+        );
+        let bytes = chunk(
+            b"RIFF",
+            &[&b"sfbk"[..], &info, &samples, &parameters].concat(),
+        );
+        SoundFont::new(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    fn node_of(app: &mut App, entity: Entity) -> Option<FirewheelNode> {
+        app.world().get::<FirewheelNode>(entity).copied()
+    }
+
+    #[test]
+    fn routing_to_a_synth_rebuilds_its_audio_node() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            SeedlingPlugin::<SilentBackend> {
+                graph_config: GraphConfiguration::Empty,
+                ..SeedlingPlugin::<SilentBackend>::new()
+            },
+            TransformPlugin,
+            SynthPlugin::<MidiData>::new(),
+        ))
+        .init_asset::<SoundFontAsset>()
+        .insert_resource(MidiInput::<MidiData>::without_client(
+            MidiInputSettings::default(),
+        ));
+        app.finish();
+        app.cleanup();
+
+        let soundfont = app
+            .world_mut()
+            .resource_mut::<Assets<SoundFontAsset>>()
+            .add(SoundFontAsset {
+                file: Arc::new(empty_soundfont()),
+            });
+        let synth = app
+            .world_mut()
+            .spawn(SynthPlayer::new(soundfont, false))
+            .id();
+        app.update();
+
+        let first = node_of(&mut app, synth).expect("the node was not added to the graph");
+        assert!(
+            app.world()
+                .get::<NodeSpawned>(synth)
+                .unwrap()
+                .channel
+                .is_none()
+        );
+
+        let route = app
+            .world()
+            .resource::<MidiInput<MidiData>>()
+            .router()
+            .add_route(MidiRoute::to_synth(synth));
+        app.update();
+        app.update();
+
+        let second = node_of(&mut app, synth).expect("the node was not added back to the graph");
+        assert_ne!(first.0, second.0);
+        assert_eq!(
+            app.world().get::<NodeSpawned>(synth).unwrap().channel,
+            Some(route)
+        );
+
+        let nodes = app
+            .world_mut()
+            .run_system_once(|mut context: ResMut<AudioContext>| {
+                context.with(|context| {
+                    context
+                        .nodes()
+                        .iter()
+                        .map(|node| node.id)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .unwrap();
+        assert!(nodes.contains(&second.0));
+        assert!(
+            !nodes.contains(&first.0),
+            "the old node is still in the graph"
+        );
     }
 }
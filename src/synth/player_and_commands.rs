@@ -1,5 +1,4 @@
 use bevy::prelude::*;
-use midix::prelude::*;
 
 use crate::assets::SoundFontAsset;
 
@@ -28,29 +27,3 @@ impl SynthPlayer {
         &self.handle
     }
 }
-
-/// Component for sending MIDI commands to a synthesizer node via ECS.
-///
-/// This is automatically added to any [`MidiSynthNode`](crate::prelude::MidiSynthNode) or [`SynthPlayer`].
-#[derive(Component, Default)]
-pub struct SynthCommands {
-    /// Queue of MIDI commands to send
-    pub queue: Vec<ChannelVoiceMessage>,
-}
-
-impl SynthCommands {
-    /// Add a MIDI command to the queue
-    pub fn send(&mut self, command: ChannelVoiceMessage) {
-        self.queue.push(command);
-    }
-
-    /// Add multiple MIDI commands to the queue
-    pub fn send_batch(&mut self, commands: impl IntoIterator<Item = ChannelVoiceMessage>) {
-        self.queue.extend(commands);
-    }
-
-    /// Take all commands, leaving the queue empty
-    pub fn take(&mut self) -> Vec<ChannelVoiceMessage> {
-        std::mem::take(&mut self.queue)
-    }
-}
@@ -0,0 +1,197 @@
+//! Crate-internal helpers for working with raw MIDI bytes and channels.
+use midix::{
+    events::{FromLiveEventBytes, LiveEvent},
+    prelude::*,
+};
+
+/// Every channel, in order.
+pub(crate) const CHANNELS: [Channel; 16] = [
+    Channel::One,
+    Channel::Two,
+    Channel::Three,
+    Channel::Four,
+    Channel::Five,
+    Channel::Six,
+    Channel::Seven,
+    Channel::Eight,
+    Channel::Nine,
+    Channel::Ten,
+    Channel::Eleven,
+    Channel::Twelve,
+    Channel::Thirteen,
+    Channel::Fourteen,
+    Channel::Fifteen,
+    Channel::Sixteen,
+];
+
+/// The zero-based index of a channel
+pub(crate) fn channel_index(channel: Channel) -> usize {
+    CHANNELS.iter().position(|c| *c == channel).unwrap()
+}
+
+/// Returns the status byte and both data bytes of a message.
+///
+/// The second data byte is zero for program changes and channel pressure.
+pub(crate) fn voice_bytes(message: &ChannelVoiceMessage) -> [u8; 3] {
+    let status = message.status();
+    let data_2 = match message_len(status) {
+        3 => message.data_2_byte().unwrap_or_default(),
+        _ => 0,
+    };
+    [status, message.data_1_byte(), data_2]
+}
+
+/// Calls `f` with the raw bytes of a message, as they would be sent over the wire.
+pub(crate) fn with_voice_bytes<R>(message: &ChannelVoiceMessage, f: impl FnOnce(&[u8]) -> R) -> R {
+    let bytes = voice_bytes(message);
+    f(&bytes[..message_len(bytes[0])])
+}
+
+/// The length in bytes of a channel voice message with the given status byte
+const fn message_len(status: u8) -> usize {
+    match status & 0xF0 {
+        STATUS_PROGRAM_CHANGE | STATUS_CHANNEL_PRESSURE => 2,
+        _ => 3,
+    }
+}
+
+pub(crate) const STATUS_NOTE_OFF: u8 = 0x80;
+pub(crate) const STATUS_NOTE_ON: u8 = 0x90;
+#[cfg(any(test, feature = "assets"))]
+pub(crate) const STATUS_POLY_PRESSURE: u8 = 0xA0;
+pub(crate) const STATUS_CONTROL_CHANGE: u8 = 0xB0;
+pub(crate) const STATUS_PROGRAM_CHANGE: u8 = 0xC0;
+pub(crate) const STATUS_CHANNEL_PRESSURE: u8 = 0xD0;
+#[cfg(any(test, feature = "assets"))]
+pub(crate) const STATUS_PITCH_BEND: u8 = 0xE0;
+
+/// Creates a message from a status byte (the channel nibble is ignored) and its data bytes.
+///
+/// Returns `None` if the bytes are not a valid channel voice message.
+pub(crate) fn voice_message(
+    status: u8,
+    channel: Channel,
+    data_1: u8,
+    data_2: u8,
+) -> Option<ChannelVoiceMessage> {
+    let status = (status & 0xF0) | channel_index(channel) as u8;
+    let bytes = [status, data_1 & 0x7F, data_2 & 0x7F];
+    LiveEvent::from_bytes(&bytes[..message_len(status)])
+        .ok()?
+        .channel_voice()
+        .copied()
+}
+
+/// Moves the key of a note on, note off or key pressure message by a number of semitones.
+///
+/// Other messages, and every message on channel 10 (the drum channel), are returned unchanged.
+/// Returns `None` if the key would leave the range of MIDI keys: such notes are dropped, as
+/// clamping them would play them at the wrong pitch, on top of the notes at the edge.
+#[cfg(feature = "assets")]
+pub(crate) fn transpose(
+    message: ChannelVoiceMessage,
+    semitones: i8,
+) -> Option<ChannelVoiceMessage> {
+    let channel = message.channel();
+    let [status, key, data_2] = voice_bytes(&message);
+    if semitones == 0
+        || channel == Channel::Ten
+        || !matches!(
+            status & 0xF0,
+            STATUS_NOTE_ON | STATUS_NOTE_OFF | STATUS_POLY_PRESSURE
+        )
+    {
+        return Some(message);
+    }
+    let key = u8::try_from(key as i16 + semitones as i16)
+        .ok()
+        .filter(|key| *key < 128)?;
+    voice_message(status, channel, key, data_2)
+}
+
+/// Returns the key of a note on with a non-zero velocity
+pub(crate) fn note_on_key(message: &ChannelVoiceMessage) -> Option<u8> {
+    let [status, key, velocity] = voice_bytes(message);
+    (status & 0xF0 == STATUS_NOTE_ON && velocity > 0).then_some(key)
+}
+
+/// Returns the key of a note off, or a note on with a velocity of zero
+pub(crate) fn note_off_key(message: &ChannelVoiceMessage) -> Option<u8> {
+    let [status, key, velocity] = voice_bytes(message);
+    match status & 0xF0 {
+        STATUS_NOTE_OFF => Some(key),
+        STATUS_NOTE_ON if velocity == 0 => Some(key),
+        _ => None,
+    }
+}
+
+/// Tracks which notes are sounding on each channel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ActiveNotes([u128; 16]);
+
+impl ActiveNotes {
+    /// Update the sounding notes with a message that was just sent
+    pub(crate) fn track(&mut self, message: &ChannelVoiceMessage) {
+        let channel = channel_index(message.channel());
+        if let Some(key) = note_on_key(message) {
+            self.0[channel] |= 1u128 << key;
+        } else if let Some(key) = note_off_key(message) {
+            self.0[channel] &= !(1u128 << key);
+        }
+    }
+
+    /// True if no notes are sounding
+    #[cfg(feature = "assets")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.iter().all(|keys| *keys == 0)
+    }
+
+    /// Returns note offs for every sounding note on a channel, and forgets them
+    pub(crate) fn release_channel(&mut self, channel: Channel) -> Vec<ChannelVoiceMessage> {
+        let keys = std::mem::take(&mut self.0[channel_index(channel)]);
+        (0..128u8)
+            .filter(|key| keys & (1u128 << key) != 0)
+            .filter_map(|key| voice_message(STATUS_NOTE_OFF, channel, key, 0))
+            .collect()
+    }
+
+    /// Returns note offs for every sounding note, and forgets them
+    pub(crate) fn release_all(&mut self) -> Vec<ChannelVoiceMessage> {
+        CHANNELS
+            .into_iter()
+            .flat_map(|channel| self.release_channel(channel))
+            .collect()
+    }
+}
+
+/// Parses a note name such as `C4`, `F#3` or `Bb-1` into a key, where `C4` is 60.
+///
+/// Returns `None` if the name is invalid or outside the range of MIDI keys.
+#[cfg(any(feature = "serde", feature = "musicxml"))]
+pub(crate) fn parse_note_name(name: &str) -> Option<u8> {
+    let name = name.trim();
+    let mut chars = name.chars();
+    let pitch_class: i16 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let accidentals = rest
+        .chars()
+        .take_while(|c| matches!(c, '#' | 'b'))
+        .collect::<Vec<_>>();
+    let shift = accidentals
+        .iter()
+        .map(|c| if *c == '#' { 1 } else { -1 })
+        .sum::<i16>();
+    let octave = rest[accidentals.len()..].parse::<i16>().ok()?;
+    u8::try_from((octave + 1) * 12 + pitch_class + shift)
+        .ok()
+        .filter(|key| *key < 128)
+}